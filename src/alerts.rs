@@ -0,0 +1,176 @@
+//! Alerts raised by the dispatcher, e.g., upon a platform entering or exiting a geofence,
+//! going stale, or getting close to another platform.
+//! Alerts are recorded in an append-only file under the state directory (available
+//! via `/api/alerts`), and also posted to any configured webhooks from a background
+//! thread, so a slow or unreachable webhook does not hold up the dispatch.
+
+use crate::config;
+use crate::jsonl;
+use crate::server::metrics::ALERTS;
+use crate::trackdb_client::Position;
+
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, OnceLock};
+use std::time::{Duration, Instant};
+use utoipa::ToSchema;
+
+/// Append-only alerts file under the state directory.
+const ALERTS_FILENAME: &str = "alerts.jsonl";
+
+/// Timeout for posting an alert to a webhook.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Maximum number of alert posts waiting for the webhook thread; any further ones
+/// are dropped (but still recorded in the alerts file).
+const WEBHOOK_QUEUE_SIZE: usize = 1000;
+
+/// Sinks for the alerts, besides the alerts file.
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct AlertsConfig {
+    /// URLs to POST each alert to, as JSON.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub webhooks: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum AlertKind {
+    GeofenceEnter,
+    GeofenceExit,
+    /// No new fix within the staleness threshold.
+    Stale,
+    /// New fix after having been stale.
+    Recovered,
+    /// Separation or projected closest point of approach to another platform below
+    /// the proximity threshold.
+    Proximity,
+    /// Separation and projected closest point of approach back above the threshold.
+    ProximityCleared,
+}
+
+impl AlertKind {
+    fn label(&self) -> &'static str {
+        match self {
+            AlertKind::GeofenceEnter => "geofence_enter",
+            AlertKind::GeofenceExit => "geofence_exit",
+            AlertKind::Stale => "stale",
+            AlertKind::Recovered => "recovered",
+            AlertKind::Proximity => "proximity",
+            AlertKind::ProximityCleared => "proximity_cleared",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Alert {
+    /// Time of the fix that triggered the alert.
+    pub time_ms: u64,
+    /// When the alert was raised.
+    pub raised_ms: u64,
+    pub kind: AlertKind,
+    pub platform_id: String,
+    pub platform_name: String,
+    /// The geofence, for geofence alerts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub geofence: Option<String>,
+    /// The other platform, for proximity alerts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub other_platform_id: Option<String>,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<Position>,
+}
+
+fn alerts_path() -> PathBuf {
+    config::get_config().state_path(ALERTS_FILENAME)
+}
+
+/// Records the given alerts and posts them to the configured webhooks.
+/// Failures are logged, but otherwise ignored.
+pub fn emit(alerts: &[Alert]) {
+    for alert in alerts {
+        println!("ALERT: {}", alert.message);
+        metrics::counter!(ALERTS, "kind" => alert.kind.label()).increment(1);
+    }
+    if let Err(e) = jsonl::append(&alerts_path(), alerts) {
+        log::error!("Failed to record alerts: {e}");
+        eprintln!("error: failed to record alerts: {e}");
+    }
+    let config = config::get_config();
+    for webhook in &config.alerts.webhooks {
+        for alert in alerts {
+            queue_post(webhook, alert);
+        }
+    }
+}
+
+/// Number of queued alert posts not yet completed.
+static PENDING_POSTS: AtomicUsize = AtomicUsize::new(0);
+
+fn queue_post(webhook: &str, alert: &Alert) {
+    static SENDER: OnceLock<mpsc::SyncSender<(String, Alert)>> = OnceLock::new();
+    let sender = SENDER.get_or_init(|| {
+        let (sender, receiver) = mpsc::sync_channel::<(String, Alert)>(WEBHOOK_QUEUE_SIZE);
+        std::thread::spawn(move || {
+            let agent: ureq::Agent = ureq::Agent::config_builder()
+                .timeout_global(Some(WEBHOOK_TIMEOUT))
+                .build()
+                .into();
+            for (webhook, alert) in receiver {
+                if let Err(e) = post_alert(&agent, &webhook, &alert) {
+                    log::error!("Failed to post alert: {e}");
+                }
+                PENDING_POSTS.fetch_sub(1, Ordering::SeqCst);
+            }
+        });
+        sender
+    });
+    PENDING_POSTS.fetch_add(1, Ordering::SeqCst);
+    if let Err(e) = sender.try_send((webhook.to_string(), alert.clone())) {
+        PENDING_POSTS.fetch_sub(1, Ordering::SeqCst);
+        log::error!("Failed to queue alert for {webhook}: {e}");
+    }
+}
+
+/// Waits up to the given time for the queued alert posts to complete, e.g., before
+/// the program exits after a single dispatch.
+pub fn wait_for_posts(max_wait: Duration) {
+    let start = Instant::now();
+    while PENDING_POSTS.load(Ordering::SeqCst) > 0 && start.elapsed() < max_wait {
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+fn post_alert(agent: &ureq::Agent, webhook: &str, alert: &Alert) -> Result<(), String> {
+    agent
+        .post(webhook)
+        .send_json(alert)
+        .map(|_| ())
+        .map_err(|e| format!("POST {webhook}: error: {e}"))
+}
+
+/// Gets the recorded alerts, optionally only those for the given platform and
+/// since the given time, limited to the last `last_number` ones, if given.
+pub fn get_alerts(
+    platform_id: Option<&str>,
+    since_ms: Option<u64>,
+    last_number: Option<usize>,
+) -> io::Result<Vec<Alert>> {
+    let mut alerts = jsonl::read::<Alert>(&alerts_path())?
+        .into_iter()
+        .filter(|a| {
+            platform_id
+                .is_none_or(|id| a.platform_id == id || a.other_platform_id.as_deref() == Some(id))
+        })
+        .filter(|a| since_ms.is_none_or(|since| a.raised_ms >= since))
+        .collect::<Vec<_>>();
+    if let Some(last_number) = last_number {
+        alerts.drain(..alerts.len().saturating_sub(last_number));
+    }
+    Ok(alerts)
+}
@@ -0,0 +1,172 @@
+use crate::config;
+use crate::jsonl;
+use crate::time_util;
+
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::PathBuf;
+use utoipa::ToSchema;
+
+/// Append-only audit log file under the state directory.
+const AUDIT_FILENAME: &str = "audit.jsonl";
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum AuditAction {
+    Add,
+    Remove,
+}
+
+/// What originated a change.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum AuditSource {
+    /// A REST API request.
+    Api,
+    /// A command-line invocation.
+    Cli,
+    /// Automatic removal of a platform missing from the Tracking DB beyond the grace period.
+    AutoPrune,
+    /// Automatic removal of a platform whose dispatch has expired.
+    Expired,
+}
+
+/// Who and what originated a change to the dispatched platforms.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AuditContext {
+    pub source: AuditSource,
+    /// Client address, for API requests.
+    pub client: Option<String>,
+    /// Authenticated identity, if known.
+    pub identity: Option<String>,
+}
+
+impl AuditContext {
+    /// Context for a command-line invocation, with the identity of the OS user.
+    pub fn cli() -> Self {
+        AuditContext {
+            source: AuditSource::Cli,
+            client: None,
+            identity: std::env::var("USER")
+                .or_else(|_| std::env::var("USERNAME"))
+                .ok(),
+        }
+    }
+
+    /// Context for an automatic removal by the dispatcher.
+    pub fn auto_prune() -> Self {
+        AuditContext {
+            source: AuditSource::AutoPrune,
+            client: None,
+            identity: None,
+        }
+    }
+
+    /// Context for the removal of an expired dispatch.
+    pub fn expired() -> Self {
+        AuditContext {
+            source: AuditSource::Expired,
+            client: None,
+            identity: None,
+        }
+    }
+}
+
+/// A recorded change to the dispatched platforms.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    pub time_ms: u64,
+    pub action: AuditAction,
+    pub platform_id: String,
+    pub source: AuditSource,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity: Option<String>,
+}
+
+fn audit_path() -> PathBuf {
+    config::get_config().state_path(AUDIT_FILENAME)
+}
+
+/// Records the given action on the platforms. Failures are logged, but otherwise
+/// do not affect the change itself.
+pub fn record(action: AuditAction, platform_ids: &[String], context: &AuditContext) {
+    let time_ms = time_util::now_ms();
+    let entries = platform_ids
+        .iter()
+        .map(|platform_id| AuditEntry {
+            time_ms,
+            action,
+            platform_id: platform_id.clone(),
+            source: context.source,
+            client: context.client.clone(),
+            identity: context.identity.clone(),
+        })
+        .collect::<Vec<_>>();
+    for entry in &entries {
+        log::info!("audit: {}", serde_json::to_string(entry).unwrap());
+    }
+    if let Err(e) = jsonl::append(&audit_path(), &entries) {
+        log::error!("Failed to record audit entries: {e}");
+        eprintln!("error: failed to record audit entries: {e}");
+    }
+}
+
+/// Gets the recorded entries, optionally only those for the given platform and
+/// since the given time, limited to the last `last_number` ones, if given.
+pub fn get_entries(
+    platform_id: Option<&str>,
+    since_ms: Option<u64>,
+    last_number: Option<usize>,
+) -> io::Result<Vec<AuditEntry>> {
+    let mut entries = jsonl::read::<AuditEntry>(&audit_path())?
+        .into_iter()
+        .filter(|e| platform_id.is_none_or(|id| e.platform_id == id))
+        .filter(|e| since_ms.is_none_or(|since| e.time_ms >= since))
+        .collect::<Vec<_>>();
+    if let Some(last_number) = last_number {
+        entries.drain(..entries.len().saturating_sub(last_number));
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_audit() {
+        config::init_test_config();
+        let platform_ids = ["audit-unit-1".to_string(), "audit-unit-2".to_string()];
+        let since_ms = time_util::now_ms();
+        record(AuditAction::Add, &platform_ids, &AuditContext::cli());
+        record(
+            AuditAction::Remove,
+            &platform_ids[..1],
+            &AuditContext::auto_prune(),
+        );
+
+        let entries = get_entries(Some("audit-unit-1"), Some(since_ms), None).unwrap();
+        let actions = entries
+            .iter()
+            .map(|e| (e.action, e.source))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            actions,
+            vec![
+                (AuditAction::Add, AuditSource::Cli),
+                (AuditAction::Remove, AuditSource::AutoPrune)
+            ]
+        );
+        assert_eq!(entries[1].identity, None);
+
+        let entries = get_entries(Some("audit-unit-2"), None, Some(5)).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert!(get_entries(Some("audit-unit-2"), Some(u64::MAX), None)
+            .unwrap()
+            .is_empty());
+    }
+}
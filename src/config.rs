@@ -1,44 +1,436 @@
+use crate::alerts::AlertsConfig;
+use crate::decimation::DecimationConfig;
+use crate::fix_filter::FixFilterConfig;
+use crate::geofence::{self, Geofence};
+use crate::proximity::ProximityRule;
+use crate::routing::RoutingRules;
+use crate::secret::Secret;
+use crate::simulation::SimulationConfig;
+use crate::staleness::StalenessConfig;
+
 use dotenv::dotenv;
 use serde::{Deserialize, Serialize};
-use std::error::Error;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
-use std::sync::OnceLock;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
 use std::time::Duration;
 
 const CONFIG_FILENAME: &str = "odss2dash.toml";
 
-static CONFIG: OnceLock<Config> = OnceLock::new();
+/// Prefix of environment variables overriding configuration settings.
+const ENV_PREFIX: &str = "ODSS2DASH_";
+
+/// Environment variable indicating the configuration file.
+pub const CONFIG_ENV_VAR: &str = "ODSS2DASH_CONFIG";
+
+/// Name of the default source, given by `odssApi`, whose platform IDs are not namespaced.
+pub const DEFAULT_SOURCE: &str = "default";
+
+static CONFIG: RwLock<Option<Arc<Config>>> = RwLock::new(None);
+
+/// Configuration file explicitly indicated, if any.
+static CONFIG_FILE: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+/// Incremented every time the configuration is (re)loaded.
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Settings that are only used at startup, so cannot be changed via reload.
+const NOT_RELOADABLE: [&str; 4] = ["port", "externalUrl", "stateDir", "stateStore"];
+
+/// Get the configuration from the given file, or from the default file, if existing.
+/// In either case, any `ODSS2DASH_*` environment variables are applied as overrides.
+/// Exits the program if the configuration cannot be loaded or is invalid.
+/// Any problems found by validation are fatal if `strict`, otherwise only warned about.
+pub fn load_config(config_file: Option<PathBuf>, strict: bool) -> Arc<Config> {
+    let config = init_config(config_file).unwrap_or_else(|e| {
+        eprintln!("error: {e}");
+        std::process::exit(1);
+    });
+    let problems = config.validate();
+    if !problems.is_empty() {
+        let level = if strict { "error" } else { "warning" };
+        eprintln!("{level}: invalid configuration:");
+        for problem in problems {
+            eprintln!("  - {problem}");
+        }
+        if strict {
+            std::process::exit(1);
+        }
+    }
+    config
+}
 
-/// Get the configuration from the default file.
-pub fn load_config() -> &'static Config {
-    CONFIG.get_or_init(|| parse_config_file(CONFIG_FILENAME))
+/// Like [load_config], but without validation of the loaded configuration.
+pub fn init_config(config_file: Option<PathBuf>) -> Result<Arc<Config>, String> {
+    if CONFIG.read().unwrap().is_none() {
+        *CONFIG_FILE.write().unwrap() = config_file;
+        set(try_load_config()?);
+    }
+    Ok(get_config())
 }
 
-// allows directly setting the configuration for testing
+/// Sets the configuration shared by all tests, with the mock ODSS and TethysDash,
+/// and a temporary state directory.
+/// The state is kept in SQLite, so concurrent tests do not lose each other's updates.
 #[cfg(test)]
-pub fn set_config(config: Config) {
-    CONFIG
-        .set(config)
-        .unwrap_or_else(|_| panic!("Configuration already set!"));
+pub fn init_test_config() -> Arc<Config> {
+    static INIT: std::sync::Once = std::sync::Once::new();
+    INIT.call_once(|| {
+        let state_dir = create_test_state_dir();
+        set(Config {
+            odss_api: crate::mock::odss::test_odss_api(),
+            external_url: "http://localhost:3033".to_string(),
+            port: 3033,
+            poll_period: Duration::from_secs(30),
+            default_last_number_of_fixes: 2,
+            tethysdashes: vec![TethysDashConfig {
+                name: "mock".to_string(),
+                api: crate::mock::tethysdash::test_tethysdash().0,
+                api_key: Secret::new(crate::mock::tethysdash::TEST_API_KEY),
+                api_key_file: None,
+                routing: None,
+                kinematics: false,
+            }],
+            state_dir,
+            state_store: StateStoreKind::Sqlite,
+            trusted_proxies: vec![IpAddr::from([192, 0, 2, 1])],
+            history: HistoryConfig {
+                enabled: true,
+                retention: None,
+            },
+            ..Config::default()
+        });
+    });
+    get_config()
+}
+
+/// Creates the state directory for this test run, first removing those of previous
+/// runs. Static values are never dropped, so a run cannot remove its own directory;
+/// instead, each run holds a lock on a file in its directory until it exits.
+#[cfg(test)]
+fn create_test_state_dir() -> PathBuf {
+    static LOCK: std::sync::OnceLock<fs::File> = std::sync::OnceLock::new();
+    const LOCK_FILENAME: &str = ".lock";
+
+    let base_dir = std::env::temp_dir().join("odss2dash-test");
+    if let Ok(entries) = fs::read_dir(&base_dir) {
+        for entry in entries.flatten() {
+            // not locked, so not of a running test process:
+            let unlocked = match fs::File::open(entry.path().join(LOCK_FILENAME)) {
+                Ok(f) => f.try_lock().is_ok(),
+                Err(e) => e.kind() == std::io::ErrorKind::NotFound,
+            };
+            if unlocked {
+                let _ = fs::remove_dir_all(entry.path());
+            }
+        }
+    }
+    let state_dir = base_dir.join(std::process::id().to_string());
+    let _ = fs::remove_dir_all(&state_dir);
+    fs::create_dir_all(&state_dir).unwrap();
+    let lock = fs::File::create(state_dir.join(LOCK_FILENAME)).unwrap();
+    lock.lock().unwrap();
+    LOCK.set(lock).unwrap();
+    state_dir
 }
 
-pub fn get_config() -> &'static Config {
+pub fn get_config() -> Arc<Config> {
     CONFIG
-        .get()
+        .read()
+        .unwrap()
+        .clone()
         .unwrap_or_else(|| panic!("Configuration not set. Call load_config() first."))
 }
 
+/// Allows users of the configuration to detect that it has been reloaded.
+pub fn get_generation() -> u64 {
+    GENERATION.load(Ordering::SeqCst)
+}
+
+fn set(config: Config) {
+    *CONFIG.write().unwrap() = Some(Arc::new(config));
+    GENERATION.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Reasons for not applying a configuration reload.
+#[derive(Debug)]
+pub enum ReloadError {
+    /// The new configuration could not be loaded or is invalid.
+    Invalid(String),
+    /// The new configuration has changes that require a restart.
+    NotReloadable(Vec<String>),
+}
+
+impl std::fmt::Display for ReloadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReloadError::Invalid(msg) => write!(f, "invalid configuration: {msg}"),
+            ReloadError::NotReloadable(settings) => write!(
+                f,
+                "changes to {} require a restart; configuration not reloaded",
+                settings.join(", ")
+            ),
+        }
+    }
+}
+
+/// Reloads the configuration from the default file, returning a description
+/// of the applied changes. The current configuration is kept if the new one
+/// is invalid or has changes that cannot be applied without a restart.
+pub fn reload_config() -> Result<Vec<String>, ReloadError> {
+    let new_config = try_load_config().map_err(ReloadError::Invalid)?;
+    let current = get_config();
+    let changes = check_reload(&current, &new_config)?;
+    if *current != new_config {
+        set(new_config);
+    }
+    Ok(changes)
+}
+
+/// Determines the changes between the current and new configurations,
+/// failing if the new one is not valid or cannot be applied live.
+fn check_reload(current: &Config, new_config: &Config) -> Result<Vec<String>, ReloadError> {
+    let problems = new_config.validate();
+    if !problems.is_empty() {
+        return Err(ReloadError::Invalid(problems.join("; ")));
+    }
+
+    let current_json = serde_json::to_value(current).unwrap();
+    let new_json = serde_json::to_value(new_config).unwrap();
+    // (secrets are redacted in the JSON, so compared by value)
+    let changed = |key: &str| match key {
+        "adminApiKey" => current.admin_api_key != new_config.admin_api_key,
+        _ => current_json.get(key) != new_json.get(key),
+    };
+
+    let not_reloadable = NOT_RELOADABLE
+        .iter()
+        .filter(|key| changed(key))
+        .map(|key| key.to_string())
+        .collect::<Vec<_>>();
+    if !not_reloadable.is_empty() {
+        return Err(ReloadError::NotReloadable(not_reloadable));
+    }
+
+    // (settings omitted when not set are only in one of the two)
+    let keys = [&new_json, &current_json]
+        .into_iter()
+        .filter_map(|json| json.as_object())
+        .flat_map(|map| map.keys())
+        .collect::<BTreeSet<_>>();
+    let mut changes = Vec::new();
+    for key in keys {
+        if key == "tethysdashes" {
+            changes.extend(tethysdashes_changes(
+                &current.tethysdashes,
+                &new_config.tethysdashes,
+            ));
+        } else if changed(key) {
+            changes.push(format!("{key}: updated"));
+        }
+    }
+    Ok(changes)
+}
+
+fn tethysdashes_changes(current: &[TethysDashConfig], new: &[TethysDashConfig]) -> Vec<String> {
+    let find =
+        |tds: &[TethysDashConfig], name: &str| tds.iter().find(|td| td.name == name).cloned();
+    let mut changes = Vec::new();
+    for td in new {
+        match find(current, &td.name) {
+            None => changes.push(format!("tethysdashes: added '{}'", td.name)),
+            Some(current_td) if current_td != *td => {
+                changes.push(format!("tethysdashes: updated '{}'", td.name))
+            }
+            _ => {}
+        }
+    }
+    for td in current {
+        if find(new, &td.name).is_none() {
+            changes.push(format!("tethysdashes: removed '{}'", td.name));
+        }
+    }
+    changes
+}
+
 /// Configuration for the odss2dash service. See `odds2dash.toml`.
 #[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct Config {
+    #[serde(default)]
     pub odss_api: String,
+    /// Directory with recorded platforms and tracks to use instead of `odssApi`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_dir: Option<PathBuf>,
+    /// Simulated platforms to use instead of `odssApi`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub simulation: Option<SimulationConfig>,
+    /// Additional Tracking DB/ODSS sources, whose platform IDs are namespaced
+    /// as `<name>:<id>`. Platform IDs from `odssApi` are not namespaced.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<SourceConfig>,
     pub external_url: String,
     pub port: u16,
     #[serde(deserialize_with = "humantime_serde::deserialize")]
     pub poll_period: Duration,
     pub default_last_number_of_fixes: u32,
     pub tethysdashes: Vec<TethysDashConfig>,
+    /// API key required by the admin endpoints, as `Authorization: Bearer <key>`,
+    /// given directly or via `$EV` syntax. If not given, the admin endpoints only
+    /// accept requests from localhost.
+    #[serde(
+        default,
+        deserialize_with = "api_key_deserialize",
+        skip_serializing_if = "Secret::is_empty"
+    )]
+    pub admin_api_key: Secret,
+    /// Addresses of the reverse proxies whose `X-Forwarded-For` and identity headers
+    /// are trusted for the audit log. From any other peer, these headers are ignored.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trusted_proxies: Vec<IpAddr>,
+    /// Directory for state files like `dispatched.json` and `reported.json`.
+    #[serde(default = "default_state_dir")]
+    pub state_dir: PathBuf,
+    /// Storage for the state under `stateDir`.
+    #[serde(default)]
+    pub state_store: StateStoreKind,
+    /// Poll periods by platform type (`typeName`), overriding `pollPeriod`.
+    #[serde(default)]
+    pub poll_period_by_type: BTreeMap<String, humantime_serde::Serde<Duration>>,
+    /// Poll periods by platform ID, overriding any by-type setting.
+    #[serde(default)]
+    pub poll_period_by_platform: BTreeMap<String, humantime_serde::Serde<Duration>>,
+    /// If given, poll period adapted to the observed fix cadence of each platform.
+    #[serde(default)]
+    pub adaptive_polling: Option<AdaptivePollingConfig>,
+    /// Local archive of the positions seen by the dispatcher.
+    #[serde(default)]
+    pub history: HistoryConfig,
+    /// Undispatching of platforms no longer in the Tracking DB.
+    #[serde(default)]
+    pub pruning: PruningConfig,
+    /// Rejection of bad fixes before dispatch.
+    #[serde(default)]
+    pub fix_filter: FixFilterConfig,
+    /// Decimation of the dispatched fixes of high-rate platforms.
+    #[serde(default)]
+    pub decimation: DecimationConfig,
+    /// Geofences evaluated on each new fix of the dispatched platforms.
+    #[serde(default)]
+    pub geofences: Vec<Geofence>,
+    /// GeoJSON file with additional geofences.
+    #[serde(default)]
+    pub geofences_file: Option<PathBuf>,
+    /// Sinks for the alerts raised by the dispatcher.
+    #[serde(default)]
+    pub alerts: AlertsConfig,
+    /// Detection of dispatched platforms that stopped reporting.
+    #[serde(default)]
+    pub staleness: StalenessConfig,
+    /// Proximity analysis between dispatched platforms.
+    #[serde(default)]
+    pub proximity: Vec<ProximityRule>,
+}
+
+fn default_state_dir() -> PathBuf {
+    PathBuf::from(".")
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceConfig {
+    pub name: String,
+    #[serde(default)]
+    pub odss_api: String,
+    /// Directory with recorded platforms and tracks to use instead of `odssApi`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_dir: Option<PathBuf>,
+    /// Simulated platforms to use instead of `odssApi`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub simulation: Option<SimulationConfig>,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum StateStoreKind {
+    /// `dispatched.json` and `reported.json` files.
+    #[default]
+    Json,
+    /// `odss2dash.db` SQLite database.
+    Sqlite,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct AdaptivePollingConfig {
+    /// Upper limit for the adapted poll period.
+    #[serde(with = "humantime_serde")]
+    pub max_poll_period: Duration,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryConfig {
+    /// Default: disabled, as the archive grows without bound unless `retention` is given.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Positions older than this are removed from the archive. Default: kept indefinitely.
+    #[serde(
+        default,
+        with = "humantime_serde",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub retention: Option<Duration>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PruningConfig {
+    /// If false, orphaned platforms are only reported, never undispatched.
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// How long a platform must be missing from the Tracking DB before being undispatched.
+    #[serde(default = "default_grace_period", with = "humantime_serde")]
+    pub grace_period: Duration,
+    /// If true, orphaned platforms are reported, but not undispatched.
+    #[serde(default)]
+    pub dry_run: bool,
+    /// How often the dispatcher refreshes the full list of platforms from the Tracking DB.
+    #[serde(default = "default_refresh_period", with = "humantime_serde")]
+    pub refresh_period: Duration,
+}
+
+impl Default for PruningConfig {
+    fn default() -> Self {
+        PruningConfig {
+            enabled: true,
+            grace_period: default_grace_period(),
+            dry_run: false,
+            refresh_period: default_refresh_period(),
+        }
+    }
+}
+
+fn default_grace_period() -> Duration {
+    Duration::from_secs(24 * 60 * 60)
+}
+
+fn default_refresh_period() -> Duration {
+    Duration::from_secs(10 * 60)
+}
+
+impl PruningConfig {
+    /// Whether orphaned platforms are actually undispatched.
+    pub fn is_active(&self) -> bool {
+        self.enabled && !self.dry_run
+    }
 }
 
 #[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
@@ -46,12 +438,23 @@ pub struct Config {
 pub struct TethysDashConfig {
     pub name: String,
     pub api: String,
-    #[serde(deserialize_with = "api_key_deserialize")]
-    pub api_key: String,
+    /// Either given directly, or via `$EV` syntax, or read from `apiKeyFile`.
+    #[serde(default, deserialize_with = "api_key_deserialize")]
+    pub api_key: Secret,
+    /// File with the API key, e.g., a Docker or Kubernetes secret.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key_file: Option<PathBuf>,
+    /// Optional rules for the platforms to be sent to this instance.
+    /// By default, it gets all dispatched platforms.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub routing: Option<RoutingRules>,
+    /// Whether to include the derived kinematics (speed, course, distance) in the XEvents.
+    #[serde(default)]
+    pub kinematics: bool,
 }
 
 /// To process environment variables in the configuration file.
-fn api_key_deserialize<'de, D>(deserializer: D) -> Result<String, D::Error>
+fn api_key_deserialize<'de, D>(deserializer: D) -> Result<Secret, D::Error>
 where
     D: serde::Deserializer<'de>,
 {
@@ -61,31 +464,232 @@ where
     }
     if let Some(env_var) = api_key.strip_prefix('$') {
         match std::env::var(env_var) {
-            Ok(val) => Ok(val),
-            Err(_) => {
-                log::warn!("'{}' undefined as environment variable.", env_var);
-                Ok(api_key) // use as given
-            }
+            Ok(val) => Ok(Secret::new(val)),
+            // kept as given, to be reported as unresolved by `Config::validate`:
+            Err(_) => Ok(Secret::new(api_key)),
         }
     } else {
-        Ok(api_key)
+        Ok(Secret::new(api_key))
+    }
+}
+
+impl TethysDashConfig {
+    /// Reads the API key from `apiKeyFile`, if given.
+    fn read_api_key_file(&mut self) -> Result<(), String> {
+        if let Some(api_key_file) = &self.api_key_file {
+            if !self.api_key.is_empty() {
+                return Err(format!(
+                    "tethysdashes['{}']: only one of apiKey or apiKeyFile can be given",
+                    self.name
+                ));
+            }
+            let api_key = fs::read_to_string(api_key_file).map_err(|e| {
+                format!(
+                    "tethysdashes['{}'].apiKeyFile: failed to read '{}': {e}",
+                    self.name,
+                    api_key_file.display()
+                )
+            })?;
+            self.api_key = Secret::new(api_key.trim());
+        }
+        Ok(())
     }
 }
 
 impl Config {
-    pub fn redacted(&self) -> Config {
-        let config = self.clone();
-        Config {
-            tethysdashes: config
-                .tethysdashes
-                .into_iter()
-                .map(|td| TethysDashConfig {
-                    api_key: "REDACTED".to_string(),
-                    ..td
-                })
-                .collect(),
-            ..config
+    /// Poll period explicitly configured for the given platform, if any.
+    /// A per-platform setting has precedence over a per-type one.
+    pub fn poll_period_override(
+        &self,
+        platform_id: &str,
+        type_name: Option<&str>,
+    ) -> Option<Duration> {
+        self.poll_period_by_platform
+            .get(platform_id)
+            .or_else(|| type_name.and_then(|t| self.poll_period_by_type.get(t)))
+            .map(|d| **d)
+    }
+
+    /// IDs of the platforms in the dispatch lists of the TethysDash instances, if any.
+    pub fn routing_dispatch_ids(&self) -> Vec<String> {
+        let mut platform_ids = self
+            .tethysdashes
+            .iter()
+            .filter_map(|td| td.routing.as_ref()?.dispatch_list.as_ref())
+            .flatten()
+            .cloned()
+            .collect::<Vec<_>>();
+        platform_ids.sort();
+        platform_ids.dedup();
+        platform_ids
+    }
+
+    /// Path of the given state file under the configured state directory.
+    pub fn state_path(&self, filename: &str) -> PathBuf {
+        self.state_dir.join(filename)
+    }
+
+    /// Checks the configuration, returning any problems found.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut check_url = |setting: &str, value: &str| {
+            if let Err(e) = check_url(value) {
+                problems.push(format!("{setting}: invalid URL '{value}': {e}"));
+            }
+        };
+        if self.data_dir.is_none() && self.simulation.is_none() {
+            check_url("odssApi", &self.odss_api);
+        }
+        let uses_odss = |s: &&SourceConfig| s.data_dir.is_none() && s.simulation.is_none();
+        for source in self.sources.iter().filter(uses_odss) {
+            check_url(
+                &format!("sources['{}'].odssApi", source.name),
+                &source.odss_api,
+            );
+        }
+        check_url("externalUrl", &self.external_url);
+        for td in &self.tethysdashes {
+            check_url(&format!("tethysdashes['{}'].api", td.name), &td.api);
+        }
+        for webhook in &self.alerts.webhooks {
+            check_url("alerts.webhooks", webhook);
+        }
+
+        if self.poll_period.is_zero() {
+            problems.push("pollPeriod must be greater than zero".to_string());
+        }
+        for (key, period) in self
+            .poll_period_by_type
+            .iter()
+            .chain(self.poll_period_by_platform.iter())
+        {
+            if period.is_zero() {
+                problems.push(format!("poll period for '{key}' must be greater than zero"));
+            }
+        }
+        let data_dirs = std::iter::once(("dataDir".to_string(), &self.data_dir)).chain(
+            self.sources
+                .iter()
+                .map(|s| (format!("sources['{}'].dataDir", s.name), &s.data_dir)),
+        );
+        for (setting, data_dir) in data_dirs {
+            if let Some(dir) = data_dir.as_ref().filter(|dir| !dir.is_dir()) {
+                problems.push(format!("{setting}: not a directory: '{}'", dir.display()));
+            }
+        }
+        let simulations = std::iter::once(("", &self.data_dir, &self.simulation)).chain(
+            self.sources
+                .iter()
+                .map(|s| (s.name.as_str(), &s.data_dir, &s.simulation)),
+        );
+        for (name, data_dir, simulation) in simulations {
+            let Some(simulation) = simulation else {
+                continue;
+            };
+            let setting = if name.is_empty() {
+                "simulation".to_string()
+            } else {
+                format!("sources['{name}'].simulation")
+            };
+            if data_dir.is_some() {
+                problems.push(format!("{setting}: cannot be given together with dataDir"));
+            }
+            problems.extend(simulation.validate(&setting));
+        }
+        if self.history.retention.is_some_and(|r| r.is_zero()) {
+            problems.push("history.retention must be greater than zero".to_string());
         }
+        if self.pruning.refresh_period.is_zero() {
+            problems.push("pruning.refreshPeriod must be greater than zero".to_string());
+        }
+        problems.extend(self.fix_filter.validate());
+        problems.extend(self.decimation.validate());
+        problems.extend(self.staleness.validate());
+        for (i, rule) in self.proximity.iter().enumerate() {
+            problems.extend(rule.validate());
+            if self.proximity[..i].iter().any(|r| r.name == rule.name) {
+                problems.push(format!("duplicate proximity rule name: '{}'", rule.name));
+            }
+        }
+        let mut geofences = self.geofences.clone();
+        for geofence in &self.geofences {
+            problems.extend(geofence.validate("geofences"));
+        }
+        if let Some(path) = &self.geofences_file {
+            match geofence::load_geojson_file(path) {
+                Ok(from_file) => geofences.extend(from_file),
+                Err(e) => problems.push(format!("geofencesFile '{}': {e}", path.display())),
+            }
+        }
+        for (i, geofence) in geofences.iter().enumerate() {
+            if geofences[..i].iter().any(|g| g.name == geofence.name) {
+                problems.push(format!("duplicate geofence name: '{}'", geofence.name));
+            }
+        }
+        if self.default_last_number_of_fixes == 0 {
+            problems.push("defaultLastNumberOfFixes must be greater than zero".to_string());
+        }
+
+        let mut source_names = vec![DEFAULT_SOURCE];
+        for source in &self.sources {
+            if source.name.is_empty() || source.name.contains(':') {
+                problems.push(format!("sources: invalid name: '{}'", source.name));
+            } else if source_names.contains(&source.name.as_str()) {
+                problems.push(format!(
+                    "duplicate or reserved source name: '{}'",
+                    source.name
+                ));
+            }
+            source_names.push(&source.name);
+        }
+
+        let mut names = Vec::new();
+        for td in &self.tethysdashes {
+            if td.name.is_empty() {
+                problems.push("tethysdashes: empty name".to_string());
+            } else if names.contains(&&td.name) {
+                problems.push(format!("duplicate TethysDash name: '{}'", td.name));
+            }
+            names.push(&td.name);
+
+            if let Some(include) = td.routing.as_ref().and_then(|r| r.include.as_ref()) {
+                if include.platform_ids.is_empty()
+                    && include.name_patterns.is_empty()
+                    && include.type_names.is_empty()
+                {
+                    problems.push(format!(
+                        "tethysdashes['{}'].routing.include: empty filter would exclude all platforms",
+                        td.name
+                    ));
+                }
+            }
+
+            match (&td.api_key_file, td.api_key.expose()) {
+                (None, "") => problems.push(format!(
+                    "tethysdashes['{}']: one of apiKey or apiKeyFile is required",
+                    td.name
+                )),
+                (None, api_key) => {
+                    if let Some(env_var) = api_key.strip_prefix('$') {
+                        problems.push(format!(
+                            "tethysdashes['{}'].apiKey: '{env_var}' undefined as environment variable",
+                            td.name
+                        ));
+                    }
+                }
+                (Some(_), "") => problems.push(format!(
+                    "tethysdashes['{}'].apiKeyFile: file is empty",
+                    td.name
+                )),
+                (Some(_), _) => {}
+            }
+        }
+        if let Some(env_var) = self.admin_api_key.expose().strip_prefix('$') {
+            problems.push(format!(
+                "adminApiKey: '{env_var}' undefined as environment variable"
+            ));
+        }
+        problems
     }
 
     pub fn json_string(&self) -> String {
@@ -93,17 +697,190 @@ impl Config {
     }
 }
 
-/// Parse the configuration from a file.
-fn parse_config_file(filename: &str) -> Config {
+/// Only absolute http(s) URLs are accepted.
+fn check_url(value: &str) -> Result<(), String> {
+    let url = url::Url::parse(value).map_err(|e| e.to_string())?;
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err(format!("unsupported scheme '{}'", url.scheme()));
+    }
+    if url.host_str().is_none() {
+        return Err("missing host".to_string());
+    }
+    Ok(())
+}
+
+/// Loads the configuration from the explicitly indicated file, or from the
+/// default file if existing, otherwise only from environment variables.
+fn try_load_config() -> Result<Config, String> {
+    let config_file = CONFIG_FILE.read().unwrap().clone();
+    match config_file {
+        Some(filename) => parse_config_file(&filename),
+        None if Path::new(CONFIG_FILENAME).exists() => {
+            parse_config_file(Path::new(CONFIG_FILENAME))
+        }
+        None => {
+            dotenv().ok();
+            parse_config_string_with_overrides("", std::env::vars())
+        }
+    }
+}
+
+/// Parse the configuration from a file, with any environment variable overrides.
+fn parse_config_file(filename: &Path) -> Result<Config, String> {
     dotenv().ok();
-    let toml_content =
-        fs::read_to_string(filename).unwrap_or_else(|_| panic!("Failed to load {filename}"));
-    parse_config_string(&toml_content).unwrap_or_else(|_| panic!("Failed to parse {filename}"))
+    let toml_content = fs::read_to_string(filename)
+        .map_err(|e| format!("Failed to load {}: {e}", filename.display()))?;
+    parse_config_string_with_overrides(&toml_content, std::env::vars())
+        .map_err(|e| format!("Failed to parse {}: {e}", filename.display()))
+}
+
+/// Parse the configuration from a string, with overrides from any given
+/// `ODSS2DASH_<SETTING>` variables, where `<SETTING>` is the name of the setting in
+/// upper snake case, e.g., `ODSS2DASH_POLL_PERIOD` for `pollPeriod`. A double underscore
+/// indicates nesting, e.g., `ODSS2DASH_ADAPTIVE_POLLING__MAX_POLL_PERIOD`.
+/// An array element is indicated by its index, e.g., `ODSS2DASH_TETHYSDASHES__0__API_KEY`,
+/// and map keys (e.g., platform types) are used as given, e.g., `ODSS2DASH_POLL_PERIOD_BY_TYPE__LRAUV`.
+/// Values are taken as TOML values if valid as such and as expected by the setting,
+/// otherwise as strings,
+/// e.g., `ODSS2DASH_TETHYSDASHES='[{name = "x", api = "https://...", apiKey = "$X_APIKEY"}]'`.
+fn parse_config_string_with_overrides(
+    toml_content: &str,
+    vars: impl Iterator<Item = (String, String)>,
+) -> Result<Config, String> {
+    let table = toml_content
+        .parse::<toml::Table>()
+        .map_err(|e| e.to_string())?;
+    let mut overrides = Vec::new();
+    for (name, value) in vars {
+        if name == CONFIG_ENV_VAR {
+            continue;
+        }
+        if let Some(setting) = name.strip_prefix(ENV_PREFIX) {
+            let keys = setting_keys(setting);
+            log::info!("Setting '{}' overridden by {name}", keys.join("."));
+            overrides.push((keys, parse_env_value(&value), value));
+        }
+    }
+    let mut config = loop {
+        let mut value = toml::Value::Table(table.clone());
+        for (keys, override_value, _) in &overrides {
+            set_nested(&mut value, keys, override_value.clone());
+        }
+        match serde_path_to_error::deserialize::<_, Config>(value) {
+            Ok(config) => break config,
+            Err(e) => {
+                // a value taken as, e.g., a number, where a string is expected:
+                let path = path_keys(e.path());
+                let retry = overrides
+                    .iter_mut()
+                    .find(|(keys, value, _)| *keys == path && !value.is_str());
+                match retry {
+                    Some((_, value, raw)) => *value = toml::Value::String(raw.clone()),
+                    None => return Err(e.into_inner().to_string()),
+                }
+            }
+        }
+    };
+    for td in &mut config.tethysdashes {
+        td.read_api_key_file()?;
+    }
+    Ok(config)
+}
+
+/// Settings that are maps, whose keys (e.g., platform types or IDs) are used as given.
+const MAP_SETTINGS: [&str; 7] = [
+    "pollPeriodByType",
+    "pollPeriodByPlatform",
+    "fixFilter.maxSpeedByType",
+    "decimation.byType",
+    "decimation.byPlatform",
+    "staleness.byType",
+    "staleness.byPlatform",
+];
+
+/// `ADAPTIVE_POLLING__MAX_POLL_PERIOD` -> `["adaptivePolling", "maxPollPeriod"]`,
+/// with array indices and map keys as given.
+fn setting_keys(setting: &str) -> Vec<String> {
+    let mut keys: Vec<String> = Vec::new();
+    for segment in setting.split("__") {
+        let is_map_key = MAP_SETTINGS.contains(&keys.join(".").as_str());
+        if is_map_key || segment.parse::<usize>().is_ok() {
+            keys.push(segment.to_string());
+        } else {
+            keys.push(camel_case(segment));
+        }
+    }
+    keys
+}
+
+/// The keys of a deserialization error path, as in [setting_keys].
+fn path_keys(path: &serde_path_to_error::Path) -> Vec<String> {
+    path.iter()
+        .filter_map(|segment| match segment {
+            serde_path_to_error::Segment::Seq { index } => Some(index.to_string()),
+            serde_path_to_error::Segment::Map { key } => Some(key.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// `POLL_PERIOD` -> `pollPeriod`
+fn camel_case(name: &str) -> String {
+    let mut result = String::new();
+    for (i, word) in name.split('_').filter(|w| !w.is_empty()).enumerate() {
+        let word = word.to_lowercase();
+        if i == 0 {
+            result.push_str(&word);
+        } else {
+            let mut chars = word.chars();
+            if let Some(first) = chars.next() {
+                result.push(first.to_ascii_uppercase());
+                result.push_str(chars.as_str());
+            }
+        }
+    }
+    result
+}
+
+fn parse_env_value(value: &str) -> toml::Value {
+    match format!("value = {value}").parse::<toml::Table>() {
+        Ok(mut table) => table.remove("value").unwrap(),
+        Err(_) => toml::Value::String(value.to_string()),
+    }
+}
+
+fn set_nested(target: &mut toml::Value, keys: &[String], value: toml::Value) {
+    let Some((key, rest)) = keys.split_first() else {
+        *target = value;
+        return;
+    };
+    if let (toml::Value::Array(items), Ok(index)) = (&mut *target, key.parse::<usize>()) {
+        if index < items.len() {
+            set_nested(&mut items[index], rest, value);
+        } else if index == items.len() {
+            let mut item = toml::Value::Table(toml::Table::new());
+            set_nested(&mut item, rest, value);
+            items.push(item);
+        } else {
+            log::warn!("Ignoring override of '{key}': beyond the end of the array");
+        }
+        return;
+    }
+    if !target.is_table() {
+        *target = toml::Value::Table(toml::Table::new());
+    }
+    if let Some(table) = target.as_table_mut() {
+        let entry = table
+            .entry(key.clone())
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+        set_nested(entry, rest, value);
+    }
 }
 
 /// Parse the configuration from a string.
-fn parse_config_string(toml_content: &str) -> Result<Config, Box<dyn Error>> {
-    Ok(toml::from_str(toml_content)?)
+#[cfg(test)]
+fn parse_config_string(toml_content: &str) -> Result<Config, String> {
+    parse_config_string_with_overrides(toml_content, std::iter::empty())
 }
 
 #[cfg(test)]
@@ -137,17 +914,329 @@ mod tests {
             vec![TethysDashConfig {
                 name: String::from("tethystest"),
                 api: String::from("https://foo.example.net/TethysDash/api"),
-                api_key: String::from("eyFooBaz")
+                api_key: Secret::new("eyFooBaz"),
+                api_key_file: None,
+                routing: None,
+                kinematics: false,
             },]
         );
     }
 
+    #[test]
+    fn test_parse_poll_period_overrides() {
+        let toml_content = r#"
+        odssApi = "https://odss.mbari.org/odss"
+        externalUrl = "http://localhost:3033"
+        port = 3033
+        pollPeriod = "30 seconds"
+        defaultLastNumberOfFixes = 5
+        tethysdashes = []
+        [pollPeriodByType]
+        glider = "5 minutes"
+        [pollPeriodByPlatform]
+        "p1" = "10 seconds"
+        [adaptivePolling]
+        maxPollPeriod = "10 minutes"
+        "#;
+
+        let config: Config = parse_config_string(toml_content).unwrap();
+
+        let period = |id, type_name| config.poll_period_override(id, type_name);
+        assert_eq!(period("p1", Some("glider")), Some(Duration::from_secs(10)));
+        assert_eq!(period("p2", Some("glider")), Some(Duration::from_secs(300)));
+        assert_eq!(period("p2", Some("ship")), None);
+        assert_eq!(period("p2", None), None);
+        assert_eq!(
+            config.adaptive_polling,
+            Some(AdaptivePollingConfig {
+                max_poll_period: Duration::from_secs(600)
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_routing_rules() {
+        let toml_content = r#"
+        odssApi = "https://odss.mbari.org/odss"
+        externalUrl = "http://localhost:3033"
+        port = 3033
+        pollPeriod = "30 seconds"
+        defaultLastNumberOfFixes = 5
+
+        [[tethysdashes]]
+        name = "okeanids"
+        api = "https://okeanids.mbari.org/TethysDash/api"
+        apiKey = "key1"
+        [tethysdashes.routing]
+        dispatchList = ["p2", "p1"]
+
+        [[tethysdashes]]
+        name = "tethystest"
+        api = "http://tethystest.shore.mbari.org:8080/TethysDash/api"
+        apiKey = "key2"
+        [tethysdashes.routing]
+        exclude = { typeNames = ["ship"], namePatterns = ["*flyer*"] }
+        [tethysdashes.routing.include]
+        "#;
+
+        let config: Config = parse_config_string(toml_content).unwrap();
+        assert_eq!(config.routing_dispatch_ids(), vec!["p1", "p2"]);
+        let routing = config.tethysdashes[1].routing.as_ref().unwrap();
+        assert_eq!(
+            routing.exclude.as_ref().unwrap().type_names,
+            vec!["ship".to_string()]
+        );
+        assert_eq!(
+            config.validate(),
+            vec![
+                "tethysdashes['tethystest'].routing.include: empty filter would exclude all platforms"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_config_with_env_overrides() {
+        let toml_content = r#"
+        odssApi = "https://odss.mbari.org/odss"
+        externalUrl = "http://localhost:3033"
+        port = 3033
+        pollPeriod = "30 seconds"
+        defaultLastNumberOfFixes = 5
+        tethysdashes = []
+        "#;
+        let vars = [
+            ("ODSS2DASH_CONFIG", "ignored.toml"),
+            ("ODSS2DASH_PORT", "8080"),
+            ("ODSS2DASH_POLL_PERIOD", "1 minute"),
+            ("ODSS2DASH_STATE_DIR", "/var/lib/odss2dash"),
+            ("ODSS2DASH_ADAPTIVE_POLLING__MAX_POLL_PERIOD", "10 minutes"),
+            (
+                "ODSS2DASH_TETHYSDASHES",
+                r#"[{name = "tethystest", api = "https://foo.example.net/TethysDash/api", apiKey = "eyFoo"}]"#,
+            ),
+            ("OTHER_VAR", "ignored"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()));
+
+        let config = parse_config_string_with_overrides(toml_content, vars).unwrap();
+
+        assert_eq!(config.odss_api, "https://odss.mbari.org/odss");
+        assert_eq!(config.port, 8080);
+        assert_eq!(config.poll_period, Duration::from_secs(60));
+        assert_eq!(config.state_dir, PathBuf::from("/var/lib/odss2dash"));
+        assert_eq!(
+            config.state_path("reported.json"),
+            PathBuf::from("/var/lib/odss2dash/reported.json")
+        );
+        assert_eq!(
+            config.adaptive_polling,
+            Some(AdaptivePollingConfig {
+                max_poll_period: Duration::from_secs(600)
+            })
+        );
+        assert_eq!(config.tethysdashes.len(), 1);
+        assert_eq!(config.tethysdashes[0].api_key.expose(), "eyFoo");
+
+        // numeric-looking values for string settings, array elements, and map keys:
+        let vars = [
+            ("ODSS2DASH_TETHYSDASHES__0__API_KEY", "12345"),
+            ("ODSS2DASH_TETHYSDASHES__0__NAME", "2026"),
+            ("ODSS2DASH_POLL_PERIOD_BY_TYPE__LRAUV", "2 minutes"),
+            ("ODSS2DASH_DECIMATION__BY_TYPE__ship__MIN_DISTANCE", "50"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()));
+        let toml_content = format!(
+            "{toml_content}\n{}",
+            r#"tethysdashes = [{name = "x", api = "https://foo.example.net/api", apiKey = "eyFoo"}]"#
+        )
+        .replace("tethysdashes = []", "");
+        let config = parse_config_string_with_overrides(&toml_content, vars).unwrap();
+        assert_eq!(config.tethysdashes[0].api_key.expose(), "12345");
+        assert_eq!(config.tethysdashes[0].name, "2026");
+        assert_eq!(
+            config.poll_period_by_type.get("LRAUV").map(|p| **p),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(config.decimation.by_type["ship"].min_distance, Some(50.0));
+    }
+
+    #[test]
+    fn test_api_key_file() {
+        let api_key_file = std::env::temp_dir().join("odss2dash-test-api-key");
+        fs::write(&api_key_file, "eyFromFile\n").unwrap();
+        let toml_content = format!(
+            r#"
+            odssApi = "https://odss.mbari.org/odss"
+            externalUrl = "http://localhost:3033"
+            port = 3033
+            pollPeriod = "30 seconds"
+            defaultLastNumberOfFixes = 5
+            [[tethysdashes]]
+            name = "tethystest"
+            api = "https://foo.example.net/TethysDash/api"
+            apiKeyFile = "{}"
+            "#,
+            api_key_file.display()
+        );
+
+        let config = parse_config_string(&toml_content).unwrap();
+        fs::remove_file(&api_key_file).unwrap();
+
+        let td = &config.tethysdashes[0];
+        assert_eq!(td.api_key.expose(), "eyFromFile");
+        assert!(!format!("{config:?}").contains("eyFromFile"));
+        assert!(!config.json_string().contains("eyFromFile"));
+        assert!(config.validate().is_empty());
+    }
+
+    #[test]
+    fn test_validate() {
+        let td = |name: &str, api: &str, api_key: &str| TethysDashConfig {
+            name: name.to_string(),
+            api: api.to_string(),
+            api_key: Secret::new(api_key),
+            api_key_file: None,
+            routing: None,
+            kinematics: false,
+        };
+        let config = Config {
+            odss_api: "https://odss.mbari.org/odss".to_string(),
+            external_url: "http://localhost:3033".to_string(),
+            port: 3033,
+            poll_period: Duration::from_secs(30),
+            default_last_number_of_fixes: 5,
+            tethysdashes: vec![td("okeanids", "https://okeanids.mbari.org/api", "eyFoo")],
+            ..Config::default()
+        };
+        assert!(config.validate().is_empty());
+
+        let config = Config {
+            odss_api: "odss.mbari.org/odss".to_string(),
+            poll_period: Duration::ZERO,
+            sources: vec![
+                SourceConfig {
+                    name: "partner".to_string(),
+                    odss_api: "https://odss.partner.example.org/odss".to_string(),
+                    data_dir: None,
+                    simulation: None,
+                },
+                SourceConfig {
+                    name: "default".to_string(),
+                    odss_api: "https://odss-test.mbari.org/odss".to_string(),
+                    data_dir: None,
+                    simulation: None,
+                },
+            ],
+            tethysdashes: vec![
+                td("okeanids", "ftp://okeanids.mbari.org/api", "eyFoo"),
+                td(
+                    "okeanids",
+                    "https://okeanids.mbari.org/api",
+                    "$UNDEFINED_APIKEY",
+                ),
+            ],
+            ..config
+        };
+        assert_eq!(
+            config.validate(),
+            vec![
+                "odssApi: invalid URL 'odss.mbari.org/odss': relative URL without a base",
+                "tethysdashes['okeanids'].api: invalid URL 'ftp://okeanids.mbari.org/api': unsupported scheme 'ftp'",
+                "pollPeriod must be greater than zero",
+                "duplicate or reserved source name: 'default'",
+                "duplicate TethysDash name: 'okeanids'",
+                "tethysdashes['okeanids'].apiKey: 'UNDEFINED_APIKEY' undefined as environment variable",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_reload() {
+        let td = |name: &str, api_key: &str| TethysDashConfig {
+            name: name.to_string(),
+            api: "https://foo.example.net/TethysDash/api".to_string(),
+            api_key: Secret::new(api_key),
+            api_key_file: None,
+            routing: None,
+            kinematics: false,
+        };
+        let current = Config {
+            odss_api: "https://odss.mbari.org/odss".to_string(),
+            external_url: "http://localhost:3033".to_string(),
+            port: 3033,
+            poll_period: Duration::from_secs(30),
+            default_last_number_of_fixes: 5,
+            tethysdashes: vec![td("okeanids", "eyFoo"), td("tethystest", "eyBaz")],
+            ..Config::default()
+        };
+
+        let new_config = Config {
+            poll_period: Duration::from_secs(60),
+            tethysdashes: vec![td("okeanids", "eyFoo2"), td("other", "eyOther")],
+            ..current.clone()
+        };
+        assert_eq!(
+            check_reload(&current, &new_config).unwrap(),
+            vec![
+                "pollPeriod: updated",
+                "tethysdashes: updated 'okeanids'",
+                "tethysdashes: added 'other'",
+                "tethysdashes: removed 'tethystest'",
+            ]
+        );
+
+        // secrets compared by value, though redacted in the JSON:
+        let current = Config {
+            admin_api_key: Secret::new("old-key"),
+            data_dir: Some(std::env::temp_dir()),
+            ..current
+        };
+        let new_config = Config {
+            admin_api_key: Secret::new("new-key"),
+            ..current.clone()
+        };
+        assert_eq!(
+            check_reload(&current, &new_config).unwrap(),
+            vec!["adminApiKey: updated"]
+        );
+
+        // settings omitted when not set:
+        let new_config = Config {
+            data_dir: None,
+            ..current.clone()
+        };
+        assert_eq!(
+            check_reload(&current, &new_config).unwrap(),
+            vec!["dataDir: updated"]
+        );
+
+        let new_config = Config {
+            port: 8080,
+            ..current.clone()
+        };
+        assert!(matches!(
+            check_reload(&current, &new_config),
+            Err(ReloadError::NotReloadable(settings)) if settings == vec!["port"]
+        ));
+
+        let new_config = Config {
+            poll_period: Duration::ZERO,
+            ..current.clone()
+        };
+        assert!(matches!(
+            check_reload(&current, &new_config),
+            Err(ReloadError::Invalid(_))
+        ));
+    }
+
     #[test]
     fn test_parse_default_config_file() {
         std::env::set_var("OKEANIDS_APIKEY", "eyFoo");
         std::env::set_var("TETHYSTEST_APIKEY", "eyBaz");
 
-        let config = parse_config_file(CONFIG_FILENAME);
+        let config = parse_config_file(Path::new(CONFIG_FILENAME)).unwrap();
 
         assert_eq!(config.odss_api, "https://odss.mbari.org/odss");
         assert_eq!(config.external_url, "http://localhost:3033");
@@ -160,12 +1249,18 @@ mod tests {
                 TethysDashConfig {
                     name: String::from("okeanids"),
                     api: String::from("https://okeanids.mbari.org/TethysDash/api"),
-                    api_key: String::from("eyFoo")
+                    api_key: Secret::new("eyFoo"),
+                    api_key_file: None,
+                    routing: None,
+                    kinematics: false,
                 },
                 TethysDashConfig {
                     name: String::from("tethystest"),
                     api: String::from("http://tethystest.shore.mbari.org:8080/TethysDash/api"),
-                    api_key: String::from("eyBaz")
+                    api_key: Secret::new("eyBaz"),
+                    api_key_file: None,
+                    routing: None,
+                    kinematics: false,
                 },
             ]
         );
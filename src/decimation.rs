@@ -0,0 +1,237 @@
+//! Reduction of high-rate tracks: decimation of the fixes to dispatch, and
+//! Douglas–Peucker simplification of tracks for display.
+
+use crate::geo;
+use crate::trackdb_client::Position;
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Minimum time interval and/or distance between dispatched fixes.
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DecimationRule {
+    #[serde(
+        default,
+        with = "humantime_serde",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub min_interval: Option<Duration>,
+    /// In meters.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_distance: Option<f64>,
+}
+
+impl DecimationRule {
+    fn is_empty(&self) -> bool {
+        self.min_interval.is_none() && self.min_distance.is_none()
+    }
+}
+
+/// Decimation of the dispatched fixes, with the rule for a platform given by its ID,
+/// or else by its type name, or else the general rule.
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DecimationConfig {
+    #[serde(
+        default,
+        with = "humantime_serde",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub min_interval: Option<Duration>,
+    /// In meters.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_distance: Option<f64>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub by_type: BTreeMap<String, DecimationRule>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub by_platform: BTreeMap<String, DecimationRule>,
+}
+
+impl DecimationConfig {
+    pub fn rule_for(&self, platform_id: &str, type_name: Option<&str>) -> DecimationRule {
+        if let Some(rule) = self.by_platform.get(platform_id) {
+            return rule.clone();
+        }
+        if let Some(rule) = type_name.and_then(|t| self.by_type.get(t)) {
+            return rule.clone();
+        }
+        DecimationRule {
+            min_interval: self.min_interval,
+            min_distance: self.min_distance,
+        }
+    }
+
+    /// Checks the settings, returning any problems found.
+    pub fn validate(&self) -> Vec<String> {
+        let general = DecimationRule {
+            min_interval: self.min_interval,
+            min_distance: self.min_distance,
+        };
+        let rules = std::iter::once(("decimation".to_string(), &general))
+            .chain(
+                self.by_type
+                    .iter()
+                    .map(|(t, r)| (format!("decimation.byType['{t}']"), r)),
+            )
+            .chain(
+                self.by_platform
+                    .iter()
+                    .map(|(id, r)| (format!("decimation.byPlatform['{id}']"), r)),
+            );
+        let mut problems = Vec::new();
+        for (setting, rule) in rules {
+            if rule.min_interval.is_some_and(|i| i.is_zero()) {
+                problems.push(format!("{setting}.minInterval must be greater than zero"));
+            }
+            if rule.min_distance.is_some_and(|d| d.is_nan() || d <= 0.0) {
+                problems.push(format!("{setting}.minDistance must be greater than zero"));
+            }
+        }
+        problems
+    }
+}
+
+/// Splits the given fixes, in chronological order, into those to dispatch and those
+/// skipped, with each fix to dispatch meeting the minimums of the rule with respect
+/// to the previously dispatched one, starting with `previous`, if any.
+pub fn decimate(
+    rule: &DecimationRule,
+    mut previous: Option<Position>,
+    fixes: Vec<Position>,
+) -> (Vec<Position>, Vec<Position>) {
+    if rule.is_empty() {
+        return (fixes, Vec::new());
+    }
+    let mut to_dispatch = Vec::new();
+    let mut skipped = Vec::new();
+    for fix in fixes {
+        let far_enough = |previous: &Position| {
+            let interval_ok = rule.min_interval.is_none_or(|min_interval| {
+                fix.time_ms.saturating_sub(previous.time_ms) >= min_interval.as_millis() as u64
+            });
+            let distance_ok = rule.min_distance.is_none_or(|min_distance| {
+                geo::distance_m((previous.lat, previous.lon), (fix.lat, fix.lon)) >= min_distance
+            });
+            interval_ok && distance_ok
+        };
+        if previous.as_ref().is_none_or(far_enough) {
+            previous = Some(fix.clone());
+            to_dispatch.push(fix);
+        } else {
+            skipped.push(fix);
+        }
+    }
+    (to_dispatch, skipped)
+}
+
+/// Simplifies the track with the Douglas–Peucker algorithm, keeping the points
+/// farther than the tolerance (in meters) from the simplified track.
+pub fn simplify(positions: Vec<Position>, tolerance_m: f64) -> Vec<Position> {
+    if positions.len() < 3 {
+        return positions;
+    }
+    let point = |i: usize| (positions[i].lat, positions[i].lon);
+    let mut keep = vec![false; positions.len()];
+    keep[0] = true;
+    keep[positions.len() - 1] = true;
+    let mut ranges = vec![(0, positions.len() - 1)];
+    while let Some((first, last)) = ranges.pop() {
+        let farthest = (first + 1..last)
+            .map(|i| {
+                (
+                    i,
+                    geo::distance_to_segment_m(point(i), point(first), point(last)),
+                )
+            })
+            .max_by(|(_, a), (_, b)| a.total_cmp(b));
+        if let Some((i, distance)) = farthest {
+            if distance > tolerance_m {
+                keep[i] = true;
+                ranges.push((first, i));
+                ranges.push((i, last));
+            }
+        }
+    }
+    positions
+        .into_iter()
+        .zip(keep)
+        .filter_map(|(position, keep)| keep.then_some(position))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn fix(time_s: u64, lat: f64, lon: f64) -> Position {
+        Position {
+            time_ms: time_s * 1000,
+            lat,
+            lon,
+            kinematics: None,
+        }
+    }
+
+    fn times(fixes: &[Position]) -> Vec<u64> {
+        fixes.iter().map(|p| p.time_ms / 1000).collect()
+    }
+
+    #[test]
+    fn test_decimate() {
+        // AIS-like: every second, ~5 m apart
+        let fixes = (1..=120)
+            .map(|s| fix(s, 36.8 + s as f64 * 0.000045, -122.0))
+            .collect::<Vec<_>>();
+        let previous = Some(fix(0, 36.8, -122.0));
+
+        let rule = DecimationRule {
+            min_interval: Some(Duration::from_secs(30)),
+            min_distance: None,
+        };
+        let (to_dispatch, skipped) = decimate(&rule, previous.clone(), fixes.clone());
+        assert_eq!(times(&to_dispatch), vec![30, 60, 90, 120]);
+        assert_eq!(skipped.len(), 116);
+
+        let rule = DecimationRule {
+            min_interval: Some(Duration::from_secs(30)),
+            min_distance: Some(250.0),
+        };
+        let (to_dispatch, _) = decimate(&rule, previous, fixes.clone());
+        assert_eq!(times(&to_dispatch), vec![50, 100]);
+
+        let (to_dispatch, skipped) = decimate(&DecimationRule::default(), None, fixes);
+        assert_eq!((to_dispatch.len(), skipped.len()), (120, 0));
+
+        let config = DecimationConfig {
+            min_interval: Some(Duration::from_secs(60)),
+            by_type: BTreeMap::from([("ship".to_string(), rule.clone())]),
+            ..DecimationConfig::default()
+        };
+        assert_eq!(config.rule_for("p1", Some("ship")), rule);
+        assert_eq!(
+            config.rule_for("p1", None).min_interval,
+            Some(Duration::from_secs(60))
+        );
+    }
+
+    #[test]
+    fn test_simplify() {
+        // a straight line with a small wiggle and a large detour
+        let positions = vec![
+            fix(0, 36.80, -122.00),
+            fix(1, 36.80001, -121.99),
+            fix(2, 36.80, -121.98),
+            fix(3, 36.81, -121.97),
+            fix(4, 36.80, -121.96),
+            fix(5, 36.80, -121.95),
+        ];
+        assert_eq!(
+            times(&simplify(positions.clone(), 10.0)),
+            vec![0, 2, 3, 4, 5]
+        );
+        assert_eq!(times(&simplify(positions, 2_000.0)), vec![0, 5]);
+    }
+}
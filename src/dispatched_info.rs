@@ -1,80 +1,364 @@
+use crate::audit::{self, AuditAction, AuditContext};
+use crate::state_store::{self, StateStore};
+use crate::time_util;
+
 use serde::{Deserialize, Serialize};
-use std::fs::File;
-use std::path::Path;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+use std::time::Duration;
+use utoipa::ToSchema;
 
 type PlatformId = String;
 
-/// The IDs of the dispatched platforms.
-#[derive(Serialize, Deserialize, Debug, Default)]
+/// The persisted information about the dispatched platforms.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
-pub struct DispatchedInfo {
-    platform_ids: Vec<PlatformId>,
+pub struct DispatchedState {
+    pub platform_ids: Vec<PlatformId>,
+    /// Optional poll periods for specific platforms, overriding those in the configuration.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub poll_periods: BTreeMap<PlatformId, humantime_serde::Serde<Duration>>,
+    /// Dispatched platforms missing from the Tracking DB, with the time (epoch ms)
+    /// since they have been missing, as determined upon full platform refreshes.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub orphaned_since: BTreeMap<PlatformId, u64>,
+    /// Optional details for specific platforms, like a dispatch time window.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub details: BTreeMap<PlatformId, DispatchDetails>,
+    /// Named groups of platforms, for bulk dispatch.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub groups: BTreeMap<GroupName, Vec<PlatformId>>,
+}
+
+pub type GroupName = String;
+
+/// Checks that the given name is valid for a group.
+pub fn check_group_name(name: &str) -> Result<(), String> {
+    if name.trim().is_empty() {
+        Err("group name cannot be empty".to_string())
+    } else if name.chars().any(|c| c.is_control() || c == '/') {
+        Err(format!("invalid group name: '{name}'"))
+    } else {
+        Ok(())
+    }
+}
+
+/// Optional details about a dispatched platform.
+#[derive(Serialize, Deserialize, ToSchema, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DispatchDetails {
+    /// Time (epoch ms) from which the platform is dispatched.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_time_ms: Option<u64>,
+    /// Time (epoch ms) at which the platform gets undispatched.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// Who added the platform.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub added_by: Option<String>,
 }
 
-/// List of dispatched platforms maintained in this file.
-const DISPATCHED_PATH: &str = "./dispatched.json";
+impl DispatchDetails {
+    /// Creates the details from the given values, with the times as accepted by
+    /// [time_util::parse_time_ms]. The expiration must be in the future and after
+    /// any start time.
+    pub fn new(
+        start_time: Option<&str>,
+        expires_at: Option<&str>,
+        note: Option<String>,
+        added_by: Option<String>,
+    ) -> Result<Self, String> {
+        let parse = |name: &str, value: Option<&str>| {
+            value
+                .map(time_util::parse_time_ms)
+                .transpose()
+                .map_err(|e| format!("{name}: {e}"))
+        };
+        let start_time_ms = parse("startTime", start_time)?;
+        let expires_at_ms = parse("expiresAt", expires_at)?;
+        if let Some(expires_at_ms) = expires_at_ms {
+            if expires_at_ms <= time_util::now_ms() {
+                return Err("expiresAt: must be in the future".to_string());
+            }
+            if start_time_ms.is_some_and(|start| expires_at_ms <= start) {
+                return Err("expiresAt: must be after startTime".to_string());
+            }
+        }
+        Ok(DispatchDetails {
+            start_time_ms,
+            expires_at_ms,
+            note: note.filter(|n| !n.trim().is_empty()),
+            added_by: added_by.filter(|a| !a.trim().is_empty()),
+        })
+    }
+
+    /// Whether the platform is to be dispatched at the given time.
+    pub fn is_active(&self, now_ms: u64) -> bool {
+        self.start_time_ms.is_none_or(|start| start <= now_ms) && !self.is_expired(now_ms)
+    }
+
+    pub fn is_expired(&self, now_ms: u64) -> bool {
+        self.expires_at_ms.is_some_and(|expires| expires <= now_ms)
+    }
+
+    /// Whether any of the settings that can be updated upon re-adding a platform is given.
+    fn has_settings(&self) -> bool {
+        self.start_time_ms.is_some() || self.expires_at_ms.is_some() || self.note.is_some()
+    }
+}
+
+/// The IDs of the dispatched platforms, as maintained in the state store.
+/// The store is always queried, so changes made by other processes
+/// (e.g., `odss2dash add-dispatched` while the service is running) are seen.
+pub struct DispatchedInfo {
+    store: Arc<dyn StateStore>,
+}
 
 impl DispatchedInfo {
     pub fn new() -> Self {
-        if !Path::new(DISPATCHED_PATH).exists() {
-            println!("Creating new '{DISPATCHED_PATH}' file");
-            Self::default().save();
+        Self {
+            store: state_store::get_store(),
         }
-        Self::load()
+    }
+
+    /// With the given store instead of the configured one, so a test does not
+    /// interfere with the platforms dispatched by other tests.
+    #[cfg(test)]
+    pub fn with_store(store: Arc<dyn StateStore>) -> Self {
+        Self { store }
     }
 
     pub fn get_platform_ids(&self) -> Vec<PlatformId> {
-        self.platform_ids.clone()
+        self.state().platform_ids
+    }
+
+    pub fn get_poll_period(&self, platform_id: &str) -> Option<Duration> {
+        self.state().poll_periods.get(platform_id).map(|d| **d)
     }
 
     pub fn is_dispatched_platform(&self, platform_id: &str) -> bool {
-        self.platform_ids.contains(&platform_id.to_string())
+        self.state().platform_ids.contains(&platform_id.to_string())
+    }
+
+    /// IDs of the dispatched platforms that are active at the given time,
+    /// that is, not pending per a start time, nor expired.
+    pub fn get_active_platform_ids(&self, now_ms: u64) -> Vec<PlatformId> {
+        let state = self.state();
+        state
+            .platform_ids
+            .into_iter()
+            .filter(|id| state.details.get(id).is_none_or(|d| d.is_active(now_ms)))
+            .collect()
+    }
+
+    pub fn get_details(&self) -> BTreeMap<PlatformId, DispatchDetails> {
+        self.state().details
+    }
+
+    /// Adds the given platforms with the given details, returning those that were not
+    /// already dispatched. For already dispatched platforms, the details are replaced
+    /// if any time window or note is given, so plain re-adding keeps existing details.
+    /// If not given, `addedBy` is set to the identity in the audit context.
+    pub fn add_platform_ids(
+        &mut self,
+        platform_ids: Vec<PlatformId>,
+        details: DispatchDetails,
+        context: &AuditContext,
+    ) -> Vec<PlatformId> {
+        let details = DispatchDetails {
+            added_by: details.added_by.or_else(|| context.identity.clone()),
+            ..details
+        };
+        let added = self.update(|state| {
+            let mut result = Vec::new();
+            for platform_id in &platform_ids {
+                let is_new = !state.platform_ids.contains(platform_id);
+                if is_new {
+                    result.push(platform_id.clone());
+                    state.platform_ids.push(platform_id.clone());
+                }
+                if is_new || details.has_settings() {
+                    if details == DispatchDetails::default() {
+                        state.details.remove(platform_id);
+                    } else {
+                        state.details.insert(platform_id.clone(), details.clone());
+                    }
+                }
+            }
+            state.platform_ids.sort();
+            result
+        });
+        audit::record(AuditAction::Add, &added, context);
+        added
     }
 
-    pub fn add_platform_id(&mut self, platform_id: &PlatformId) -> bool {
-        if self.platform_ids.contains(platform_id) {
-            return false;
+    pub fn delete_platform_id(
+        &mut self,
+        platform_id: &str,
+        context: &AuditContext,
+    ) -> Option<PlatformId> {
+        let deleted = self.update(|state| {
+            if state.platform_ids.contains(&platform_id.to_string()) {
+                state.platform_ids.retain(|x| x != platform_id);
+                state.orphaned_since.remove(platform_id);
+                state.details.remove(platform_id);
+                Some(platform_id.to_string())
+            } else {
+                None
+            }
+        });
+        if let Some(deleted) = &deleted {
+            audit::record(AuditAction::Remove, std::slice::from_ref(deleted), context);
         }
-        self.platform_ids.push(platform_id.clone());
-        self.platform_ids.sort();
-        self.save();
-        true
-    }
-
-    pub fn add_platform_ids(&mut self, platform_ids: Vec<PlatformId>) -> Vec<PlatformId> {
-        let mut result = Vec::new();
-        for platform_id in platform_ids {
-            if !self.platform_ids.contains(&platform_id) {
-                result.push(platform_id.clone());
-                self.platform_ids.push(platform_id);
+        deleted
+    }
+
+    pub fn get_groups(&self) -> BTreeMap<GroupName, Vec<PlatformId>> {
+        self.state().groups
+    }
+
+    pub fn get_group(&self, name: &str) -> Option<Vec<PlatformId>> {
+        self.state().groups.remove(name)
+    }
+
+    /// Names of the groups each platform belongs to.
+    pub fn get_memberships(&self) -> BTreeMap<PlatformId, Vec<GroupName>> {
+        let mut memberships: BTreeMap<PlatformId, Vec<GroupName>> = BTreeMap::new();
+        for (name, platform_ids) in self.state().groups {
+            for platform_id in platform_ids {
+                memberships
+                    .entry(platform_id)
+                    .or_default()
+                    .push(name.clone());
             }
         }
-        self.platform_ids.sort();
-        self.save();
-        result
+        memberships
     }
 
-    pub fn delete_platform_id(&mut self, platform_id: &str) -> Option<PlatformId> {
-        let result = if self.platform_ids.contains(&platform_id.to_string()) {
-            self.platform_ids.retain(|x| x != platform_id);
-            Some(platform_id.to_string())
-        } else {
-            None
-        };
-        self.platform_ids.sort();
-        self.save();
-        result
+    /// Creates or replaces a group, returning true if created.
+    pub fn set_group(&mut self, name: &str, mut platform_ids: Vec<PlatformId>) -> bool {
+        platform_ids.sort();
+        platform_ids.dedup();
+        self.update(|state| {
+            state
+                .groups
+                .insert(name.to_string(), platform_ids.clone())
+                .is_none()
+        })
     }
 
-    fn save(&self) {
-        println!("Saving '{DISPATCHED_PATH}'");
-        let f = File::create(DISPATCHED_PATH).unwrap();
-        serde_json::to_writer_pretty(f, &self).unwrap();
+    /// Deletes a group (not affecting the dispatch of its platforms),
+    /// returning its platforms if it existed.
+    pub fn delete_group(&mut self, name: &str) -> Option<Vec<PlatformId>> {
+        self.update(|state| state.groups.remove(name))
     }
 
-    fn load() -> Self {
-        println!("Loading '{DISPATCHED_PATH}'");
-        let s = std::fs::read_to_string(DISPATCHED_PATH).unwrap();
-        serde_json::from_str(&s).unwrap()
+    /// Removes the platforms whose dispatch has expired at the given time,
+    /// returning their IDs.
+    pub fn remove_expired(&mut self, now_ms: u64) -> Vec<PlatformId> {
+        // checked first, to avoid a store update otherwise:
+        if !self.state().details.values().any(|d| d.is_expired(now_ms)) {
+            return Vec::new();
+        }
+        let expired = self.update(|state| {
+            let expired = state
+                .details
+                .iter()
+                .filter(|(_, d)| d.is_expired(now_ms))
+                .map(|(id, _)| id.clone())
+                .collect::<Vec<_>>();
+            for platform_id in &expired {
+                state.platform_ids.retain(|x| x != platform_id);
+                state.orphaned_since.remove(platform_id);
+                state.details.remove(platform_id);
+            }
+            expired
+        });
+        audit::record(AuditAction::Remove, &expired, &AuditContext::expired());
+        expired
+    }
+
+    /// The orphaned dispatched platforms, with the time since they have been missing.
+    pub fn get_orphans(&self) -> BTreeMap<PlatformId, u64> {
+        self.state().orphaned_since
+    }
+
+    /// Updates the orphaned platforms per the given IDs from a platform refresh,
+    /// returning the resulting orphans. Platforms not `checked` in the refresh
+    /// (i.e., from a source that could not be refreshed) keep their status.
+    pub fn update_orphans(
+        &mut self,
+        known_ids: &BTreeSet<PlatformId>,
+        checked: &dyn Fn(&str) -> bool,
+        now_ms: u64,
+    ) -> BTreeMap<PlatformId, u64> {
+        self.update(|state| {
+            let orphaned_since = std::mem::take(&mut state.orphaned_since);
+            for platform_id in &state.platform_ids {
+                let orphaned = if checked(platform_id) {
+                    !known_ids.contains(platform_id)
+                } else {
+                    orphaned_since.contains_key(platform_id)
+                };
+                if orphaned {
+                    let since = orphaned_since.get(platform_id).unwrap_or(&now_ms);
+                    state.orphaned_since.insert(platform_id.clone(), *since);
+                }
+            }
+            state.orphaned_since.clone()
+        })
+    }
+
+    fn state(&self) -> DispatchedState {
+        self.store.load_dispatched().unwrap_or_else(|e| {
+            log::error!("Failed to load dispatched platforms: {e}");
+            DispatchedState::default()
+        })
+    }
+
+    /// Applies the given update atomically in the store, returning the result of
+    /// the update, or its default value if the store could not be updated.
+    fn update<R: Default>(&mut self, mut f: impl FnMut(&mut DispatchedState) -> R) -> R {
+        let mut result = R::default();
+        match self.store.update_dispatched(&mut |state| result = f(state)) {
+            Ok(()) => result,
+            Err(e) => {
+                log::error!("Failed to update dispatched platforms: {e}");
+                eprintln!("error: failed to update dispatched platforms: {e}");
+                R::default()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_dispatch_details() {
+        let details = DispatchDetails::new(
+            Some("2026-01-01T00:00:00Z"),
+            Some("2099-01-01"),
+            Some("cruise".to_string()),
+            None,
+        )
+        .unwrap();
+        assert_eq!(details.start_time_ms, Some(1_767_225_600_000));
+        assert!(!details.is_active(1_767_225_599_999));
+        assert!(details.is_active(1_767_225_600_000));
+        assert!(details.is_expired(4_070_908_800_000));
+        assert!(!details.is_active(4_070_908_800_000));
+
+        assert!(DispatchDetails::new(None, Some("2020-01-01"), None, None).is_err());
+        assert!(DispatchDetails::new(Some("2099-02-01"), Some("2099-01-01"), None, None).is_err());
+        assert!(DispatchDetails::new(Some("soon"), None, None, None).is_err());
+        assert_eq!(
+            DispatchDetails::new(None, None, None, None),
+            Ok(DispatchDetails::default())
+        );
     }
 }
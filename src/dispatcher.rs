@@ -1,31 +1,63 @@
+use crate::alerts::{self, Alert};
+use crate::audit::AuditContext;
 use crate::config;
+use crate::decimation;
 use crate::dispatched_info::DispatchedInfo;
+use crate::fix_filter::{self, FixReference};
+use crate::geofence::{self, Geofence, GeofenceMonitor};
+use crate::history::{History, HistoryEntry};
 use crate::platform_info::PlatformInfo;
+use crate::poll_schedule::{poll_period_for, PollSchedule};
+use crate::proximity::{self, ProximityMonitor};
 use crate::publisher::{PostXEventFn, Publisher};
+use crate::server::metrics::{REJECTED_FIXES, STALE_PLATFORMS};
+use crate::staleness::{self, StalenessMonitor};
+use crate::state_store::{self, ReportedMap};
 use crate::tethysdash_client::XEvent;
+use crate::time_util;
 use crate::trackdb_client::{self, PlatformRes, Position};
 
-use std::collections::BTreeMap;
-use std::fs::File;
+use std::collections::{BTreeMap, BTreeSet};
 use std::io::Write;
-use std::path::Path;
 use std::sync::{mpsc, Arc, Mutex};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 type PlatformId = String;
-type LastTsReported = u64;
-
-type ReportedMap = BTreeMap<PlatformId, LastTsReported>;
 
 pub struct Dispatcher {
-    poll_period: Duration,
     publisher: Publisher,
     platform_info: Arc<Mutex<PlatformInfo>>,
     dispatched_info: Arc<Mutex<DispatchedInfo>>,
+    poll_schedule: PollSchedule,
+    config_generation: u64,
+    last_history_prune: Option<Instant>,
+    last_platform_refresh: Option<Instant>,
+    /// The currently active platforms in the general dispatched list.
+    dispatched_ids: BTreeSet<PlatformId>,
+    /// The reference for the fix filter of each platform.
+    fix_references: BTreeMap<PlatformId, FixReference>,
+    /// The last dispatched fix of each platform, as reference for decimation.
+    last_dispatched: BTreeMap<PlatformId, Position>,
+    /// The geofences, as loaded at the start of each dispatch.
+    geofences: Vec<Geofence>,
+    geofence_monitor: GeofenceMonitor,
+    /// Alerts raised during the current dispatch, emitted at its end.
+    pending_alerts: Vec<Alert>,
+    /// The time of the latest accepted fix of each platform, for staleness detection.
+    latest_fix_ms: BTreeMap<PlatformId, u64>,
+    staleness_monitor: StalenessMonitor,
+    /// The latest two accepted fixes of each platform, kept across polls, for proximity
+    /// analysis.
+    recent_fixes: BTreeMap<PlatformId, Vec<Position>>,
+    proximity_monitor: ProximityMonitor,
+    /// If true, XEvents are only printed, and no state is saved.
+    dry_run: bool,
+    /// In dry-run mode, the reported timestamps, only kept in memory.
+    dry_run_reported: Option<ReportedMap>,
 }
 
-/// File that keeps track of last notified position timestamps.
-const REPORTED_PATH: &str = "./reported.json";
+/// How often to remove positions beyond the history retention period.
+const HISTORY_PRUNE_PERIOD: Duration = Duration::from_secs(60 * 60);
 
 impl Dispatcher {
     pub fn new(
@@ -35,23 +67,45 @@ impl Dispatcher {
     ) -> Self {
         let config = config::get_config();
         Self {
-            poll_period: config.poll_period,
             publisher: Publisher::new(post_xevent, config.tethysdashes.clone()),
             platform_info,
             dispatched_info,
+            poll_schedule: PollSchedule::default(),
+            config_generation: config::get_generation(),
+            last_history_prune: None,
+            last_platform_refresh: None,
+            dispatched_ids: BTreeSet::new(),
+            fix_references: BTreeMap::new(),
+            last_dispatched: BTreeMap::new(),
+            geofences: Vec::new(),
+            geofence_monitor: GeofenceMonitor::default(),
+            pending_alerts: Vec::new(),
+            latest_fix_ms: BTreeMap::new(),
+            staleness_monitor: StalenessMonitor::default(),
+            recent_fixes: BTreeMap::new(),
+            proximity_monitor: ProximityMonitor::default(),
+            dry_run: false,
+            dry_run_reported: None,
         }
     }
 
-    pub fn launch_dispatch(&self, done_receiver: Option<mpsc::Receiver<()>>) {
+    /// Makes the dispatcher run the full cycle, but only print the XEvents that would
+    /// be sent, without posting them or saving any state.
+    pub fn set_dry_run(&mut self) {
+        self.publisher.set_dry_run();
+        self.dry_run = true;
+    }
+
+    pub fn launch_dispatch(&mut self, done_receiver: Option<mpsc::Receiver<()>>) {
         println!(
-            "\nDispatcher is running  (polling every {} secs)",
-            self.poll_period.as_secs()
+            "\nDispatcher is running{}  (polling every {} secs by default)",
+            if self.dry_run { " (dry run)" } else { "" },
+            config::get_config().poll_period.as_secs()
         );
 
         // quickly react to done signal:
         let loop_sleep = Duration::from_millis(333);
 
-        let mut remaining_time_to_dispatch = Duration::from_secs(0);
         loop {
             if let Some(done_receiver) = &done_receiver {
                 match done_receiver.try_recv() {
@@ -68,113 +122,497 @@ impl Dispatcher {
                 }
             }
 
-            if remaining_time_to_dispatch.is_zero() {
-                let num_dispatched = self.dispatch_one();
+            self.apply_any_reloaded_config();
+            self.refresh_platforms_if_due();
+
+            if let Some(num_dispatched) = self.dispatch_due() {
+                self.print_dry_run_xevents();
+                let next_poll = self
+                    .poll_schedule
+                    .time_to_next_poll(Instant::now())
+                    .unwrap_or(config::get_config().poll_period);
                 println!(
                     "Dispatch done. {} positions dispatched.  Will poll again in {} secs",
                     num_dispatched,
-                    self.poll_period.as_secs()
+                    next_poll.as_secs()
                 );
-                remaining_time_to_dispatch = self.poll_period;
-            } else {
-                if remaining_time_to_dispatch >= loop_sleep {
-                    remaining_time_to_dispatch -= loop_sleep;
-                } else {
-                    remaining_time_to_dispatch = Duration::from_secs(0);
+            }
+            std::thread::sleep(loop_sleep);
+        }
+    }
+
+    /// Applies the current configuration if it has been reloaded since last applied.
+    fn apply_any_reloaded_config(&mut self) {
+        let generation = config::get_generation();
+        if generation != self.config_generation {
+            self.config_generation = generation;
+            let config = config::get_config();
+            self.publisher.set_tethysdashes(config.tethysdashes.clone());
+            // so any updated poll periods take effect right away:
+            self.poll_schedule = PollSchedule::default();
+            println!("\nDispatcher: applied reloaded configuration.");
+        }
+    }
+
+    /// Refreshes the full list of platforms from the Tracking DB if due, to then
+    /// determine the orphaned dispatched platforms, and prune any per the configuration.
+    fn refresh_platforms_if_due(&mut self) {
+        let config = config::get_config();
+        let now = Instant::now();
+        if self
+            .last_platform_refresh
+            .is_some_and(|last| now.duration_since(last) < config.pruning.refresh_period)
+        {
+            return;
+        }
+        self.last_platform_refresh = Some(now);
+
+        // Orphans are only determined for the sources successfully refreshed:
+        let platforms_by_source = trackdb_client::get_platforms_by_source();
+        let (refreshed, known_ids) = {
+            let mut platform_info = self.platform_info.lock().unwrap();
+            let refreshed = platform_info.apply_refresh(platforms_by_source);
+            let known_ids = refreshed
+                .iter()
+                .flat_map(|source| platform_info.get_source_platforms(source))
+                .map(|p| p._id)
+                .collect::<BTreeSet<_>>();
+            (refreshed, known_ids)
+        };
+        if refreshed.is_empty() {
+            log::warn!("Platform refresh failed for all sources; not checking for orphans");
+            return;
+        }
+        if self.dry_run {
+            log::debug!("Dry run; not checking for orphans");
+            return;
+        }
+        let checked =
+            |platform_id: &str| refreshed.contains(&trackdb_client::source_of(platform_id));
+        self.prune_orphans(&known_ids, &checked);
+    }
+
+    fn prune_orphans(&mut self, known_ids: &BTreeSet<PlatformId>, checked: &dyn Fn(&str) -> bool) {
+        let pruning = config::get_config().pruning.clone();
+        let now_ms = time_util::now_ms();
+        let mut dispatched_info = self.dispatched_info.lock().unwrap();
+        let orphans = dispatched_info.update_orphans(known_ids, checked, now_ms);
+        for (platform_id, since_ms) in orphans {
+            let orphaned_for = Duration::from_millis(now_ms.saturating_sub(since_ms));
+            if pruning.is_active() && orphaned_for >= pruning.grace_period {
+                if dispatched_info
+                    .delete_platform_id(&platform_id, &AuditContext::auto_prune())
+                    .is_some()
+                {
+                    println!(
+                        "Undispatched {platform_id}: missing from Tracking DB for {} secs",
+                        orphaned_for.as_secs()
+                    );
                 }
-                std::thread::sleep(loop_sleep);
+            } else if since_ms == now_ms {
+                let note = if pruning.is_active() {
+                    format!(
+                        "to be undispatched after {} secs",
+                        pruning.grace_period.as_secs()
+                    )
+                } else {
+                    "not to be undispatched (pruning disabled or dry run)".to_string()
+                };
+                println!(
+                    "Orphaned dispatched platform {platform_id}: missing from Tracking DB, {note}"
+                );
             }
         }
     }
 
-    pub fn launch_one_dispatch(&self) {
-        let num_dispatched = self.dispatch_one();
+    pub fn launch_one_dispatch(&mut self) {
+        let platform_ids = self.get_platform_ids_to_dispatch();
+        let num_dispatched = self.dispatch_platforms(&platform_ids);
+        self.update_staleness(&platform_ids);
+        self.update_proximity(&platform_ids);
+        self.print_dry_run_xevents();
         println!("Dispatch done. {} positions dispatched.", num_dispatched);
     }
 
-    fn dispatch_one(&self) -> usize {
-        let platforms = self.get_dispatched_platforms();
+    /// In dry-run mode, prints the XEvents that would have been sent, per TethysDash.
+    fn print_dry_run_xevents(&self) {
+        if !self.dry_run {
+            return;
+        }
+        let xevents = self.publisher.take_dry_run_xevents();
+        println!("Dry run: nothing posted or saved. XEvents that would be sent:");
+        if xevents.is_empty() {
+            println!("  (none)");
+        }
+        for (name, xevents) in &xevents {
+            println!("  TethysDash '{name}': {} XEvents", xevents.len());
+            for xevent in xevents {
+                println!("    {}", serde_json::to_string(xevent).unwrap());
+            }
+        }
+    }
+
+    /// Dispatches the platforms that are due per the poll schedule, if any,
+    /// returning the number of dispatched positions.
+    fn dispatch_due(&mut self) -> Option<usize> {
+        let platform_ids = self.get_platform_ids_to_dispatch();
+        self.poll_schedule.retain(&platform_ids);
+
+        let now = Instant::now();
+        let due_ids = platform_ids
+            .iter()
+            .filter(|id| self.poll_schedule.is_due(id, now))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        if due_ids.is_empty() {
+            None
+        } else {
+            let num_dispatched = self.dispatch_platforms(&due_ids);
+            self.update_staleness(&platform_ids);
+            self.update_proximity(&platform_ids);
+            Some(num_dispatched)
+        }
+    }
+
+    /// Determines which of the given platforms are stale, per the time of their latest
+    /// fix, raising any stale/recovered alerts if so configured, and saving the status.
+    fn update_staleness(&mut self, platform_ids: &[PlatformId]) {
+        let platforms = {
+            let platform_info = self.platform_info.lock().unwrap();
+            platform_ids
+                .iter()
+                .filter_map(|id| platform_info.get_platform(id))
+                .map(|p| {
+                    let latest_fix_ms = self.latest_fix_ms.get(&p._id).copied();
+                    (p, latest_fix_ms)
+                })
+                .collect::<Vec<_>>()
+        };
+        let config = config::get_config();
+        let (status, alerts) =
+            self.staleness_monitor
+                .evaluate(&config.staleness, &platforms, time_util::now_ms());
+        metrics::gauge!(STALE_PLATFORMS).set(status.num_stale() as f64);
+        if self.dry_run {
+            for alert in &alerts {
+                println!("Dry run: alert not raised: {}", alert.message);
+            }
+            return;
+        }
+        if config.staleness.notify && !alerts.is_empty() {
+            alerts::emit(&alerts);
+        } else {
+            for alert in &alerts {
+                println!("{}", alert.message);
+            }
+        }
+        staleness::save_status(&status);
+    }
+
+    /// Determines the proximity of the pairs of the given platforms selected by the
+    /// configured rules, if any, raising any alerts, and saving the report.
+    fn update_proximity(&mut self, platform_ids: &[PlatformId]) {
+        let config = config::get_config();
+        if config.proximity.is_empty() {
+            return;
+        }
+        let platforms = {
+            let platform_info = self.platform_info.lock().unwrap();
+            platform_ids
+                .iter()
+                .filter_map(|id| platform_info.get_platform(id))
+                .map(|p| {
+                    let fixes = self.recent_fixes.get(&p._id).cloned().unwrap_or_default();
+                    (p, fixes)
+                })
+                .collect::<Vec<_>>()
+        };
+        let (report, alerts) =
+            self.proximity_monitor
+                .evaluate(&config.proximity, &platforms, time_util::now_ms());
+        if self.dry_run {
+            for alert in &alerts {
+                println!("Dry run: alert not raised: {}", alert.message);
+            }
+            return;
+        }
+        if !alerts.is_empty() {
+            alerts::emit(&alerts);
+        }
+        proximity::save_report(&report);
+    }
+
+    /// Removes any expired platforms, and returns the IDs of those to be dispatched now,
+    /// that is, the active dispatched platforms plus any in the dispatch lists of the
+    /// TethysDash instances.
+    fn get_platform_ids_to_dispatch(&mut self) -> Vec<PlatformId> {
+        let now_ms = time_util::now_ms();
+        let mut dispatched_info = self.dispatched_info.lock().unwrap();
+        // (expired platforms are not active anyway, so only removed if not dry run)
+        if !self.dry_run {
+            for platform_id in dispatched_info.remove_expired(now_ms) {
+                println!("Undispatched {platform_id}: dispatch expired");
+            }
+        }
+        self.dispatched_ids = dispatched_info
+            .get_active_platform_ids(now_ms)
+            .into_iter()
+            .collect();
+        let mut platform_ids = self.dispatched_ids.clone();
+        platform_ids.extend(config::get_config().routing_dispatch_ids());
+        platform_ids.into_iter().collect()
+    }
+
+    fn dispatch_platforms(&mut self, platform_ids: &[PlatformId]) -> usize {
+        let platforms = self.get_platforms(platform_ids);
 
         println!(
             "\nDispatching any new positions for {} platforms",
             platforms.len()
         );
 
+        self.geofences = geofence::get_geofences();
+
         let mut num_dispatched = 0;
-        let mut reported_map = load_reported();
+        let mut reported_map = self.dry_run_reported.take().unwrap_or_else(load_reported);
         for platform in &platforms {
             num_dispatched += self.dispatch_platform(&mut reported_map, platform);
         }
 
-        save_reported(&reported_map);
+        let alerts = std::mem::take(&mut self.pending_alerts);
+        if self.dry_run {
+            self.dry_run_reported = Some(reported_map);
+            for alert in &alerts {
+                println!("Dry run: alert not raised: {}", alert.message);
+            }
+        } else {
+            save_reported(&reported_map);
+            if !alerts.is_empty() {
+                alerts::emit(&alerts);
+            }
+            self.geofence_monitor.save(&self.geofences);
+            self.prune_history_if_due();
+        }
         num_dispatched
     }
 
-    fn get_dispatched_platforms(&self) -> Vec<PlatformRes> {
-        let dispatched_info = self.dispatched_info.lock().unwrap();
+    fn prune_history_if_due(&mut self) {
+        let Some(retention) = config::get_config().history.retention else {
+            return;
+        };
+        let Some(history) = History::from_config() else {
+            return;
+        };
+        let now = Instant::now();
+        if self
+            .last_history_prune
+            .is_some_and(|last| now.duration_since(last) < HISTORY_PRUNE_PERIOD)
+        {
+            return;
+        }
+        self.last_history_prune = Some(now);
+        let older_than_ms = time_util::now_ms().saturating_sub(retention.as_millis() as u64);
+        match history.prune(older_than_ms) {
+            Ok(0) => {}
+            Ok(num_removed) => println!("Removed {num_removed} positions from history"),
+            Err(e) => log::error!("Failed to prune history: {e}"),
+        }
+    }
+
+    fn get_platforms(&mut self, platform_ids: &[PlatformId]) -> Vec<PlatformRes> {
         let platform_info = self.platform_info.lock().unwrap();
-        dispatched_info
-            .get_platform_ids()
-            .iter()
-            .fold(vec![], |mut acc, id| {
-                match platform_info.get_platform(id) {
-                    Some(platform) => {
-                        acc.push(platform);
-                    }
-                    _ => {
-                        eprintln!("No platform by id: {id}");
-                    }
+        let now = Instant::now();
+        platform_ids.iter().fold(vec![], |mut acc, id| {
+            match platform_info.get_platform(id) {
+                Some(platform) => {
+                    acc.push(platform);
                 }
-                acc
-            })
+                _ => {
+                    eprintln!("No platform by id: {id}");
+                    // check again later per the general poll period:
+                    self.poll_schedule
+                        .schedule(id, now, config::get_config().poll_period);
+                }
+            }
+            acc
+        })
     }
 
-    fn dispatch_platform(&self, reported_map: &mut ReportedMap, platform: &PlatformRes) -> usize {
+    fn dispatch_platform(
+        &mut self,
+        reported_map: &mut ReportedMap,
+        platform: &PlatformRes,
+    ) -> usize {
         let pos_res = trackdb_client::get_positions_per_config(&platform._id);
-        if let Some(pos_res) = pos_res {
-            self.report_positions(reported_map, platform, pos_res.positions)
-        } else {
-            0
-        }
+        let positions = pos_res.map(|p| p.positions).unwrap_or_default();
+        let config = config::get_config();
+        let dispatched_period = self
+            .dispatched_info
+            .lock()
+            .unwrap()
+            .get_poll_period(&platform._id);
+        let period = poll_period_for(&config, dispatched_period, platform, &positions);
+        log::debug!(
+            "{} ({}): next poll in {} secs",
+            platform.name,
+            platform._id,
+            period.as_secs()
+        );
+        self.poll_schedule
+            .schedule(&platform._id, Instant::now(), period);
+
+        self.report_positions(reported_map, platform, positions)
     }
 
     fn report_positions(
-        &self,
+        &mut self,
         reported_map: &mut ReportedMap,
         platform: &PlatformRes,
         positions: Vec<Position>,
     ) -> usize {
-        let last_ts_reported = reported_map.get(&platform._id).unwrap_or(&0);
+        let last_ts_reported = *reported_map.get(&platform._id).unwrap_or(&0);
+
+        // Fallback reference for the fix filter and decimation:
+        let latest_reported = positions
+            .iter()
+            .filter(|p| p.time_ms <= last_ts_reported)
+            .max_by_key(|p| p.time_ms)
+            .cloned();
 
         let mut new_to_report = positions
             .into_iter()
-            .filter(|p| p.time_ms > *last_ts_reported)
+            .filter(|p| p.time_ms > last_ts_reported)
             .collect::<Vec<Position>>();
+        new_to_report.sort_by_key(|a| a.time_ms);
+
+        let config = config::get_config();
+        let reference = self
+            .fix_references
+            .entry(platform._id.clone())
+            .or_insert_with(|| FixReference::new(latest_reported.clone()));
+        if reference.last.is_none() {
+            reference.last = latest_reported.clone();
+        }
+        let previous = reference.last.clone();
+        let (mut new_to_report, rejected) = fix_filter::filter_fixes(
+            &config.fix_filter,
+            platform.type_name.as_deref(),
+            reference,
+            new_to_report,
+            time_util::now_ms(),
+        );
+        for (position, rejection) in &rejected {
+            println!(
+                "    {} ({}): rejected fix at {} ({}, {}): {rejection}",
+                platform.name, platform._id, position.time_ms, position.lat, position.lon
+            );
+            metrics::counter!(REJECTED_FIXES, "reason" => rejection.reason()).increment(1);
+        }
+        // for staleness and proximity, only per the fixes passing the filter:
+        if let Some(last) = new_to_report.last() {
+            let latest_fix_ms = self.latest_fix_ms.entry(platform._id.clone()).or_default();
+            *latest_fix_ms = (*latest_fix_ms).max(last.time_ms);
+            let recent = self.recent_fixes.entry(platform._id.clone()).or_default();
+            recent.extend(new_to_report.iter().cloned());
+            recent.drain(..recent.len().saturating_sub(2));
+        }
+        let alerts = self
+            .geofence_monitor
+            .evaluate(&self.geofences, platform, &new_to_report);
+        self.pending_alerts.extend(alerts);
+        // for the XEvents, so only relative to the previous fix:
+        trackdb_client::add_kinematics(previous.as_ref(), &mut new_to_report);
+        for kinematics in new_to_report.iter_mut().flat_map(|p| &mut p.kinematics) {
+            kinematics.cumulative_distance = None;
+        }
+
+        let (new_to_report, skipped) = decimation::decimate(
+            &config
+                .decimation
+                .rule_for(&platform._id, platform.type_name.as_deref()),
+            self.last_dispatched
+                .get(&platform._id)
+                .cloned()
+                .or(latest_reported),
+            new_to_report,
+        );
+        if !skipped.is_empty() {
+            log::debug!(
+                "{} ({}): {} fixes skipped per decimation",
+                platform.name,
+                platform._id,
+                skipped.len()
+            );
+        }
+        if let Some(last) = new_to_report.last() {
+            self.last_dispatched
+                .insert(platform._id.clone(), last.clone());
+        }
+
+        let seen_ms = time_util::now_ms();
+        let history_entry = |position: &Position, sent_to, not_dispatched| HistoryEntry {
+            time_ms: position.time_ms,
+            lat: position.lat,
+            lon: position.lon,
+            seen_ms,
+            sent_to,
+            not_dispatched,
+        };
+        // also archiving the fixes not dispatched, except those that may be accepted later:
+        let mut history_entries = rejected
+            .iter()
+            .filter(|(_, rejection)| !rejection.is_transient())
+            .map(|(position, rejection)| {
+                history_entry(position, vec![], Some(rejection.reason().to_string()))
+            })
+            .chain(
+                skipped
+                    .iter()
+                    .map(|position| history_entry(position, vec![], Some("decimated".to_string()))),
+            )
+            .collect::<Vec<_>>();
 
         if !new_to_report.is_empty() {
-            new_to_report.sort_by_key(|a| a.time_ms);
             print!("    {} ({}): new positions ", platform.name, platform._id);
             flush_stdout();
 
             for position in &new_to_report {
                 print!(".");
                 flush_stdout();
-                self.report_position(reported_map, platform, position);
+                let sent_to = self.report_position(reported_map, platform, position);
 
                 let new_last_ts_reported = position.time_ms;
                 reported_map.insert(platform._id.clone(), new_last_ts_reported);
+
+                history_entries.push(history_entry(position, sent_to, None));
             }
             println!();
         }
+        if !history_entries.is_empty() {
+            if let Some(history) = History::from_config().filter(|_| !self.dry_run) {
+                if let Err(e) = history.append(&platform._id, &history_entries) {
+                    log::error!("Failed to save history for {}: {e}", platform._id);
+                }
+            }
+        }
+        // so these are not checked again, except those that may be accepted later:
+        let not_to_report = rejected
+            .iter()
+            .filter(|(_, rejection)| !rejection.is_transient())
+            .map(|(position, _)| position)
+            .chain(&skipped);
+        mark_as_reported(reported_map, &platform._id, not_to_report);
         new_to_report.len()
     }
 
+    /// Returns the names of the TethysDash instances the position was sent to.
     fn report_position(
         &self,
         reported_map: &mut ReportedMap,
         platform: &PlatformRes,
         position: &Position,
-    ) {
+    ) -> Vec<String> {
         //println!("report_position {}", position);
         let xevent = XEvent {
             asset_id: platform._id.clone(),
@@ -184,29 +622,47 @@ impl Dispatcher {
             color: platform.color.clone(),
             icon_url: platform.icon_url.clone(),
         };
-        if self.publisher.publish_xevent(xevent).is_ok() {
-            let new_last_ts_reported = position.time_ms;
-            reported_map.insert(platform._id.clone(), new_last_ts_reported);
+        let dispatched = self.dispatched_ids.contains(&platform._id);
+        match self.publisher.publish_xevent(xevent, dispatched) {
+            Ok(sent_to) => {
+                let new_last_ts_reported = position.time_ms;
+                reported_map.insert(platform._id.clone(), new_last_ts_reported);
+                sent_to
+            }
+            Err(_) => Vec::new(),
         }
     }
 }
 
+/// Updates the last reported timestamp of the platform, as needed, to include
+/// the given positions, which are not actually reported.
+fn mark_as_reported<'a>(
+    reported_map: &mut ReportedMap,
+    platform_id: &str,
+    positions: impl Iterator<Item = &'a Position>,
+) {
+    if let Some(time_ms) = positions.map(|p| p.time_ms).max() {
+        let last_reported = reported_map.entry(platform_id.to_string()).or_insert(0);
+        *last_reported = (*last_reported).max(time_ms);
+    }
+}
+
 fn load_reported() -> ReportedMap {
-    let reported_map = if Path::new(REPORTED_PATH).exists() {
-        let s = std::fs::read_to_string(REPORTED_PATH).unwrap();
-        serde_json::from_str(&s).unwrap()
-    } else {
-        let new_reported = ReportedMap::new();
-        save_reported(&new_reported);
-        new_reported
-    };
+    let reported_map = state_store::get_store()
+        .load_reported()
+        .unwrap_or_else(|e| {
+            log::error!("Failed to load reported timestamps: {e}");
+            ReportedMap::new()
+        });
     log::debug!("{}", serde_json::to_string_pretty(&reported_map).unwrap());
     reported_map
 }
 
 fn save_reported(reported_map: &ReportedMap) {
-    let f = File::create(REPORTED_PATH).unwrap();
-    serde_json::to_writer_pretty(f, reported_map).unwrap();
+    if let Err(e) = state_store::get_store().save_reported(reported_map) {
+        log::error!("Failed to save reported timestamps: {e}");
+        eprintln!("error: failed to save reported timestamps: {e}");
+    }
 }
 
 fn flush_stdout() {
@@ -218,3 +674,223 @@ fn flush<W: Write>(writer: &mut W) {
         log::warn!("Failed to flush output: {}", e);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit;
+    use crate::config::init_test_config;
+    use crate::dispatched_info::DispatchDetails;
+    use crate::mock::tethysdash::test_tethysdash;
+    use crate::tethysdash_client;
+    use pretty_assertions::assert_eq;
+
+    /// From the mock ODSS through the dispatcher to the mock TethysDash.
+    #[test]
+    fn test_dispatch_end_to_end() {
+        init_test_config();
+        let (_, mock) = test_tethysdash();
+        let platform_id = "54065b5560d0e168c88d4043";
+        let received_times = || {
+            mock.get_received()
+                .into_iter()
+                .filter(|r| r.xevent.asset_id == platform_id)
+                .map(|r| r.xevent.position.time_ms)
+                .collect::<Vec<_>>()
+        };
+
+        let platform_info = Arc::new(Mutex::new(PlatformInfo::default()));
+        platform_info.lock().unwrap().refresh();
+        let dispatched_info = Arc::new(Mutex::new(DispatchedInfo::new()));
+        dispatched_info.lock().unwrap().add_platform_ids(
+            vec![platform_id.to_string()],
+            DispatchDetails::default(),
+            &AuditContext::cli(),
+        );
+        let mut dispatcher = Dispatcher::new(
+            tethysdash_client::post_xevent,
+            platform_info,
+            dispatched_info,
+        );
+
+        // the last 2 fixes (per defaultLastNumberOfFixes), in chronological order:
+        dispatcher.launch_one_dispatch();
+        assert_eq!(received_times(), vec![1700000200000, 1700000300000]);
+        assert_eq!(
+            dispatcher.latest_fix_ms.get(platform_id),
+            Some(&1700000300000)
+        );
+
+        // nothing new to report:
+        dispatcher.launch_one_dispatch();
+        assert_eq!(received_times(), vec![1700000200000, 1700000300000]);
+
+        let history = History::from_config().unwrap();
+        let entries = history
+            .get_entries(platform_id, None, None, None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].sent_to, vec!["mock"]);
+    }
+
+    /// Fixes rejected by the fix filter are also archived, though not sent.
+    #[test]
+    fn test_history_of_rejected_fixes() {
+        init_test_config();
+        let _ = test_tethysdash();
+        let platform = PlatformRes {
+            _id: "history-rejected-test".to_string(),
+            name: "history-rejected-test".to_string(),
+            abbreviation: "hrt".to_string(),
+            type_name: None,
+            color: None,
+            icon_url: None,
+        };
+        let fix = |time_ms: u64, lat: f64, lon: f64| Position {
+            time_ms,
+            lat,
+            lon,
+            kinematics: None,
+        };
+        let mut dispatcher = Dispatcher::new(
+            tethysdash_client::post_xevent,
+            Arc::new(Mutex::new(PlatformInfo::default())),
+            Arc::new(Mutex::new(DispatchedInfo::new())),
+        );
+        dispatcher.dispatched_ids.insert(platform._id.clone());
+        let mut reported_map = ReportedMap::new();
+        let positions = vec![
+            fix(1700000100000, 36.8, -122.0),
+            fix(1700000200000, 0.0, 0.0),
+        ];
+        assert_eq!(
+            dispatcher.report_positions(&mut reported_map, &platform, positions),
+            1
+        );
+
+        let entries = History::from_config()
+            .unwrap()
+            .get_entries(&platform._id, None, None, None)
+            .unwrap()
+            .unwrap()
+            .into_iter()
+            .map(|e| (e.time_ms, e.sent_to, e.not_dispatched))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            vec![
+                (1700000100000, vec!["mock".to_string()], None),
+                (1700000200000, vec![], Some("null_island".to_string())),
+            ]
+        );
+    }
+
+    /// Orphaned platforms per the platforms from the mock ODSS, undispatched after
+    /// the grace period.
+    #[test]
+    fn test_prune_orphans() {
+        init_test_config();
+        let store_dir =
+            std::env::temp_dir().join(format!("odss2dash-pruning-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&store_dir);
+        std::fs::create_dir_all(&store_dir).unwrap();
+        let store = Arc::new(state_store::JsonStore::new(&store_dir));
+        let dispatched_info = Arc::new(Mutex::new(DispatchedInfo::with_store(store)));
+        let known_id = "54065b5560d0e168c88d4043";
+        let add = |platform_id: &str| {
+            dispatched_info.lock().unwrap().add_platform_ids(
+                vec![platform_id.to_string()],
+                DispatchDetails::default(),
+                &AuditContext::cli(),
+            );
+        };
+
+        // missing from the Tracking DB since beyond the grace period:
+        add(known_id);
+        add("pruning-test-old");
+        let grace_ms = config::get_config().pruning.grace_period.as_millis() as u64;
+        let long_ago_ms = time_util::now_ms() - grace_ms - 60_000;
+        let known_ids = BTreeSet::from([known_id.to_string()]);
+        dispatched_info
+            .lock()
+            .unwrap()
+            .update_orphans(&known_ids, &|_| true, long_ago_ms);
+        // just went missing:
+        add("pruning-test-new");
+
+        let mut dispatcher = Dispatcher::new(
+            tethysdash_client::post_xevent,
+            Arc::new(Mutex::new(PlatformInfo::default())),
+            dispatched_info.clone(),
+        );
+        let before_ms = time_util::now_ms();
+        dispatcher.refresh_platforms_if_due();
+
+        let dispatched_info = dispatched_info.lock().unwrap();
+        assert_eq!(
+            dispatched_info.get_platform_ids(),
+            vec![known_id.to_string(), "pruning-test-new".to_string()]
+        );
+        let orphans = dispatched_info.get_orphans();
+        assert_eq!(orphans.keys().collect::<Vec<_>>(), vec!["pruning-test-new"]);
+        assert!(orphans["pruning-test-new"] >= before_ms);
+        let removal = audit::get_entries(Some("pruning-test-old"), Some(before_ms), None)
+            .unwrap()
+            .pop()
+            .unwrap();
+        assert_eq!(removal.source, audit::AuditSource::AutoPrune);
+
+        // not refreshed again until due:
+        drop(dispatched_info);
+        let last_refresh = dispatcher.last_platform_refresh;
+        dispatcher.refresh_platforms_if_due();
+        assert_eq!(dispatcher.last_platform_refresh, last_refresh);
+
+        std::fs::remove_dir_all(&store_dir).unwrap();
+    }
+
+    /// The full cycle, but with nothing posted or saved.
+    #[test]
+    fn test_dispatch_dry_run() {
+        init_test_config();
+        let (_, mock) = test_tethysdash();
+        let platform_id = "5d5b2ea653a65f9ec656d872";
+
+        let platform_info = Arc::new(Mutex::new(PlatformInfo::default()));
+        platform_info.lock().unwrap().refresh();
+        let dispatched_info = Arc::new(Mutex::new(DispatchedInfo::new()));
+        dispatched_info.lock().unwrap().add_platform_ids(
+            vec![platform_id.to_string()],
+            DispatchDetails::default(),
+            &AuditContext::cli(),
+        );
+        let mut dispatcher = Dispatcher::new(
+            tethysdash_client::post_xevent,
+            platform_info,
+            dispatched_info,
+        );
+        dispatcher.set_dry_run();
+
+        assert!(dispatcher
+            .get_platform_ids_to_dispatch()
+            .contains(&platform_id.to_string()));
+        let platform_ids = vec![platform_id.to_string()];
+        assert_eq!(dispatcher.dispatch_platforms(&platform_ids), 2);
+        let xevents = dispatcher.publisher.take_dry_run_xevents();
+        assert_eq!(xevents.keys().collect::<Vec<_>>(), vec!["mock"]);
+        assert_eq!(xevents["mock"].len(), 2);
+
+        // already "reported" in memory:
+        assert_eq!(dispatcher.dispatch_platforms(&platform_ids), 0);
+
+        assert!(mock
+            .get_received()
+            .iter()
+            .all(|r| r.xevent.asset_id != platform_id));
+        assert_eq!(load_reported().get(platform_id), None);
+        let history = History::from_config().unwrap();
+        let entries = history.get_entries(platform_id, None, None, None).unwrap();
+        assert!(entries.is_none_or(|e| e.is_empty()));
+    }
+}
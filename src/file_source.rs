@@ -0,0 +1,255 @@
+//! Offline Tracking DB source backed by local files, e.g., recorded data for demos at sea
+//! or for testing without network. The directory is expected to contain:
+//!
+//! - `platforms.json`: the platforms, as in the ODSS `/platforms` response. If missing,
+//!   one platform per track file is assumed, with the file stem as ID and name.
+//! - `tracks/<platform_id>.{json,csv,gpx}`: the positions of each platform, either as in the
+//!   ODSS `/tracks` response (JSON), as CSV with a header including `time`, `lat`, and `lon`
+//!   columns (time as epoch milliseconds or ISO 8601 UTC), or as GPX track or waypoints.
+
+use crate::time_util;
+use crate::trackdb_client::{PlatformRes, Position, TrackRes};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const TRACK_EXTENSIONS: [&str; 3] = ["json", "csv", "gpx"];
+
+pub fn get_platforms(dir: &Path) -> Result<Vec<PlatformRes>, String> {
+    let path = dir.join("platforms.json");
+    if path.exists() {
+        let contents = read_file(&path)?;
+        return serde_json::from_str(&contents)
+            .map_err(|e| format!("'{}': invalid platforms: {e}", path.display()));
+    }
+    let tracks_dir = dir.join("tracks");
+    let entries =
+        fs::read_dir(&tracks_dir).map_err(|e| format!("'{}': {e}", tracks_dir.display()))?;
+    let mut ids = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let extension = path.extension()?.to_str()?;
+            if TRACK_EXTENSIONS.contains(&extension) {
+                Some(path.file_stem()?.to_str()?.to_string())
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+    ids.sort();
+    ids.dedup();
+    Ok(ids
+        .into_iter()
+        .map(|id| PlatformRes {
+            _id: id.clone(),
+            name: id.clone(),
+            abbreviation: id,
+            type_name: None,
+            color: None,
+            icon_url: None,
+        })
+        .collect())
+}
+
+pub fn get_platform(dir: &Path, platform_id: &str) -> Result<Option<PlatformRes>, String> {
+    let platforms = get_platforms(dir)?;
+    Ok(platforms.into_iter().find(|p| p._id == platform_id))
+}
+
+/// Gets the positions of the platform, most recent first, in the given time range,
+/// or else the last number of fixes, as the ODSS would.
+pub fn get_positions(
+    dir: &Path,
+    platform_id: &str,
+    last_number_of_fixes: u32,
+    start_date: Option<&str>,
+    end_date: Option<&str>,
+) -> Result<Vec<Position>, String> {
+    let Some(path) = track_path(dir, platform_id)? else {
+        return Ok(Vec::new());
+    };
+    let contents = read_file(&path)?;
+    let mut positions = match path.extension().and_then(|e| e.to_str()) {
+        Some("csv") => parse_csv(&contents),
+        Some("gpx") => parse_gpx(&contents),
+        _ => serde_json::from_str::<TrackRes>(&contents)
+            .map(|track_res| track_res.positions())
+            .map_err(|e| e.to_string()),
+    }
+    .map_err(|e| format!("'{}': {e}", path.display()))?;
+    positions.sort_by_key(|p| std::cmp::Reverse(p.time_ms));
+
+    if start_date.is_some() || end_date.is_some() {
+        let start_ms = start_date.map(time_util::parse_time_ms).transpose()?;
+        let end_ms = end_date.map(time_util::parse_time_ms).transpose()?;
+        positions.retain(|p| {
+            start_ms.is_none_or(|s| p.time_ms >= s) && end_ms.is_none_or(|e| p.time_ms <= e)
+        });
+    } else {
+        positions.truncate(last_number_of_fixes as usize);
+    }
+    Ok(positions)
+}
+
+/// The track file of the platform, if any. The platform ID, as given by a client,
+/// must be a plain file name, so no file outside the `tracks` directory can be read.
+fn track_path(dir: &Path, platform_id: &str) -> Result<Option<PathBuf>, String> {
+    if platform_id.is_empty() || platform_id.contains(['/', '\\']) || platform_id.contains("..") {
+        return Err(format!("invalid platform ID: '{platform_id}'"));
+    }
+    Ok(TRACK_EXTENSIONS
+        .iter()
+        .map(|ext| dir.join("tracks").join(format!("{platform_id}.{ext}")))
+        .find(|path| path.exists()))
+}
+
+fn read_file(path: &Path) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|e| format!("'{}': {e}", path.display()))
+}
+
+/// Parses simple CSV (no quoted fields) with a header including `time`, `lat`,
+/// and `lon` columns, in any order.
+fn parse_csv(contents: &str) -> Result<Vec<Position>, String> {
+    let mut lines = contents.lines().filter(|line| !line.trim().is_empty());
+    let header = lines.next().ok_or("missing header")?;
+    let columns = header
+        .split(',')
+        .map(|c| c.trim().to_lowercase())
+        .collect::<Vec<_>>();
+    let column = |name: &str| {
+        columns
+            .iter()
+            .position(|c| c == name)
+            .ok_or_else(|| format!("missing column '{name}'"))
+    };
+    let (time_col, lat_col, lon_col) = (column("time")?, column("lat")?, column("lon")?);
+
+    lines
+        .enumerate()
+        .map(|(index, line)| {
+            let fields = line.split(',').map(str::trim).collect::<Vec<_>>();
+            let field = |col: usize| {
+                fields
+                    .get(col)
+                    .copied()
+                    .ok_or_else(|| format!("line {}: missing field", index + 2))
+            };
+            let coordinate = |col: usize| {
+                field(col)?
+                    .parse::<f64>()
+                    .map_err(|e| format!("line {}: {e}", index + 2))
+            };
+            Ok(Position {
+                time_ms: time_util::parse_time_ms(field(time_col)?)
+                    .map_err(|e| format!("line {}: {e}", index + 2))?,
+                lat: coordinate(lat_col)?,
+                lon: coordinate(lon_col)?,
+                kinematics: None,
+            })
+        })
+        .collect()
+}
+
+/// Parses the timed track points and waypoints of a GPX document.
+fn parse_gpx(contents: &str) -> Result<Vec<Position>, String> {
+    let doc = roxmltree::Document::parse(contents).map_err(|e| e.to_string())?;
+    let points = doc
+        .descendants()
+        .filter(|n| n.has_tag_name("trkpt") || n.has_tag_name("wpt"));
+    let mut positions = Vec::new();
+    for point in points {
+        let coordinate = |name: &str| {
+            point
+                .attribute(name)
+                .ok_or_else(|| format!("point without {name}"))?
+                .parse::<f64>()
+                .map_err(|e| e.to_string())
+        };
+        let time = point
+            .children()
+            .find(|n| n.has_tag_name("time"))
+            .and_then(|n| n.text());
+        // points without time cannot be dispatched:
+        if let Some(time) = time {
+            positions.push(Position {
+                time_ms: time_util::parse_time_ms(time)?,
+                lat: coordinate("lat")?,
+                lon: coordinate("lon")?,
+                kinematics: None,
+            });
+        }
+    }
+    Ok(positions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn lat_lons(positions: &[Position]) -> Vec<(u64, f64, f64)> {
+        positions
+            .iter()
+            .map(|p| (p.time_ms, p.lat, p.lon))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_track_files() {
+        let csv = "lon, lat, time\n-122.0, 36.8, 1700000000000\n-122.1,36.9,2023-11-14T22:13:30Z\n";
+        assert_eq!(
+            lat_lons(&parse_csv(csv).unwrap()),
+            vec![
+                (1_700_000_000_000, 36.8, -122.0),
+                (1_700_000_010_000, 36.9, -122.1)
+            ]
+        );
+        assert!(parse_csv("time,lat\n1,2\n").is_err());
+
+        let gpx = r#"<?xml version="1.0"?>
+            <gpx version="1.1" xmlns="http://www.topografix.com/GPX/1/1">
+              <trk><trkseg>
+                <trkpt lat="36.8" lon="-122.0"><time>2023-11-14T22:13:20Z</time></trkpt>
+                <trkpt lat="36.9" lon="-122.1"><ele>0</ele></trkpt>
+              </trkseg></trk>
+            </gpx>"#;
+        assert_eq!(
+            lat_lons(&parse_gpx(gpx).unwrap()),
+            vec![(1_700_000_000_000, 36.8, -122.0)]
+        );
+    }
+
+    #[test]
+    fn test_get_positions() {
+        let dir = std::env::temp_dir().join(format!("odss2dash-files-{}", std::process::id()));
+        fs::create_dir_all(dir.join("tracks")).unwrap();
+        let csv = "time,lat,lon\n1000,36.1,-122.1\n3000,36.3,-122.3\n2000,36.2,-122.2\n";
+        fs::write(dir.join("tracks/p1.csv"), csv).unwrap();
+
+        let platforms = get_platforms(&dir).unwrap();
+        assert_eq!(platforms.len(), 1);
+        assert_eq!(platforms[0]._id, "p1");
+
+        let positions = get_positions(&dir, "p1", 2, None, None).unwrap();
+        assert_eq!(
+            positions.iter().map(|p| p.time_ms).collect::<Vec<_>>(),
+            vec![3000, 2000]
+        );
+        let positions = get_positions(&dir, "p1", 2, Some("1500"), None).unwrap();
+        assert_eq!(
+            positions.iter().map(|p| p.time_ms).collect::<Vec<_>>(),
+            vec![3000, 2000]
+        );
+        assert!(get_positions(&dir, "other", 2, None, None)
+            .unwrap()
+            .is_empty());
+
+        // no access outside the tracks directory:
+        fs::write(dir.join("secret.csv"), csv).unwrap();
+        for platform_id in ["../secret", "..\\secret", "/etc/passwd", ""] {
+            assert!(get_positions(&dir, platform_id, 2, None, None).is_err());
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,350 @@
+//! Rejection of bad fixes from the Tracking DB before dispatch: invalid positions,
+//! null island (0, 0), fixes from the future, duplicates, and those implying an
+//! unrealistic speed from the previous fix. A platform that really relocated (e.g.,
+//! a mooring redeployed, or a gap in its track) gets its new track accepted after
+//! a few consecutive fixes consistent with each other, or once the previous fix is
+//! too old to serve as reference.
+
+use crate::geo;
+use crate::trackdb_client::Position;
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FixFilterConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Whether to reject fixes at (0, 0).
+    #[serde(default = "default_true")]
+    pub reject_null_island: bool,
+    /// How far in the future (per our clock) a fix may be, to allow for clock skew.
+    #[serde(default = "default_max_future", with = "humantime_serde")]
+    pub max_future: Duration,
+    /// Maximum speed in meters per second implied from the previous fix.
+    /// Default: no speed check.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_speed: Option<f64>,
+    /// Maximum speed by platform type name, overriding `max_speed`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub max_speed_by_type: BTreeMap<String, f64>,
+    /// Number of consecutive fixes, too fast from the previous accepted fix but not
+    /// from each other, upon which the last of them is accepted as the new track.
+    /// 0: never.
+    #[serde(default = "default_relocation_fixes")]
+    pub relocation_fixes: u32,
+    /// The previous accepted fix is not used for the speed check if older than
+    /// this relative to the checked fix. Default: no limit.
+    #[serde(
+        default,
+        with = "humantime_serde",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max_reference_age: Option<Duration>,
+}
+
+impl Default for FixFilterConfig {
+    fn default() -> Self {
+        FixFilterConfig {
+            enabled: true,
+            reject_null_island: true,
+            max_future: default_max_future(),
+            max_speed: None,
+            max_speed_by_type: BTreeMap::new(),
+            relocation_fixes: default_relocation_fixes(),
+            max_reference_age: None,
+        }
+    }
+}
+
+fn default_relocation_fixes() -> u32 {
+    3
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_max_future() -> Duration {
+    Duration::from_secs(5 * 60)
+}
+
+impl FixFilterConfig {
+    /// Checks the settings, returning any problems found.
+    pub fn validate(&self) -> Vec<String> {
+        let speeds = self
+            .max_speed
+            .iter()
+            .map(|s| ("maxSpeed".to_string(), s))
+            .chain(
+                self.max_speed_by_type
+                    .iter()
+                    .map(|(t, s)| (format!("maxSpeedByType['{t}']"), s)),
+            );
+        let mut problems = speeds
+            .filter(|(_, speed)| speed.is_nan() || **speed <= 0.0)
+            .map(|(setting, _)| format!("fixFilter.{setting} must be greater than zero"))
+            .collect::<Vec<_>>();
+        if self.relocation_fixes == 1 {
+            problems.push("fixFilter.relocationFixes must be 0 or at least 2".to_string());
+        }
+        if self.max_reference_age.is_some_and(|age| age.is_zero()) {
+            problems.push("fixFilter.maxReferenceAge must be greater than zero".to_string());
+        }
+        problems
+    }
+
+    pub fn max_speed_for(&self, type_name: Option<&str>) -> Option<f64> {
+        type_name
+            .and_then(|t| self.max_speed_by_type.get(t).copied())
+            .or(self.max_speed)
+    }
+}
+
+/// Why a fix was rejected.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rejection {
+    InvalidPosition,
+    NullIsland,
+    Future,
+    Duplicate,
+    /// With the implied speed in meters per second.
+    TooFast(f64),
+}
+
+impl Rejection {
+    /// Short label, e.g., for metrics.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Rejection::InvalidPosition => "invalid_position",
+            Rejection::NullIsland => "null_island",
+            Rejection::Future => "future",
+            Rejection::Duplicate => "duplicate",
+            Rejection::TooFast(_) => "too_fast",
+        }
+    }
+
+    /// Whether the fix may be accepted at a later time, so it should not be
+    /// considered as reported.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, Rejection::Future)
+    }
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rejection::InvalidPosition => write!(f, "invalid position"),
+            Rejection::NullIsland => write!(f, "null island"),
+            Rejection::Future => write!(f, "timestamp in the future"),
+            Rejection::Duplicate => write!(f, "duplicate"),
+            Rejection::TooFast(speed) => write!(f, "implied speed of {speed:.1} m/s"),
+        }
+    }
+}
+
+/// The reference for checking the fixes of a platform, kept across polls.
+#[derive(Debug, Clone, Default)]
+pub struct FixReference {
+    /// The last accepted fix, if any.
+    pub last: Option<Position>,
+    /// Consecutive fixes rejected as too fast from `last`, but consistent with each
+    /// other, as possibly from a real relocation.
+    candidates: Vec<Position>,
+}
+
+impl FixReference {
+    pub fn new(last: Option<Position>) -> Self {
+        FixReference {
+            last,
+            candidates: Vec::new(),
+        }
+    }
+
+    fn accept(&mut self, fix: &Position) {
+        self.last = Some(fix.clone());
+        self.candidates.clear();
+    }
+}
+
+/// Splits the given fixes, in chronological order, into those accepted and those
+/// rejected, with each fix checked against the previously accepted one per the
+/// given reference, which gets updated accordingly.
+pub fn filter_fixes(
+    config: &FixFilterConfig,
+    type_name: Option<&str>,
+    reference: &mut FixReference,
+    fixes: Vec<Position>,
+    now_ms: u64,
+) -> (Vec<Position>, Vec<(Position, Rejection)>) {
+    if !config.enabled {
+        if let Some(last) = fixes.last() {
+            reference.accept(last);
+        }
+        return (fixes, Vec::new());
+    }
+    let max_speed = config.max_speed_for(type_name);
+    let mut accepted = Vec::new();
+    let mut rejected = Vec::new();
+    for fix in fixes {
+        match check_fix(config, max_speed, reference, &fix, now_ms) {
+            Ok(()) => {
+                reference.accept(&fix);
+                accepted.push(fix);
+            }
+            Err(rejection) => rejected.push((fix, rejection)),
+        }
+    }
+    (accepted, rejected)
+}
+
+fn check_fix(
+    config: &FixFilterConfig,
+    max_speed: Option<f64>,
+    reference: &mut FixReference,
+    fix: &Position,
+    now_ms: u64,
+) -> Result<(), Rejection> {
+    if !(-90.0..=90.0).contains(&fix.lat) || !(-180.0..=180.0).contains(&fix.lon) {
+        return Err(Rejection::InvalidPosition);
+    }
+    if config.reject_null_island && fix.lat.abs() < 1e-6 && fix.lon.abs() < 1e-6 {
+        return Err(Rejection::NullIsland);
+    }
+    if fix.time_ms > now_ms + config.max_future.as_millis() as u64 {
+        return Err(Rejection::Future);
+    }
+    let Some(previous) = &reference.last else {
+        return Ok(());
+    };
+    if fix.time_ms == previous.time_ms {
+        return Err(Rejection::Duplicate);
+    }
+    let Some(max_speed) = max_speed else {
+        return Ok(());
+    };
+    let age_ms = fix.time_ms.abs_diff(previous.time_ms);
+    if config
+        .max_reference_age
+        .is_some_and(|max_age| age_ms > max_age.as_millis() as u64)
+    {
+        return Ok(());
+    }
+    let speed = implied_speed(previous, fix);
+    if speed <= max_speed {
+        return Ok(());
+    }
+    // possibly a relocation, if consistent with the previous such fixes:
+    let consistent = reference
+        .candidates
+        .last()
+        .is_some_and(|c| c.time_ms != fix.time_ms && implied_speed(c, fix) <= max_speed);
+    if !consistent {
+        reference.candidates.clear();
+    }
+    reference.candidates.push(fix.clone());
+    if config.relocation_fixes > 0 && reference.candidates.len() >= config.relocation_fixes as usize
+    {
+        log::info!(
+            "Accepting new track after {} consistent fixes too fast from the previous one",
+            reference.candidates.len()
+        );
+        return Ok(());
+    }
+    Err(Rejection::TooFast(speed))
+}
+
+/// Speed in meters per second implied by moving between the given fixes.
+fn implied_speed(from: &Position, to: &Position) -> f64 {
+    let distance = geo::distance_m((from.lat, from.lon), (to.lat, to.lon));
+    let elapsed_s = to.time_ms.abs_diff(from.time_ms) as f64 / 1000.0;
+    distance / elapsed_s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_filter_fixes() {
+        let config = FixFilterConfig {
+            max_speed: Some(10.0),
+            max_speed_by_type: BTreeMap::from([("lrauv".to_string(), 1.0)]),
+            ..FixFilterConfig::default()
+        };
+        let fix = |time_s: u64, lat: f64, lon: f64| Position {
+            time_ms: time_s * 1000,
+            lat,
+            lon,
+            kinematics: None,
+        };
+        let now_ms = 10_000_000;
+        let previous = fix(0, 36.8, -122.0);
+        let fixes = vec![
+            fix(60, 36.801, -122.0),  // ~111 m in 60 s
+            fix(60, 36.802, -122.0),  // duplicate
+            fix(120, 0.0, 0.0),       // null island
+            fix(180, 36.9, -122.0),   // ~11 km in 120 s
+            fix(240, 36.802, -122.0), // back in the bay
+            fix(300, 91.0, -122.0),   // invalid
+            fix(20_000, 36.803, -122.0),
+        ];
+        let mut reference = FixReference::new(Some(previous.clone()));
+        let (accepted, rejected) =
+            filter_fixes(&config, None, &mut reference, fixes.clone(), now_ms);
+        let times = |fixes: &[Position]| fixes.iter().map(|p| p.time_ms / 1000).collect::<Vec<_>>();
+        assert_eq!(times(&accepted), vec![60, 240]);
+        let reasons = rejected.iter().map(|(_, r)| r.reason()).collect::<Vec<_>>();
+        assert_eq!(
+            reasons,
+            vec![
+                "duplicate",
+                "null_island",
+                "too_fast",
+                "invalid_position",
+                "future"
+            ]
+        );
+        assert!(rejected.last().unwrap().1.is_transient());
+
+        // stricter for the type: ~111 m in 60 s is too fast for an LRAUV
+        let mut reference = FixReference::new(Some(previous.clone()));
+        let (accepted, _) = filter_fixes(&config, Some("lrauv"), &mut reference, fixes, now_ms);
+        assert_eq!(times(&accepted), vec![240]);
+
+        // a relocation ~11 km away, accepted upon the third consistent fix, even
+        // if across calls, and a single outlier in between is not enough:
+        let relocated = |time_s: u64| fix(time_s, 36.9, -122.0 + time_s as f64 * 1e-6);
+        let mut reference = FixReference::new(Some(previous.clone()));
+        let fixes = vec![relocated(60), fix(90, 37.5, -122.0), relocated(120)];
+        let (accepted, _) = filter_fixes(&config, None, &mut reference, fixes, now_ms);
+        assert!(accepted.is_empty());
+        let fixes = vec![relocated(180), relocated(240), relocated(300)];
+        let (accepted, rejected) = filter_fixes(&config, None, &mut reference, fixes, now_ms);
+        assert_eq!(times(&accepted), vec![240, 300]);
+        assert_eq!(rejected.len(), 1);
+
+        // or accepted right away if the previous fix is too old as reference:
+        let config = FixFilterConfig {
+            max_reference_age: Some(Duration::from_secs(3600)),
+            ..config
+        };
+        let mut reference = FixReference::new(Some(previous.clone()));
+        let fixes = vec![relocated(60), relocated(7200)];
+        let (accepted, _) = filter_fixes(&config, None, &mut reference, fixes, now_ms);
+        assert_eq!(times(&accepted), vec![7200]);
+
+        assert_eq!(
+            FixFilterConfig {
+                max_speed: Some(0.0),
+                ..FixFilterConfig::default()
+            }
+            .validate(),
+            vec!["fixFilter.maxSpeed must be greater than zero"]
+        );
+    }
+}
@@ -0,0 +1,139 @@
+//! Geodesic helpers on a spherical Earth, with positions as `(lat, lon)` in degrees.
+
+/// Mean Earth radius in meters.
+pub const EARTH_RADIUS_M: f64 = 6_371_008.8;
+
+/// Latitude and longitude in degrees.
+pub type LatLon = (f64, f64);
+
+/// Great-circle distance in meters (haversine formula).
+pub fn distance_m(from: LatLon, to: LatLon) -> f64 {
+    let (lat1, lat2) = (from.0.to_radians(), to.0.to_radians());
+    let d_lat = lat2 - lat1;
+    let d_lon = (to.1 - from.1).to_radians();
+    let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_M * a.sqrt().min(1.0).asin()
+}
+
+/// Initial bearing in degrees, in `[0, 360)`, of the great circle from one point to another.
+pub fn bearing_deg(from: LatLon, to: LatLon) -> f64 {
+    let (lat1, lat2) = (from.0.to_radians(), to.0.to_radians());
+    let d_lon = (to.1 - from.1).to_radians();
+    let y = d_lon.sin() * lat2.cos();
+    let x = lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * d_lon.cos();
+    y.atan2(x).to_degrees().rem_euclid(360.0)
+}
+
+/// The point at the given distance along the great circle with the given initial bearing.
+pub fn destination(from: LatLon, bearing_deg: f64, distance_m: f64) -> LatLon {
+    let lat1 = from.0.to_radians();
+    let lon1 = from.1.to_radians();
+    let bearing = bearing_deg.to_radians();
+    let delta = distance_m / EARTH_RADIUS_M;
+    let lat2 = (lat1.sin() * delta.cos() + lat1.cos() * delta.sin() * bearing.cos()).asin();
+    let lon2 = lon1
+        + (bearing.sin() * delta.sin() * lat1.cos()).atan2(delta.cos() - lat1.sin() * lat2.sin());
+    (lat2.to_degrees(), normalize_lon(lon2.to_degrees()))
+}
+
+/// The point at the given fraction of the way along the great circle between two points.
+pub fn interpolate(from: LatLon, to: LatLon, fraction: f64) -> LatLon {
+    let distance = distance_m(from, to);
+    if distance == 0.0 {
+        return from;
+    }
+    destination(from, bearing_deg(from, to), distance * fraction)
+}
+
+/// Distance in meters from a point to the segment between two points, using a local
+/// equirectangular projection, which is adequate for segments of a track.
+pub fn distance_to_segment_m(point: LatLon, from: LatLon, to: LatLon) -> f64 {
+    let cos_lat = ((from.0 + to.0) / 2.0).to_radians().cos();
+    let project = |(lat, lon): LatLon| {
+        let x = normalize_lon(lon - from.1).to_radians() * cos_lat * EARTH_RADIUS_M;
+        let y = (lat - from.0).to_radians() * EARTH_RADIUS_M;
+        (x, y)
+    };
+    let (px, py) = project(point);
+    let (bx, by) = project(to);
+    let length_sq = bx * bx + by * by;
+    let t = if length_sq > 0.0 {
+        ((px * bx + py * by) / length_sq).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    (px - t * bx).hypot(py - t * by)
+}
+
+/// Closest point of approach of two points moving at constant velocities, each given
+/// as speed in meters per second and course in degrees, within the next `horizon_s`
+/// seconds, using a local equirectangular projection. Returns the time in seconds
+/// from now to the closest point of approach, and the distance in meters then.
+pub fn closest_approach(
+    a: LatLon,
+    (a_speed, a_course): (f64, f64),
+    b: LatLon,
+    (b_speed, b_course): (f64, f64),
+    horizon_s: f64,
+) -> (f64, f64) {
+    let cos_lat = ((a.0 + b.0) / 2.0).to_radians().cos();
+    // position and velocity of b relative to a:
+    let x = normalize_lon(b.1 - a.1).to_radians() * cos_lat * EARTH_RADIUS_M;
+    let y = (b.0 - a.0).to_radians() * EARTH_RADIUS_M;
+    let (a_course, b_course) = (a_course.to_radians(), b_course.to_radians());
+    let vx = b_speed * b_course.sin() - a_speed * a_course.sin();
+    let vy = b_speed * b_course.cos() - a_speed * a_course.cos();
+    let speed_sq = vx * vx + vy * vy;
+    let t = if speed_sq > 0.0 {
+        (-(x * vx + y * vy) / speed_sq).clamp(0.0, horizon_s)
+    } else {
+        0.0
+    };
+    (t, (x + vx * t).hypot(y + vy * t))
+}
+
+/// Longitude in `[-180, 180)`.
+fn normalize_lon(lon: f64) -> f64 {
+    (lon + 180.0).rem_euclid(360.0) - 180.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_geo() {
+        // one degree of latitude:
+        let d = distance_m((36.0, -122.0), (37.0, -122.0));
+        assert!((d - 111_195.0).abs() < 1.0, "{d}");
+
+        assert!((bearing_deg((36.0, -122.0), (37.0, -122.0)) - 0.0).abs() < 1e-9);
+        assert!((bearing_deg((0.0, 10.0), (0.0, 9.0)) - 270.0).abs() < 1e-9);
+
+        let (lat, lon) = destination((36.8, -122.0), 90.0, 1000.0);
+        assert!((distance_m((36.8, -122.0), (lat, lon)) - 1000.0).abs() < 1e-6);
+
+        // one minute of latitude off a meridian segment, and beyond its end:
+        let d = distance_to_segment_m((36.5, -121.0 + 1.0 / 60.0), (36.0, -121.0), (37.0, -121.0));
+        assert!((d - 1_489.0).abs() < 5.0, "{d}");
+        let d = distance_to_segment_m((38.0, -121.0), (36.0, -121.0), (37.0, -121.0));
+        assert!((d - 111_195.0).abs() < 1.0, "{d}");
+
+        // head-on at 5 m/s each, 1 km apart, passing 100 m abeam after 100 s:
+        let b = destination(destination((36.8, -122.0), 90.0, 1000.0), 0.0, 100.0);
+        let (t, d) = closest_approach((36.8, -122.0), (5.0, 90.0), b, (5.0, 270.0), 3600.0);
+        assert!(
+            (t - 100.0).abs() < 0.1 && (d - 100.0).abs() < 0.1,
+            "{t}, {d}"
+        );
+        // diverging:
+        let (t, d) = closest_approach((36.8, -122.0), (5.0, 270.0), b, (5.0, 90.0), 3600.0);
+        assert!(t == 0.0 && (d - 1005.0).abs() < 1.0, "{t}, {d}");
+
+        let (lat, lon) = interpolate((0.0, 179.5), (0.0, -179.5), 0.5);
+        assert!(
+            lat.abs() < 1e-9 && (lon.abs() - 180.0).abs() < 1e-9,
+            "{lat}, {lon}"
+        );
+    }
+}
@@ -0,0 +1,471 @@
+//! Geofences (circles or polygons) evaluated by the dispatcher on each new fix of the
+//! dispatched platforms, raising alerts when a platform enters or exits them.
+//! Geofences are given in the configuration, directly or in a GeoJSON file, or added
+//! via the API (as GeoJSON), in which case they are kept under the state directory.
+//! The state (whether each platform is inside each geofence) is also kept there,
+//! so restarts do not re-fire alerts.
+
+use crate::alerts::{Alert, AlertKind};
+use crate::config;
+use crate::geo::{self, LatLon};
+use crate::routing::PlatformFilter;
+use crate::state_file;
+use crate::time_util;
+use crate::trackdb_client::{PlatformRes, Position};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// File under the state directory with the geofences added via the API.
+const GEOFENCES_FILENAME: &str = "geofences.json";
+
+/// File under the state directory with the geofence state of the platforms.
+const GEOFENCE_STATE_FILENAME: &str = "geofence_state.json";
+
+/// To serialize the updates of the geofences added via the API.
+static API_GEOFENCES_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Geofence {
+    pub name: String,
+    #[serde(flatten)]
+    pub area: Area,
+    /// The platforms the geofence applies to. Default: all dispatched platforms.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub platforms: Option<PlatformFilter>,
+}
+
+/// With points given as `[lat, lon]`.
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub enum Area {
+    /// With the radius in meters.
+    Circle { center: LatLon, radius: f64 },
+    /// Vertices of the polygon, with the closing one optional.
+    Polygon(Vec<LatLon>),
+}
+
+impl Area {
+    pub fn contains(&self, point: LatLon) -> bool {
+        match self {
+            Area::Circle { center, radius } => geo::distance_m(*center, point) <= *radius,
+            Area::Polygon(vertices) => {
+                // ray casting, with longitude as x and latitude as y:
+                let (y, x) = point;
+                let mut inside = false;
+                let edges = vertices.iter().zip(vertices.iter().cycle().skip(1));
+                for (&(y1, x1), &(y2, x2)) in edges {
+                    if (y1 > y) != (y2 > y) && x < x1 + (y - y1) / (y2 - y1) * (x2 - x1) {
+                        inside = !inside;
+                    }
+                }
+                inside
+            }
+        }
+    }
+}
+
+impl Geofence {
+    pub fn applies_to(&self, platform: &PlatformRes) -> bool {
+        self.platforms
+            .as_ref()
+            .is_none_or(|f| f.matches_platform(platform))
+    }
+
+    /// Checks the geofence, returning any problems found.
+    pub fn validate(&self, setting: &str) -> Vec<String> {
+        let mut problems = Vec::new();
+        let setting = format!("{setting}['{}']", self.name);
+        if self.name.is_empty() {
+            problems.push(format!("{setting}: empty name"));
+        }
+        let points = match &self.area {
+            Area::Circle { center, radius } => {
+                if radius.is_nan() || *radius <= 0.0 {
+                    problems.push(format!("{setting}: radius must be greater than zero"));
+                }
+                vec![*center]
+            }
+            Area::Polygon(vertices) => {
+                if vertices.len() < 3 {
+                    problems.push(format!("{setting}: polygon needs at least 3 vertices"));
+                }
+                vertices.clone()
+            }
+        };
+        if points
+            .iter()
+            .any(|(lat, lon)| !(-90.0..=90.0).contains(lat) || !(-180.0..=180.0).contains(lon))
+        {
+            problems.push(format!("{setting}: invalid point"));
+        }
+        problems
+    }
+
+    /// As a GeoJSON Feature, see [from_geojson].
+    pub fn to_geojson(&self, source: &str) -> Value {
+        let lon_lat = |(lat, lon): &LatLon| json!([lon, lat]);
+        let (geometry, radius) = match &self.area {
+            Area::Circle { center, radius } => (
+                json!({"type": "Point", "coordinates": lon_lat(center)}),
+                Some(*radius),
+            ),
+            Area::Polygon(vertices) => {
+                let mut ring = vertices.iter().map(lon_lat).collect::<Vec<_>>();
+                if vertices.first() != vertices.last() {
+                    ring.push(lon_lat(&vertices[0]));
+                }
+                (json!({"type": "Polygon", "coordinates": [ring]}), None)
+            }
+        };
+        let mut properties = match &self.platforms {
+            Some(filter) => serde_json::to_value(filter).unwrap(),
+            None => json!({}),
+        };
+        properties["name"] = json!(self.name);
+        properties["source"] = json!(source);
+        if let Some(radius) = radius {
+            properties["radius"] = json!(radius);
+        }
+        json!({"type": "Feature", "properties": properties, "geometry": geometry})
+    }
+}
+
+/// Parses geofences from a GeoJSON Feature or FeatureCollection. The name is given by
+/// the `name` property, and the area by a Polygon geometry (only its outer ring is
+/// considered), or by a Point geometry with a `radius` property in meters for a circle.
+/// Optional `platformIds`, `namePatterns`, and `typeNames` properties restrict the
+/// platforms the geofence applies to.
+pub fn from_geojson(value: &Value) -> Result<Vec<Geofence>, String> {
+    match value["type"].as_str() {
+        Some("FeatureCollection") => value["features"]
+            .as_array()
+            .ok_or("FeatureCollection without features")?
+            .iter()
+            .map(feature_to_geofence)
+            .collect(),
+        Some("Feature") => Ok(vec![feature_to_geofence(value)?]),
+        _ => Err("expecting a GeoJSON Feature or FeatureCollection".to_string()),
+    }
+}
+
+fn feature_to_geofence(feature: &Value) -> Result<Geofence, String> {
+    let properties = &feature["properties"];
+    let name = properties["name"]
+        .as_str()
+        .ok_or("Feature without a 'name' property")?
+        .to_string();
+    let lat_lon = |coordinates: &Value| -> Result<LatLon, String> {
+        match coordinates.as_array().map(|c| c.as_slice()) {
+            Some([lon, lat, ..]) => match (lat.as_f64(), lon.as_f64()) {
+                (Some(lat), Some(lon)) => Ok((lat, lon)),
+                _ => Err(format!("'{name}': invalid coordinates")),
+            },
+            _ => Err(format!("'{name}': invalid coordinates")),
+        }
+    };
+    let geometry = &feature["geometry"];
+    let area = match geometry["type"].as_str() {
+        Some("Point") => Area::Circle {
+            center: lat_lon(&geometry["coordinates"])?,
+            radius: properties["radius"]
+                .as_f64()
+                .ok_or(format!("'{name}': Point without a 'radius' property"))?,
+        },
+        Some("Polygon") => Area::Polygon(
+            geometry["coordinates"][0]
+                .as_array()
+                .ok_or(format!("'{name}': Polygon without coordinates"))?
+                .iter()
+                .map(lat_lon)
+                .collect::<Result<_, _>>()?,
+        ),
+        _ => return Err(format!("'{name}': expecting a Point or Polygon geometry")),
+    };
+    let platforms = serde_json::from_value::<PlatformFilter>(properties.clone())
+        .map_err(|e| format!("'{name}': {e}"))?;
+    let geofence = Geofence {
+        name,
+        area,
+        platforms: (platforms != PlatformFilter::default()).then_some(platforms),
+    };
+    match geofence.validate("geofences").first() {
+        Some(problem) => Err(problem.clone()),
+        None => Ok(geofence),
+    }
+}
+
+/// Loads the geofences from a GeoJSON file.
+pub fn load_geojson_file(path: &Path) -> Result<Vec<Geofence>, String> {
+    let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let value = serde_json::from_str::<Value>(&contents).map_err(|e| e.to_string())?;
+    from_geojson(&value)
+}
+
+/// The geofences from the configuration, including any `geofencesFile`.
+pub fn get_config_geofences() -> Vec<Geofence> {
+    let config = config::get_config();
+    let mut geofences = config.geofences.clone();
+    if let Some(path) = &config.geofences_file {
+        match load_geojson_file(path) {
+            Ok(from_file) => geofences.extend(from_file),
+            Err(e) => log::error!("geofencesFile '{}': {e}", path.display()),
+        }
+    }
+    geofences
+}
+
+/// The geofences added via the API.
+pub fn get_api_geofences() -> Vec<Geofence> {
+    let path = config::get_config().state_path(GEOFENCES_FILENAME);
+    state_file::load_json(&path).unwrap_or_default()
+}
+
+/// All the geofences, those from the configuration first.
+pub fn get_geofences() -> Vec<Geofence> {
+    let mut geofences = get_config_geofences();
+    geofences.extend(get_api_geofences());
+    geofences
+}
+
+/// Adds the given geofences via the API, replacing any existing ones by the same name.
+/// Geofences from the configuration cannot be replaced.
+pub fn put_api_geofences(new_geofences: Vec<Geofence>) -> Result<(), String> {
+    let config_names = get_config_geofences()
+        .into_iter()
+        .map(|g| g.name)
+        .collect::<Vec<_>>();
+    if let Some(g) = new_geofences
+        .iter()
+        .find(|g| config_names.contains(&g.name))
+    {
+        return Err(format!("'{}' is defined in the configuration", g.name));
+    }
+    let _lock = API_GEOFENCES_LOCK.lock().unwrap();
+    let mut geofences = get_api_geofences();
+    geofences.retain(|g| !new_geofences.iter().any(|n| n.name == g.name));
+    geofences.extend(new_geofences);
+    save_api_geofences(&geofences)
+}
+
+/// Removes a geofence added via the API, returning whether it existed.
+pub fn delete_api_geofence(name: &str) -> Result<bool, String> {
+    let _lock = API_GEOFENCES_LOCK.lock().unwrap();
+    let mut geofences = get_api_geofences();
+    let num_geofences = geofences.len();
+    geofences.retain(|g| g.name != name);
+    if geofences.len() == num_geofences {
+        return Ok(false);
+    }
+    save_api_geofences(&geofences).map(|_| true)
+}
+
+fn save_api_geofences(geofences: &[Geofence]) -> Result<(), String> {
+    let path = config::get_config().state_path(GEOFENCES_FILENAME);
+    state_file::save_json(&path, &geofences).map_err(|e| e.to_string())
+}
+
+/// The state of the platforms for each geofence, by geofence name.
+type GeofenceState = BTreeMap<String, FenceState>;
+
+/// Whether each platform is inside a geofence, by platform ID, along with the geofence
+/// definition the state is for, so it gets reset when the geofence is replaced.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct FenceState {
+    geofence: Geofence,
+    inside: BTreeMap<String, bool>,
+}
+
+/// Keeps track of the platforms entering or exiting the geofences.
+#[derive(Default)]
+pub struct GeofenceMonitor {
+    /// Loaded upon first use.
+    state: Option<GeofenceState>,
+    changed: bool,
+}
+
+impl GeofenceMonitor {
+    /// Evaluates the new fixes of the platform, in chronological order, against the
+    /// applicable geofences, returning the alerts for any entering or exiting.
+    /// The first evaluation of a platform against a geofence only sets its state.
+    pub fn evaluate(
+        &mut self,
+        geofences: &[Geofence],
+        platform: &PlatformRes,
+        fixes: &[Position],
+    ) -> Vec<Alert> {
+        let state = self.state.get_or_insert_with(|| {
+            let path = config::get_config().state_path(GEOFENCE_STATE_FILENAME);
+            state_file::load_json(&path).unwrap_or_default()
+        });
+        let raised_ms = time_util::now_ms();
+        let mut alerts = Vec::new();
+        for geofence in geofences.iter().filter(|g| g.applies_to(platform)) {
+            let fence_state = state
+                .entry(geofence.name.clone())
+                .or_insert_with(|| FenceState {
+                    geofence: geofence.clone(),
+                    inside: BTreeMap::new(),
+                });
+            if fence_state.geofence != *geofence {
+                log::info!("Geofence '{}' replaced; resetting its state", geofence.name);
+                fence_state.geofence = geofence.clone();
+                fence_state.inside.clear();
+                self.changed = true;
+            }
+            let platforms = &mut fence_state.inside;
+            for fix in fixes {
+                let inside = geofence.area.contains((fix.lat, fix.lon));
+                let was_inside = platforms.insert(platform._id.clone(), inside);
+                if was_inside != Some(inside) {
+                    self.changed = true;
+                }
+                if was_inside.is_none_or(|was_inside| was_inside == inside) {
+                    continue;
+                }
+                let (kind, verb) = if inside {
+                    (AlertKind::GeofenceEnter, "entered")
+                } else {
+                    (AlertKind::GeofenceExit, "exited")
+                };
+                alerts.push(Alert {
+                    time_ms: fix.time_ms,
+                    raised_ms,
+                    kind,
+                    platform_id: platform._id.clone(),
+                    platform_name: platform.name.clone(),
+                    geofence: Some(geofence.name.clone()),
+                    other_platform_id: None,
+                    message: format!(
+                        "{} ({}) {verb} geofence '{}'",
+                        platform.name, platform._id, geofence.name
+                    ),
+                    position: Some(fix.clone()),
+                });
+            }
+        }
+        alerts.sort_by_key(|a| a.time_ms);
+        alerts
+    }
+
+    /// Saves the state, if changed, only keeping that of the given geofences.
+    pub fn save(&mut self, geofences: &[Geofence]) {
+        let Some(state) = self.state.as_mut().filter(|_| self.changed) else {
+            return;
+        };
+        state.retain(|name, _| geofences.iter().any(|g| &g.name == name));
+        let path = config::get_config().state_path(GEOFENCE_STATE_FILENAME);
+        match state_file::save_json(&path, state) {
+            Ok(()) => self.changed = false,
+            Err(e) => log::error!("Failed to save geofence state: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_geofences() {
+        crate::config::init_test_config();
+        let geojson = json!({
+            "type": "FeatureCollection",
+            "features": [
+                {
+                    "type": "Feature",
+                    "properties": {"name": "op-area", "typeNames": ["lrauv"]},
+                    "geometry": {"type": "Polygon", "coordinates": [[
+                        [-122.0, 36.7], [-121.8, 36.7], [-121.8, 36.9], [-122.0, 36.9], [-122.0, 36.7]
+                    ]]}
+                },
+                {
+                    "type": "Feature",
+                    "properties": {"name": "mooring", "radius": 500.0},
+                    "geometry": {"type": "Point", "coordinates": [-121.9, 36.8]}
+                }
+            ]
+        });
+        let geofences = from_geojson(&geojson).unwrap();
+        assert_eq!(geofences.len(), 2);
+        assert!(geofences[0].area.contains((36.8, -121.85)));
+        assert!(!geofences[0].area.contains((36.8, -121.7)));
+        assert!(geofences[1].area.contains((36.803, -121.9)));
+        assert_eq!(
+            from_geojson(&geofences[1].to_geojson("api")).unwrap(),
+            vec![geofences[1].clone()]
+        );
+        assert!(from_geojson(&json!({"type": "Feature", "properties": {}})).is_err());
+
+        let platform = PlatformRes {
+            _id: "p1".to_string(),
+            name: "one".to_string(),
+            abbreviation: "one".to_string(),
+            type_name: Some("lrauv".to_string()),
+            color: None,
+            icon_url: None,
+        };
+        let fix = |time_ms: u64, lat: f64, lon: f64| Position {
+            time_ms,
+            lat,
+            lon,
+            kinematics: None,
+        };
+        let mut monitor = GeofenceMonitor {
+            state: Some(GeofenceState::new()),
+            changed: false,
+        };
+        // first evaluation only sets the state:
+        let alerts = monitor.evaluate(&geofences, &platform, &[fix(1, 36.8, -121.9)]);
+        assert!(alerts.is_empty());
+        let alerts = monitor.evaluate(
+            &geofences,
+            &platform,
+            &[fix(2, 36.8, -121.85), fix(3, 36.8, -121.7)],
+        );
+        let summary = alerts
+            .iter()
+            .map(|a| (a.time_ms, a.kind, a.geofence.clone().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                (2, AlertKind::GeofenceExit, "mooring".to_string()),
+                (3, AlertKind::GeofenceExit, "op-area".to_string()),
+            ]
+        );
+        // no change, no alert:
+        let alerts = monitor.evaluate(&geofences, &platform, &[fix(4, 36.8, -121.7)]);
+        assert!(alerts.is_empty());
+        let alerts = monitor.evaluate(&geofences, &platform, &[fix(5, 36.8, -121.9)]);
+        assert_eq!(alerts.len(), 2);
+        assert!(alerts.iter().all(|a| a.kind == AlertKind::GeofenceEnter));
+
+        // a replaced geofence by the same name starts over, without a spurious exit:
+        let mut replaced = geofences.clone();
+        replaced[1].area = Area::Circle {
+            center: (36.0, -121.0),
+            radius: 500.0,
+        };
+        let alerts = monitor.evaluate(&replaced, &platform, &[fix(6, 36.8, -121.9)]);
+        assert!(alerts.is_empty());
+        let alerts = monitor.evaluate(&replaced, &platform, &[fix(7, 36.0, -121.0)]);
+        let summary = alerts
+            .iter()
+            .map(|a| (a.kind, a.geofence.clone().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                (AlertKind::GeofenceExit, "op-area".to_string()),
+                (AlertKind::GeofenceEnter, "mooring".to_string()),
+            ]
+        );
+    }
+}
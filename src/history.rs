@@ -0,0 +1,189 @@
+use crate::config;
+use crate::jsonl;
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use utoipa::ToSchema;
+
+/// Directory under the state directory for the position history.
+const HISTORY_DIRNAME: &str = "history";
+
+/// A position seen by the dispatcher, as kept in the history.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    pub time_ms: u64,
+    pub lat: f64,
+    pub lon: f64,
+    /// When the position was first seen by the dispatcher.
+    pub seen_ms: u64,
+    /// Names of the TethysDash instances the position was successfully sent to.
+    pub sent_to: Vec<String>,
+    /// Why the position was not dispatched at all, if so: the fix filter rejection
+    /// reason (e.g., `too_fast`), or `decimated`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub not_dispatched: Option<String>,
+}
+
+/// On-disk archive of the positions seen by the dispatcher, with an append-only
+/// JSON lines file per platform.
+pub struct History {
+    dir: PathBuf,
+}
+
+impl History {
+    /// The history per the configuration, if enabled.
+    pub fn from_config() -> Option<Self> {
+        let config = config::get_config();
+        if config.history.enabled {
+            Some(History::new(&config.state_path(HISTORY_DIRNAME)))
+        } else {
+            None
+        }
+    }
+
+    pub fn new(dir: &Path) -> Self {
+        History {
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    pub fn append(&self, platform_id: &str, entries: &[HistoryEntry]) -> io::Result<()> {
+        jsonl::append(&self.platform_path(platform_id), entries)
+    }
+
+    /// Gets the entries for the platform within the given time range (inclusive),
+    /// limited to the last `last_number` ones, if given.
+    /// Returns `None` if there is no history for the platform.
+    pub fn get_entries(
+        &self,
+        platform_id: &str,
+        start_ms: Option<u64>,
+        end_ms: Option<u64>,
+        last_number: Option<usize>,
+    ) -> io::Result<Option<Vec<HistoryEntry>>> {
+        let path = self.platform_path(platform_id);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let mut entries = jsonl::read::<HistoryEntry>(&path)?
+            .into_iter()
+            .filter(|e| start_ms.is_none_or(|start| e.time_ms >= start))
+            .filter(|e| end_ms.is_none_or(|end| e.time_ms <= end))
+            .collect::<Vec<_>>();
+        entries.sort_by_key(|e| e.time_ms);
+        if let Some(last_number) = last_number {
+            entries.drain(..entries.len().saturating_sub(last_number));
+        }
+        Ok(Some(entries))
+    }
+
+    /// Removes the entries with positions older than the given time,
+    /// returning the number of removed entries.
+    pub fn prune(&self, older_than_ms: u64) -> io::Result<usize> {
+        if !self.dir.exists() {
+            return Ok(0);
+        }
+        let mut num_removed = 0;
+        for dir_entry in fs::read_dir(&self.dir)? {
+            let path = dir_entry?.path();
+            if path.extension().is_none_or(|ext| ext != "jsonl") {
+                continue;
+            }
+            let entries = jsonl::read::<HistoryEntry>(&path)?;
+            let num_entries = entries.len();
+            let kept = entries
+                .into_iter()
+                .filter(|e| e.time_ms >= older_than_ms)
+                .collect::<Vec<_>>();
+            if kept.len() < num_entries {
+                num_removed += num_entries - kept.len();
+                jsonl::rewrite(&path, &kept)?;
+            }
+        }
+        Ok(num_removed)
+    }
+
+    fn platform_path(&self, platform_id: &str) -> PathBuf {
+        // platform IDs are expected to be simple, but just in case,
+        // any other characters are percent-encoded, so distinct IDs get distinct files:
+        let mut filename = String::with_capacity(platform_id.len());
+        for byte in platform_id.bytes() {
+            if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_' {
+                filename.push(byte as char);
+            } else {
+                filename.push_str(&format!("%{byte:02X}"));
+            }
+        }
+        self.dir.join(format!("{filename}.jsonl"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::fs::OpenOptions;
+    use std::io::Write;
+
+    fn entry(time_ms: u64) -> HistoryEntry {
+        HistoryEntry {
+            time_ms,
+            lat: 36.8,
+            lon: -121.9,
+            seen_ms: time_ms + 1000,
+            sent_to: vec!["okeanids".to_string()],
+            not_dispatched: None,
+        }
+    }
+
+    #[test]
+    fn test_history() {
+        let dir = std::env::temp_dir().join(format!("odss2dash-history-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let history = History::new(&dir);
+
+        assert_eq!(history.get_entries("p1", None, None, None).unwrap(), None);
+
+        history.append("p1", &[entry(1000), entry(2000)]).unwrap();
+        history.append("p1", &[entry(3000)]).unwrap();
+        // a partial line, as upon a crash, is skipped:
+        let mut f = OpenOptions::new()
+            .append(true)
+            .open(history.platform_path("p1"))
+            .unwrap();
+        f.write_all(br#"{"timeMs":4000,"la"#).unwrap();
+        history.append("p1", &[entry(5000)]).unwrap();
+
+        let times = |entries: Option<Vec<HistoryEntry>>| {
+            entries
+                .unwrap()
+                .iter()
+                .map(|e| e.time_ms)
+                .collect::<Vec<_>>()
+        };
+        let get = |start, end, last| history.get_entries("p1", start, end, last).unwrap();
+        assert_eq!(times(get(None, None, None)), vec![1000, 2000, 3000, 5000]);
+        assert_eq!(times(get(Some(2000), None, None)), vec![2000, 3000, 5000]);
+        assert_eq!(times(get(None, Some(2000), None)), vec![1000, 2000]);
+        assert_eq!(times(get(None, None, Some(1))), vec![5000]);
+
+        assert_eq!(history.prune(2500).unwrap(), 2);
+        assert_eq!(times(get(None, None, None)), vec![3000, 5000]);
+
+        // distinct files for IDs that only differ in special characters:
+        history.append("partner:x", &[entry(1000)]).unwrap();
+        assert_eq!(
+            history.get_entries("partner_x", None, None, None).unwrap(),
+            None
+        );
+        assert_eq!(
+            history.platform_path("../x").file_name().unwrap(),
+            "%2E%2E%2Fx.jsonl"
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,120 @@
+//! Helpers for append-only JSON lines files.
+
+use serde::{de::DeserializeOwned, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Appends the given values to the file, one JSON document per line.
+pub fn append<T: Serialize>(path: &Path, values: &[T]) -> io::Result<()> {
+    if values.is_empty() {
+        return Ok(());
+    }
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    let mut lines = String::new();
+    if !ends_with_newline(path)? {
+        // so a partial line from a previous crash does not affect the new lines:
+        lines.push('\n');
+    }
+    for value in values {
+        lines.push_str(&serde_json::to_string(value)?);
+        lines.push('\n');
+    }
+    // single write so a crash can at most leave a partial last line:
+    let mut f = OpenOptions::new().create(true).append(true).open(path)?;
+    f.write_all(lines.as_bytes())?;
+    f.sync_data()
+}
+
+/// Reads the values in the given file, skipping any malformed lines
+/// (e.g., a partial last line upon a crash).
+/// Returns an empty list if the file does not exist.
+pub fn read<T: DeserializeOwned>(path: &Path) -> io::Result<Vec<T>> {
+    let f = match File::open(path) {
+        Ok(f) => f,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut values = Vec::new();
+    for (index, line) in BufReader::new(f).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<T>(&line) {
+            Ok(value) => values.push(value),
+            Err(e) => log::warn!(
+                "Skipping malformed line {} in '{}': {e}",
+                index + 1,
+                path.display()
+            ),
+        }
+    }
+    Ok(values)
+}
+
+/// Replaces the contents of the file with the given values in a crash-safe manner.
+pub fn rewrite<T: Serialize>(path: &Path, values: &[T]) -> io::Result<()> {
+    let tmp_path = path.with_extension("jsonl.tmp");
+    {
+        let mut f = File::create(&tmp_path)?;
+        for value in values {
+            serde_json::to_writer(&mut f, value)?;
+            f.write_all(b"\n")?;
+        }
+        f.sync_all()?;
+    }
+    fs::rename(&tmp_path, path)
+}
+
+/// True if the file does not exist, is empty, or ends with a newline.
+fn ends_with_newline(path: &Path) -> io::Result<bool> {
+    let mut f = match File::open(path) {
+        Ok(f) => f,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(true),
+        Err(e) => return Err(e),
+    };
+    if f.metadata()?.len() == 0 {
+        return Ok(true);
+    }
+    let mut last = [0u8];
+    f.seek(SeekFrom::End(-1))?;
+    f.read_exact(&mut last)?;
+    Ok(last[0] == b'\n')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_jsonl() {
+        let path =
+            std::env::temp_dir().join(format!("odss2dash-jsonl-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(read::<u64>(&path).unwrap(), Vec::<u64>::new());
+        append(&path, &[1u64, 2]).unwrap();
+        append::<u64>(&path, &[]).unwrap();
+        // a partial last line, as upon a crash, does not affect the following lines:
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"3")
+            .unwrap();
+        append(&path, &["four"]).unwrap();
+        append(&path, &[5u64]).unwrap();
+        // "3" is still a valid line; "four" is malformed as a number and skipped:
+        assert_eq!(read::<u64>(&path).unwrap(), vec![1, 2, 3, 5]);
+
+        rewrite(&path, &[5u64]).unwrap();
+        assert_eq!(read::<u64>(&path).unwrap(), vec![5]);
+        assert!(!path.with_extension("jsonl.tmp").exists());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
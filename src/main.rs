@@ -1,18 +1,41 @@
+mod alerts;
+mod audit;
 mod config;
+mod decimation;
 mod dispatched_info;
 mod dispatcher;
+mod file_source;
+mod fix_filter;
+mod geo;
+mod geofence;
+mod history;
+mod jsonl;
+mod mock;
 mod platform_info;
+mod poll_schedule;
+mod proximity;
 mod publisher;
+mod routing;
+mod secret;
 mod serve_dispatch;
 mod server;
+mod simulation;
+mod staleness;
+mod state_file;
+mod state_store;
 mod tethysdash_client;
+mod time_util;
 mod trackdb_client;
 use serve_dispatch::{dispatch, serve};
 
-use crate::dispatched_info::DispatchedInfo;
+use crate::audit::AuditContext;
+use crate::dispatched_info::{check_group_name, DispatchDetails, DispatchedInfo};
 use crate::server::health::get_health_status;
 
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
 
 /// The odss2dash CLI
 #[derive(Parser)]
@@ -26,15 +49,23 @@ use clap::{Parser, Subcommand};
     styles=cli_styles()
 )]
 struct Cli {
+    /// Configuration file [default: odss2dash.toml, if existing]
+    #[arg(long, global = true, env = config::CONFIG_ENV_VAR)]
+    config: Option<PathBuf>,
+
     #[command(subcommand)]
     command: Commands,
 }
 
 #[derive(Debug, Subcommand)]
 enum Commands {
-    /// Perform basic configuration checks
+    /// Validate the configuration, exiting with non-zero status if any problems
     #[command()]
-    CheckConfig,
+    CheckConfig {
+        /// Also check connectivity to ODSS and to each TethysDash with its API key
+        #[arg(long)]
+        online: bool,
+    },
 
     /// Get all platforms from TrackingDB/ODSS
     #[command()]
@@ -59,6 +90,21 @@ enum Commands {
     AddDispatched {
         /// Platform IDs to dispatch
         platform_ids: Vec<String>,
+
+        #[command(flatten)]
+        details: DetailsArgs,
+    },
+
+    /// Manage named platform groups for bulk dispatch
+    #[command(subcommand)]
+    Group(GroupCommands),
+
+    /// Import `dispatched.json` and `reported.json` into the SQLite state store
+    #[command()]
+    ImportState {
+        /// Directory with the JSON files [default: the configured stateDir]
+        #[arg(long)]
+        from: Option<PathBuf>,
     },
 
     /// Launch dispatch according to configuration
@@ -67,6 +113,10 @@ enum Commands {
         /// Run dispatch only once
         #[arg(long)]
         once: bool,
+
+        /// Only print the XEvents that would be sent, without posting them or saving state
+        #[arg(long)]
+        dry_run: bool,
     },
 
     /// Launch service
@@ -75,47 +125,234 @@ enum Commands {
         /// Only run the server, not the dispatcher
         #[arg(long)]
         no_dispatch: bool,
+
+        /// Only print the XEvents that would be dispatched, without posting them or saving state
+        #[arg(long, conflicts_with = "no_dispatch")]
+        dry_run: bool,
     },
 
     /// Get health similar to the endpoint
     #[command()]
     Health,
+
+    /// Run a mock ODSS serving recorded data, for testing and local development
+    #[command()]
+    MockOdss {
+        /// Port to listen on
+        #[arg(long, default_value_t = 3044)]
+        port: u16,
+
+        /// Directory with the data, as for the `dataDir` setting
+        #[arg(long, default_value = mock::odss::DEFAULT_DATA_DIR)]
+        data_dir: PathBuf,
+    },
+
+    /// Run a mock TethysDash recording the received XEvents, for testing and local development
+    #[command()]
+    MockTethysdash {
+        /// Port to listen on
+        #[arg(long, default_value_t = 3055)]
+        port: u16,
+
+        /// API key expected as bearer token [default: any accepted]
+        #[arg(long)]
+        api_key: Option<String>,
+
+        /// Delay before responding to each XEvent, e.g., "2s"
+        #[arg(long, value_parser = humantime::parse_duration)]
+        latency: Option<Duration>,
+
+        /// Fail every n-th XEvent request
+        #[arg(long, default_value_t = 0)]
+        fail_every: u32,
+    },
+}
+
+#[derive(Debug, Args)]
+struct DetailsArgs {
+    /// Dispatch only from this time (ISO 8601 UTC or epoch milliseconds)
+    #[arg(long)]
+    start_time: Option<String>,
+
+    /// Undispatch at this time (ISO 8601 UTC or epoch milliseconds)
+    #[arg(long)]
+    expires_at: Option<String>,
+
+    /// Note about the dispatch, e.g., the cruise
+    #[arg(long)]
+    note: Option<String>,
+
+    /// Who adds the platforms [default: the current user]
+    #[arg(long)]
+    added_by: Option<String>,
+}
+
+impl DetailsArgs {
+    /// Gets the details, exiting the program if invalid.
+    fn into_details(self) -> DispatchDetails {
+        DispatchDetails::new(
+            self.start_time.as_deref(),
+            self.expires_at.as_deref(),
+            self.note,
+            self.added_by,
+        )
+        .unwrap_or_else(|e| {
+            eprintln!("error: {e}");
+            std::process::exit(1);
+        })
+    }
+}
+
+#[derive(Debug, Subcommand)]
+enum GroupCommands {
+    /// List all groups with their platforms
+    #[command()]
+    List,
+
+    /// List the groups each dispatched platform belongs to
+    #[command()]
+    Membership,
+
+    /// Create or replace a group
+    #[command(arg_required_else_help = true)]
+    Set {
+        /// The group name
+        name: String,
+        /// Platform IDs in the group
+        platform_ids: Vec<String>,
+    },
+
+    /// Delete a group (its platforms remain dispatched, if so)
+    #[command(arg_required_else_help = true)]
+    Delete {
+        /// The group name
+        name: String,
+    },
+
+    /// Dispatch all platforms of a group
+    #[command(arg_required_else_help = true)]
+    Dispatch {
+        /// The group name
+        name: String,
+
+        #[command(flatten)]
+        details: DetailsArgs,
+    },
+
+    /// Undispatch all platforms of a group
+    #[command(arg_required_else_help = true)]
+    Undispatch {
+        /// The group name
+        name: String,
+    },
 }
 
 fn main() {
     let args = Cli::parse();
-    config::load_config();
     env_logger::init();
     match args.command {
-        Commands::CheckConfig => {
-            check_config();
+        Commands::CheckConfig { online } => {
+            check_config(args.config, online);
+        }
+        Commands::MockOdss { port, data_dir } => {
+            mock_odss(port, data_dir);
+        }
+        Commands::MockTethysdash {
+            port,
+            api_key,
+            latency,
+            fail_every,
+        } => {
+            let faults = mock::tethysdash::Faults {
+                fail_every,
+                latency_ms: latency.unwrap_or_default().as_millis() as u64,
+                ..Default::default()
+            };
+            mock_tethysdash(port, api_key, faults);
         }
         Commands::GetPlatforms => {
+            config::load_config(args.config, false);
             get_platforms();
         }
         Commands::GetPlatform { platform_id } => {
+            config::load_config(args.config, false);
             get_platform(&platform_id);
         }
         Commands::GetPositions { platform_id } => {
+            config::load_config(args.config, false);
             get_positions(&platform_id);
         }
-        Commands::AddDispatched { platform_ids } => {
-            add_dispatched(platform_ids);
+        Commands::AddDispatched {
+            platform_ids,
+            details,
+        } => {
+            config::load_config(args.config, false);
+            add_dispatched(platform_ids, details.into_details());
+        }
+        Commands::Group(command) => {
+            config::load_config(args.config, false);
+            group(command);
+        }
+        Commands::ImportState { from } => {
+            config::load_config(args.config, false);
+            import_state(from);
         }
-        Commands::Dispatch { once } => {
-            dispatch(once);
+        Commands::Dispatch { once, dry_run } => {
+            config::load_config(args.config, true);
+            dispatch(once, dry_run);
         }
-        Commands::Serve { no_dispatch } => {
-            serve(no_dispatch);
+        Commands::Serve {
+            no_dispatch,
+            dry_run,
+        } => {
+            config::load_config(args.config, true);
+            serve(no_dispatch, dry_run);
         }
         Commands::Health => {
+            config::load_config(args.config, false);
             get_health();
         }
     }
 }
 
-fn check_config() {
-    println!("{}", config::get_config().redacted().json_string());
+fn check_config(config_file: Option<PathBuf>, online: bool) {
+    let config = config::init_config(config_file).unwrap_or_else(|e| {
+        eprintln!("error: {e}");
+        std::process::exit(1);
+    });
+    println!("{}", config.json_string());
+
+    let mut problems = config.validate();
+    if online && problems.is_empty() {
+        problems.extend(check_online(&config));
+    }
+
+    if problems.is_empty() {
+        println!("Configuration OK");
+    } else {
+        eprintln!("Configuration problems:");
+        for problem in problems {
+            eprintln!("  - {problem}");
+        }
+        std::process::exit(1);
+    }
+}
+
+fn check_online(config: &config::Config) -> Vec<String> {
+    let mut problems = Vec::new();
+    for (source, result) in trackdb_client::probe() {
+        match result {
+            Ok(num_platforms) => println!("ODSS '{source}': OK ({num_platforms} platforms)"),
+            Err(e) => problems.push(format!("ODSS '{source}': {e}")),
+        }
+    }
+    for td in &config.tethysdashes {
+        match tethysdash_client::probe(td) {
+            Ok(()) => println!("TethysDash '{}': OK", td.name),
+            Err(e) => problems.push(format!("TethysDash '{}': {e}", td.name)),
+        }
+    }
+    problems
 }
 
 fn get_platforms() {
@@ -143,8 +380,98 @@ fn get_positions(platform_id: &str) {
     }
 }
 
-fn add_dispatched(platform_ids: Vec<String>) {
-    DispatchedInfo::new().add_platform_ids(platform_ids);
+fn add_dispatched(platform_ids: Vec<String>, details: DispatchDetails) {
+    DispatchedInfo::new().add_platform_ids(platform_ids, details, &AuditContext::cli());
+}
+
+fn group(command: GroupCommands) {
+    let mut dispatched_info = DispatchedInfo::new();
+    let get_group = |dispatched_info: &DispatchedInfo, name: &str| {
+        dispatched_info.get_group(name).unwrap_or_else(|| {
+            eprintln!("error: no group by name: {name}");
+            std::process::exit(1);
+        })
+    };
+    match command {
+        GroupCommands::List => {
+            let groups = dispatched_info.get_groups();
+            println!("{}", serde_json::to_string_pretty(&groups).unwrap());
+        }
+        GroupCommands::Membership => {
+            let mut memberships = dispatched_info.get_memberships();
+            let membership = dispatched_info
+                .get_platform_ids()
+                .into_iter()
+                .map(|id| {
+                    let groups = memberships.remove(&id).unwrap_or_default();
+                    (id, groups)
+                })
+                .collect::<BTreeMap<_, _>>();
+            println!("{}", serde_json::to_string_pretty(&membership).unwrap());
+        }
+        GroupCommands::Set { name, platform_ids } => {
+            if let Err(e) = check_group_name(&name) {
+                eprintln!("error: {e}");
+                std::process::exit(1);
+            }
+            let created = dispatched_info.set_group(&name, platform_ids);
+            println!(
+                "Group '{name}' {}",
+                if created { "created" } else { "replaced" }
+            );
+        }
+        GroupCommands::Delete { name } => match dispatched_info.delete_group(&name) {
+            Some(_) => println!("Group '{name}' deleted"),
+            None => {
+                eprintln!("error: no group by name: {name}");
+                std::process::exit(1);
+            }
+        },
+        GroupCommands::Dispatch { name, details } => {
+            let platform_ids = get_group(&dispatched_info, &name);
+            let added = dispatched_info.add_platform_ids(
+                platform_ids,
+                details.into_details(),
+                &AuditContext::cli(),
+            );
+            println!("Dispatched {} new platforms: {added:?}", added.len());
+        }
+        GroupCommands::Undispatch { name } => {
+            let removed = get_group(&dispatched_info, &name)
+                .iter()
+                .filter_map(|id| dispatched_info.delete_platform_id(id, &AuditContext::cli()))
+                .collect::<Vec<_>>();
+            println!("Undispatched {} platforms: {removed:?}", removed.len());
+        }
+    }
+}
+
+fn import_state(from: Option<PathBuf>) {
+    let from = from.unwrap_or_else(|| config::get_config().state_dir.clone());
+    match state_store::import_json_state(&from) {
+        Ok((num_dispatched, num_reported)) => println!(
+            "Imported {num_dispatched} dispatched platforms and {num_reported} reported timestamps from '{}'",
+            from.display()
+        ),
+        Err(e) => {
+            eprintln!("error: {e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn mock_odss(port: u16, data_dir: PathBuf) {
+    if let Err(e) = mock::odss::serve(port, data_dir) {
+        eprintln!("error: {e}");
+        std::process::exit(1);
+    }
+}
+
+fn mock_tethysdash(port: u16, api_key: Option<String>, faults: mock::tethysdash::Faults) {
+    if let Err(e) = mock::tethysdash::serve(port, api_key, faults) {
+        eprintln!("error: {e}");
+        std::process::exit(1);
+    }
 }
 
 fn get_health() {
@@ -166,3 +493,49 @@ fn cli_styles() -> clap::builder::Styles {
         .literal(style(Green))
         .placeholder(style(Blue))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn run_group_command(args: &[&str]) {
+        let args = ["odss2dash", "group"].iter().chain(args);
+        match Cli::try_parse_from(args).unwrap().command {
+            Commands::Group(command) => group(command),
+            command => panic!("unexpected command: {command:?}"),
+        }
+    }
+
+    #[test]
+    fn test_group_commands() {
+        config::init_test_config();
+        let platform_ids = vec!["group-cli-1".to_string(), "group-cli-2".to_string()];
+
+        run_group_command(&["set", "group-cli", "group-cli-1", "group-cli-2"]);
+        assert_eq!(
+            DispatchedInfo::new().get_group("group-cli"),
+            Some(platform_ids.clone())
+        );
+
+        run_group_command(&["dispatch", "group-cli", "--note", "cruise"]);
+        let dispatched_info = DispatchedInfo::new();
+        let details = dispatched_info.get_details();
+        for platform_id in &platform_ids {
+            assert!(dispatched_info.is_dispatched_platform(platform_id));
+            assert_eq!(details[platform_id].note.as_deref(), Some("cruise"));
+        }
+        assert_eq!(
+            dispatched_info.get_memberships()["group-cli-1"],
+            vec!["group-cli"]
+        );
+
+        run_group_command(&["undispatch", "group-cli"]);
+        run_group_command(&["delete", "group-cli"]);
+        let dispatched_info = DispatchedInfo::new();
+        for platform_id in &platform_ids {
+            assert!(!dispatched_info.is_dispatched_platform(platform_id));
+        }
+        assert_eq!(dispatched_info.get_group("group-cli"), None);
+    }
+}
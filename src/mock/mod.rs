@@ -0,0 +1,44 @@
+//! Mock ODSS and TethysDash services, for tests and local development.
+
+pub mod odss;
+pub mod tethysdash;
+
+use axum::Router;
+use std::net::{Ipv4Addr, SocketAddr};
+
+/// Runs the given mock service until interrupted.
+#[tokio::main]
+async fn serve(name: &str, port: u16, router: Router) -> Result<(), String> {
+    let address = SocketAddr::from((Ipv4Addr::UNSPECIFIED, port));
+    let listener = tokio::net::TcpListener::bind(address)
+        .await
+        .map_err(|e| format!("{address}: {e}"))?;
+    println!("Mock {name} listening on {address}");
+    axum::serve(listener, router)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Launches the given mock service on an ephemeral port in a background thread,
+/// returning its base URL. The service has its own runtime, so it can be used
+/// from blocking code running in the runtime of a test.
+#[cfg(test)]
+fn spawn(router: Router) -> String {
+    let listener = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .and_then(|l| l.set_nonblocking(true).map(|_| l))
+        .expect("mock listener bound");
+    let address = listener.local_addr().expect("mock listener address");
+    std::thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("runtime for mock service");
+        runtime.block_on(async {
+            let listener = tokio::net::TcpListener::from_std(listener).expect("mock listener");
+            axum::serve(listener, router)
+                .await
+                .expect("mock service running");
+        });
+    });
+    format!("http://{address}")
+}
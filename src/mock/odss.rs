@@ -0,0 +1,101 @@
+//! Mock ODSS API serving `/platforms`, `/platforms/{id}`, and `/tracks` from
+//! recorded data (see [file_source]).
+
+use crate::file_source;
+use crate::trackdb_client::TrackRes;
+
+use axum::{
+    extract::{Path, Query, State},
+    response::IntoResponse,
+    routing, Json, Router,
+};
+use hyper::StatusCode;
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Default directory with the mock data.
+pub const DEFAULT_DATA_DIR: &str = "fixtures/odss";
+
+/// Number of fixes returned when no `lastNumberOfFixes` or date range is given.
+const DEFAULT_LAST_NUMBER_OF_FIXES: u32 = 1;
+
+pub fn create_mock_odss_router(data_dir: PathBuf) -> Router {
+    Router::new()
+        .route("/platforms", routing::get(get_platforms))
+        .route("/platforms/{platform_id}", routing::get(get_platform))
+        .route("/tracks", routing::get(get_tracks))
+        .with_state(Arc::new(data_dir))
+}
+
+/// Runs the mock ODSS until interrupted.
+pub fn serve(port: u16, data_dir: PathBuf) -> Result<(), String> {
+    println!("Serving data from '{}'", data_dir.display());
+    println!("  odssApi = \"http://localhost:{port}\"");
+    super::serve("ODSS", port, create_mock_odss_router(data_dir))
+}
+
+/// Base URL of a mock ODSS with the fixtures, shared by all tests.
+#[cfg(test)]
+pub fn test_odss_api() -> String {
+    static ODSS_API: std::sync::OnceLock<String> = std::sync::OnceLock::new();
+    ODSS_API
+        .get_or_init(|| {
+            let data_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(DEFAULT_DATA_DIR);
+            super::spawn(create_mock_odss_router(data_dir))
+        })
+        .clone()
+}
+
+async fn get_platforms(State(data_dir): State<Arc<PathBuf>>) -> impl IntoResponse {
+    match file_source::get_platforms(&data_dir) {
+        Ok(platforms) => Json(platforms).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+    }
+}
+
+async fn get_platform(
+    State(data_dir): State<Arc<PathBuf>>,
+    Path(platform_id): Path<String>,
+) -> impl IntoResponse {
+    match file_source::get_platform(&data_dir, &platform_id) {
+        Ok(Some(platform)) => Json(platform).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "Platform not found").into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct TracksQuery {
+    #[serde(rename = "platformID")]
+    platform_id: String,
+    last_number_of_fixes: Option<u32>,
+    start_date: Option<String>,
+    end_date: Option<String>,
+}
+
+async fn get_tracks(
+    State(data_dir): State<Arc<PathBuf>>,
+    Query(query): Query<TracksQuery>,
+) -> impl IntoResponse {
+    log::debug!("mock ODSS: get_tracks: {query:?}");
+    match file_source::get_platform(&data_dir, &query.platform_id) {
+        Ok(Some(_)) => {}
+        Ok(None) => return (StatusCode::NOT_FOUND, "Platform not found").into_response(),
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+    }
+    let positions = file_source::get_positions(
+        &data_dir,
+        &query.platform_id,
+        query
+            .last_number_of_fixes
+            .unwrap_or(DEFAULT_LAST_NUMBER_OF_FIXES),
+        query.start_date.as_deref(),
+        query.end_date.as_deref(),
+    );
+    match positions {
+        Ok(positions) => Json(TrackRes::from_positions(&positions)).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
@@ -0,0 +1,252 @@
+//! Mock TethysDash API accepting `/async/xevent` notifications, which are recorded
+//! for inspection via `/mock/xevents`. Failures and latency can be injected via
+//! `/mock/faults`.
+
+use crate::tethysdash_client::XEvent;
+use crate::time_util;
+
+use axum::{
+    extract::State,
+    http::{header, HeaderMap},
+    response::IntoResponse,
+    routing, Json, Router,
+};
+use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Injected faults for the `/async/xevent` requests.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Faults {
+    /// Number of upcoming requests to fail.
+    #[serde(default)]
+    pub fail_next: u32,
+    /// If greater than zero, every n-th request fails.
+    #[serde(default)]
+    pub fail_every: u32,
+    /// Status for the failed requests. Default: 500.
+    #[serde(default)]
+    pub failure_status: Option<u16>,
+    /// Delay before responding to each request.
+    #[serde(default)]
+    pub latency_ms: u64,
+}
+
+/// An XEvent as received by the mock.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ReceivedXEvent {
+    pub received_ms: u64,
+    pub xevent: XEvent,
+}
+
+/// State of a mock TethysDash.
+#[derive(Debug, Default)]
+pub struct MockTethysDash {
+    /// If given, requests must have it as bearer token.
+    api_key: Option<String>,
+    faults: Mutex<Faults>,
+    num_requests: Mutex<u32>,
+    received: Mutex<Vec<ReceivedXEvent>>,
+}
+
+impl MockTethysDash {
+    pub fn new(api_key: Option<String>, faults: Faults) -> Self {
+        MockTethysDash {
+            api_key,
+            faults: Mutex::new(faults),
+            ..MockTethysDash::default()
+        }
+    }
+
+    pub fn get_received(&self) -> Vec<ReceivedXEvent> {
+        self.received.lock().unwrap().clone()
+    }
+
+    pub fn set_faults(&self, faults: Faults) {
+        *self.faults.lock().unwrap() = faults;
+    }
+
+    fn is_authorized(&self, headers: &HeaderMap) -> bool {
+        let Some(api_key) = &self.api_key else {
+            return true;
+        };
+        headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .is_some_and(|token| token == api_key)
+    }
+
+    /// Determines the latency and any failure status for the next request.
+    fn next_request(&self) -> (Duration, Option<StatusCode>) {
+        let mut faults = self.faults.lock().unwrap();
+        let mut num_requests = self.num_requests.lock().unwrap();
+        *num_requests += 1;
+        let fail = if faults.fail_next > 0 {
+            faults.fail_next -= 1;
+            true
+        } else {
+            faults.fail_every > 0 && num_requests.is_multiple_of(faults.fail_every)
+        };
+        let status = fail.then(|| {
+            faults
+                .failure_status
+                .and_then(|s| StatusCode::from_u16(s).ok())
+                .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+        });
+        (Duration::from_millis(faults.latency_ms), status)
+    }
+}
+
+pub fn create_mock_tethysdash_router(mock: Arc<MockTethysDash>) -> Router {
+    Router::new()
+        .route("/", routing::get(get_root))
+        .route("/async/xevent", routing::post(post_xevent))
+        .route(
+            "/mock/xevents",
+            routing::get(get_xevents).delete(delete_xevents),
+        )
+        .route("/mock/faults", routing::get(get_faults).put(put_faults))
+        .with_state(mock)
+}
+
+/// Runs a mock TethysDash until interrupted.
+pub fn serve(port: u16, api_key: Option<String>, faults: Faults) -> Result<(), String> {
+    println!("  api = \"http://localhost:{port}\"");
+    if api_key.is_none() {
+        println!("  (any API key accepted)");
+    }
+    let mock = Arc::new(MockTethysDash::new(api_key, faults));
+    super::serve("TethysDash", port, create_mock_tethysdash_router(mock))
+}
+
+/// Launches a mock TethysDash in a background thread, returning its base URL
+/// and its state for inspection.
+#[cfg(test)]
+pub fn spawn(api_key: Option<String>) -> (String, Arc<MockTethysDash>) {
+    let mock = Arc::new(MockTethysDash::new(api_key, Faults::default()));
+    let url = super::spawn(create_mock_tethysdash_router(Arc::clone(&mock)));
+    (url, mock)
+}
+
+/// API key of the mock TethysDash shared by all tests.
+#[cfg(test)]
+pub const TEST_API_KEY: &str = "test-api-key";
+
+/// Base URL and state of a mock TethysDash shared by all tests.
+#[cfg(test)]
+pub fn test_tethysdash() -> (String, Arc<MockTethysDash>) {
+    static MOCK: std::sync::OnceLock<(String, Arc<MockTethysDash>)> = std::sync::OnceLock::new();
+    MOCK.get_or_init(|| spawn(Some(TEST_API_KEY.to_string())))
+        .clone()
+}
+
+async fn get_root(State(mock): State<Arc<MockTethysDash>>, headers: HeaderMap) -> StatusCode {
+    if mock.is_authorized(&headers) {
+        StatusCode::OK
+    } else {
+        StatusCode::UNAUTHORIZED
+    }
+}
+
+async fn post_xevent(
+    State(mock): State<Arc<MockTethysDash>>,
+    headers: HeaderMap,
+    Json(xevent): Json<XEvent>,
+) -> impl IntoResponse {
+    log::debug!("mock TethysDash: post_xevent: {xevent:?}");
+    if !mock.is_authorized(&headers) {
+        return (StatusCode::UNAUTHORIZED, "Invalid API key").into_response();
+    }
+    let (latency, failure_status) = mock.next_request();
+    if !latency.is_zero() {
+        tokio::time::sleep(latency).await;
+    }
+    if let Some(status) = failure_status {
+        return (status, "Injected failure").into_response();
+    }
+    mock.received.lock().unwrap().push(ReceivedXEvent {
+        received_ms: time_util::now_ms(),
+        xevent,
+    });
+    StatusCode::OK.into_response()
+}
+
+async fn get_xevents(State(mock): State<Arc<MockTethysDash>>) -> Json<Vec<ReceivedXEvent>> {
+    Json(mock.get_received())
+}
+
+/// Clears the received XEvents, returning their number.
+async fn delete_xevents(State(mock): State<Arc<MockTethysDash>>) -> Json<usize> {
+    let mut received = mock.received.lock().unwrap();
+    let num_received = received.len();
+    received.clear();
+    Json(num_received)
+}
+
+async fn get_faults(State(mock): State<Arc<MockTethysDash>>) -> Json<Faults> {
+    Json(mock.faults.lock().unwrap().clone())
+}
+
+async fn put_faults(
+    State(mock): State<Arc<MockTethysDash>>,
+    Json(faults): Json<Faults>,
+) -> Json<Faults> {
+    log::info!("mock TethysDash: faults: {faults:?}");
+    mock.set_faults(faults.clone());
+    Json(faults)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TethysDashConfig;
+    use crate::secret::Secret;
+    use crate::tethysdash_client;
+    use crate::trackdb_client::Position;
+
+    #[test]
+    fn test_mock_tethysdash() {
+        let (api, mock) = spawn(Some("key".to_string()));
+        let tethysdash_config = |api_key: &str| TethysDashConfig {
+            name: "mock".to_string(),
+            api: api.clone(),
+            api_key: Secret::new(api_key),
+            api_key_file: None,
+            routing: None,
+            kinematics: false,
+        };
+        let xevent = XEvent {
+            asset_id: "p1".to_string(),
+            asset_name: "one".to_string(),
+            position: Position {
+                time_ms: 1_700_000_000_000,
+                lat: 36.8,
+                lon: -122.0,
+                kinematics: None,
+            },
+            type_name: None,
+            color: None,
+            icon_url: None,
+        };
+
+        assert!(tethysdash_client::probe(&tethysdash_config("bad")).is_err());
+        assert!(tethysdash_client::post_xevent(&tethysdash_config("bad"), xevent.clone()).is_err());
+
+        let valid = tethysdash_config("key");
+        assert!(tethysdash_client::probe(&valid).is_ok());
+        mock.set_faults(Faults {
+            fail_next: 1,
+            ..Faults::default()
+        });
+        assert!(tethysdash_client::post_xevent(&valid, xevent.clone()).is_err());
+        assert!(tethysdash_client::post_xevent(&valid, xevent).is_ok());
+
+        let received = mock.get_received();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].xevent.asset_id, "p1");
+    }
+}
@@ -1,4 +1,4 @@
-use crate::trackdb_client::PlatformRes;
+use crate::trackdb_client::{self, PlatformRes};
 
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
@@ -9,24 +9,71 @@ use std::collections::BTreeMap;
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct PlatformInfo {
-    info: BTreeMap<String, PlatformRes>,
+    /// Platforms by ID, per source.
+    info: BTreeMap<String, BTreeMap<String, PlatformRes>>,
 }
 
 impl PlatformInfo {
-    pub fn set_platforms(&mut self, platforms: Vec<PlatformRes>) {
-        self.info = platforms.into_iter().map(|p| (p._id.clone(), p)).collect();
+    /// Replaces the cached platforms of the given source.
+    pub fn set_platforms(&mut self, source: &str, platforms: Vec<PlatformRes>) {
+        let platforms = platforms.into_iter().map(|p| (p._id.clone(), p)).collect();
+        self.info.insert(source.to_string(), platforms);
+    }
+
+    /// Refreshes the cache from all sources, keeping the cached platforms of any
+    /// source that fails or returns no platforms.
+    /// Returns the names of the refreshed sources.
+    pub fn refresh(&mut self) -> Vec<String> {
+        self.apply_refresh(trackdb_client::get_platforms_by_source())
+    }
+
+    /// Like [PlatformInfo::refresh], but with the platforms already fetched with
+    /// [trackdb_client::get_platforms_by_source], so a shared cache need not be
+    /// locked during the requests.
+    pub fn apply_refresh(
+        &mut self,
+        platforms_by_source: Vec<(String, Result<Vec<PlatformRes>, String>)>,
+    ) -> Vec<String> {
+        let mut refreshed = Vec::new();
+        for (source, platforms) in platforms_by_source {
+            match platforms {
+                Ok(platforms) if !platforms.is_empty() => {
+                    log::info!("source '{source}': {} platforms", platforms.len());
+                    self.set_platforms(&source, platforms);
+                    refreshed.push(source);
+                }
+                Ok(_) => log::warn!("source '{source}': no platforms returned"),
+                Err(e) => log::warn!("source '{source}': {e}"),
+            }
+        }
+        refreshed
     }
 
     pub fn get_platforms(&self) -> Vec<PlatformRes> {
-        self.info.values().cloned().collect()
+        self.info
+            .values()
+            .flat_map(|p| p.values())
+            .cloned()
+            .collect()
+    }
+
+    /// The cached platforms of the given source.
+    pub fn get_source_platforms(&self, source: &str) -> Vec<PlatformRes> {
+        self.info
+            .get(source)
+            .map(|p| p.values().cloned().collect())
+            .unwrap_or_default()
     }
 
     pub fn get_platform(&self, platform_id: &str) -> Option<PlatformRes> {
-        self.info.get(platform_id).cloned()
+        self.info.values().find_map(|p| p.get(platform_id)).cloned()
     }
 
     pub fn update_platform(&mut self, platform_res: &PlatformRes) {
+        let source = trackdb_client::source_of(&platform_res._id);
         self.info
+            .entry(source)
+            .or_default()
             .insert(platform_res._id.clone(), platform_res.clone());
     }
 }
@@ -0,0 +1,131 @@
+use crate::config::Config;
+use crate::trackdb_client::{PlatformRes, Position};
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+type PlatformId = String;
+
+/// Keeps track of when each dispatched platform is next due for polling.
+#[derive(Default)]
+pub struct PollSchedule {
+    next_polls: BTreeMap<PlatformId, Instant>,
+}
+
+impl PollSchedule {
+    /// A platform not yet scheduled is due right away.
+    pub fn is_due(&self, platform_id: &str, now: Instant) -> bool {
+        match self.next_polls.get(platform_id) {
+            Some(next_poll) => *next_poll <= now,
+            None => true,
+        }
+    }
+
+    pub fn schedule(&mut self, platform_id: &str, now: Instant, period: Duration) {
+        self.next_polls
+            .insert(platform_id.to_string(), now + period);
+    }
+
+    /// Forgets any platforms no longer dispatched.
+    pub fn retain(&mut self, platform_ids: &[PlatformId]) {
+        self.next_polls.retain(|id, _| platform_ids.contains(id));
+    }
+
+    /// Time until the next scheduled poll, if any.
+    pub fn time_to_next_poll(&self, now: Instant) -> Option<Duration> {
+        self.next_polls
+            .values()
+            .min()
+            .map(|next_poll| next_poll.saturating_duration_since(now))
+    }
+}
+
+/// Determines the poll period for a platform, in order of precedence:
+/// - the period given in `dispatched.json` for the platform
+/// - the period given in the configuration for the platform ID or type
+/// - the adapted period per observed fix cadence, if adaptive polling is enabled
+/// - the general `pollPeriod`
+pub fn poll_period_for(
+    config: &Config,
+    dispatched_period: Option<Duration>,
+    platform: &PlatformRes,
+    positions: &[Position],
+) -> Duration {
+    if let Some(period) = dispatched_period {
+        return period;
+    }
+    let type_name = platform.type_name.as_deref();
+    if let Some(period) = config.poll_period_override(&platform._id, type_name) {
+        return period;
+    }
+    match (&config.adaptive_polling, observed_cadence(positions)) {
+        (Some(adaptive), Some(cadence)) => {
+            adaptive_period(config.poll_period, cadence, adaptive.max_poll_period)
+        }
+        _ => config.poll_period,
+    }
+}
+
+/// Median interval between consecutive fixes, if at least two fixes are given.
+fn observed_cadence(positions: &[Position]) -> Option<u64> {
+    let mut times = positions.iter().map(|p| p.time_ms).collect::<Vec<_>>();
+    times.sort();
+    times.dedup();
+    let mut intervals = times.windows(2).map(|w| w[1] - w[0]).collect::<Vec<_>>();
+    if intervals.is_empty() {
+        return None;
+    }
+    intervals.sort();
+    Some(intervals[intervals.len() / 2])
+}
+
+/// Polls at twice the rate of the observed cadence (in milliseconds), but never faster
+/// than the base period, so fast movers keep the usual latency, and never slower than
+/// the given maximum.
+fn adaptive_period(base: Duration, cadence_ms: u64, max: Duration) -> Duration {
+    let half_cadence = Duration::from_millis(cadence_ms / 2);
+    half_cadence.min(max).max(base)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn position(time_ms: u64) -> Position {
+        Position {
+            time_ms,
+            lat: 36.8,
+            lon: -121.9,
+            kinematics: None,
+        }
+    }
+
+    #[test]
+    fn test_observed_cadence() {
+        assert_eq!(observed_cadence(&[]), None);
+        assert_eq!(observed_cadence(&[position(1000)]), None);
+        let positions = [
+            position(10_000),
+            position(0),
+            position(4_000),
+            position(2_000),
+        ];
+        assert_eq!(observed_cadence(&positions), Some(2_000));
+    }
+
+    #[test]
+    fn test_adaptive_period() {
+        let base = Duration::from_secs(30);
+        let max = Duration::from_secs(600);
+        // fast mover keeps the base period:
+        assert_eq!(adaptive_period(base, 10_000, max), base);
+        // moderate cadence:
+        assert_eq!(
+            adaptive_period(base, 240_000, max),
+            Duration::from_secs(120)
+        );
+        // slow mover limited by the maximum:
+        assert_eq!(adaptive_period(base, 3 * 3600 * 1000, max), max);
+    }
+}
@@ -0,0 +1,422 @@
+//! Proximity analysis between dispatched platforms, e.g., AUVs operating near ships
+//! or moorings: for the pairs of platforms selected by each rule, the current separation
+//! and the projected closest point of approach (CPA), from the velocities per their
+//! recent fixes, raising alerts when below the distance threshold of the rule. Pairs with
+//! a fix older than the `maxFixAge` of the rule are only flagged as stale.
+//! The resulting report is kept under the state directory (available via
+//! `/api/proximity`), so restarts do not re-fire the alerts.
+
+use crate::alerts::{Alert, AlertKind};
+use crate::config;
+use crate::geo;
+use crate::routing::PlatformFilter;
+use crate::state_file;
+use crate::trackdb_client::{self, PlatformRes, Position};
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use utoipa::ToSchema;
+
+/// File under the state directory with the latest proximity report.
+const REPORT_FILENAME: &str = "proximity.json";
+
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ProximityRule {
+    pub name: String,
+    /// The platforms, with all the pairs among them checked, unless `with` is given.
+    pub platforms: PlatformFilter,
+    /// If given, only the pairs of one of `platforms` and one of these are checked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub with: Option<PlatformFilter>,
+    /// Threshold in meters for the separation and the projected CPA distance.
+    pub distance: f64,
+    /// How far ahead to project the closest point of approach.
+    #[serde(default = "default_horizon", with = "humantime_serde")]
+    pub horizon: Duration,
+    /// A pair with a fix older than this is flagged as stale, and not projected.
+    #[serde(default = "default_max_fix_age", with = "humantime_serde")]
+    pub max_fix_age: Duration,
+}
+
+fn default_horizon() -> Duration {
+    Duration::from_secs(30 * 60)
+}
+
+fn default_max_fix_age() -> Duration {
+    Duration::from_secs(60 * 60)
+}
+
+impl ProximityRule {
+    /// Checks the rule, returning any problems found.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let setting = format!("proximity['{}']", self.name);
+        if self.name.is_empty() {
+            problems.push(format!("{setting}: empty name"));
+        }
+        if self.platforms == PlatformFilter::default() {
+            problems.push(format!("{setting}.platforms: no platforms selected"));
+        }
+        if self.distance.is_nan() || self.distance <= 0.0 {
+            problems.push(format!("{setting}.distance must be greater than zero"));
+        }
+        if self.horizon.is_zero() {
+            problems.push(format!("{setting}.horizon must be greater than zero"));
+        }
+        if self.max_fix_age.is_zero() {
+            problems.push(format!("{setting}.maxFixAge must be greater than zero"));
+        }
+        problems
+    }
+
+    /// The pairs of the given platforms selected by the rule.
+    fn pairs<'a>(&self, platforms: &'a [PlatformRes]) -> Vec<(&'a PlatformRes, &'a PlatformRes)> {
+        let members = platforms
+            .iter()
+            .filter(|p| self.platforms.matches_platform(p))
+            .collect::<Vec<_>>();
+        let mut pairs = Vec::new();
+        match &self.with {
+            Some(with) => {
+                for a in &members {
+                    for b in platforms.iter().filter(|p| with.matches_platform(p)) {
+                        let duplicate =
+                            pairs.iter().any(|(x, y): &(&PlatformRes, &PlatformRes)| {
+                                x._id == b._id && y._id == a._id
+                            });
+                        if a._id != b._id && !duplicate {
+                            pairs.push((*a, b));
+                        }
+                    }
+                }
+            }
+            None => {
+                for (i, a) in members.iter().enumerate() {
+                    for b in &members[i + 1..] {
+                        pairs.push((*a, *b));
+                    }
+                }
+            }
+        }
+        pairs
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PairProximity {
+    /// The proximity rule selecting the pair.
+    pub rule: String,
+    pub platform_ids: Vec<String>,
+    pub platform_names: Vec<String>,
+    /// Time of the latest fix of the two, when the separation is determined.
+    pub time_ms: u64,
+    /// Separation in meters, with the platform with the older fix projected to `timeMs`.
+    pub distance: f64,
+    /// Projected time of the closest point of approach, within the horizon of the rule.
+    pub cpa_time_ms: u64,
+    /// Projected distance in meters at the closest point of approach.
+    pub cpa_distance: f64,
+    /// Whether the separation or the CPA distance is below the threshold of the rule.
+    /// For a stale pair, this is as before, with no alert raised or cleared.
+    pub alert: bool,
+    /// Whether the fix of either platform is older than the `maxFixAge` of the rule,
+    /// in which case the separation is between the latest fixes as they are, and the
+    /// CPA distance the same.
+    #[serde(default)]
+    pub stale: bool,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ProximityReport {
+    /// When the report was determined.
+    pub updated_ms: u64,
+    pub pairs: Vec<PairProximity>,
+}
+
+/// The report last saved by the dispatcher, if any.
+pub fn load_report() -> Option<ProximityReport> {
+    let path = config::get_config().state_path(REPORT_FILENAME);
+    state_file::load_json(&path)
+}
+
+pub fn save_report(report: &ProximityReport) {
+    let path = config::get_config().state_path(REPORT_FILENAME);
+    if let Err(e) = state_file::save_json(&path, report) {
+        log::error!("Failed to save proximity report: {e}");
+    }
+}
+
+/// The latest fix and the velocity (speed in m/s, course in degrees) per the given
+/// recent fixes in chronological order, with no velocity if only one fix is given.
+fn latest_and_velocity(fixes: &[Position]) -> Option<(Position, (f64, f64))> {
+    let mut recent = fixes[fixes.len().saturating_sub(2)..].to_vec();
+    trackdb_client::add_kinematics(None, &mut recent);
+    let latest = recent.pop()?;
+    let velocity = latest
+        .kinematics
+        .as_ref()
+        .and_then(|k| k.sog.zip(k.cog))
+        .unwrap_or((0.0, 0.0));
+    Some((latest, velocity))
+}
+
+/// Keeps track of the pairs of platforms getting close or apart.
+#[derive(Default)]
+pub struct ProximityMonitor {
+    /// Whether each pair was in alert per the previous report, loaded upon first use.
+    previous: Option<BTreeMap<(String, Vec<String>), bool>>,
+}
+
+impl ProximityMonitor {
+    /// Determines the proximity of the pairs selected by the rules among the given
+    /// platforms, each with its recent fixes in chronological order, also returning the
+    /// alerts for any pair getting below or back above the threshold.
+    pub fn evaluate(
+        &mut self,
+        rules: &[ProximityRule],
+        platforms: &[(PlatformRes, Vec<Position>)],
+        now_ms: u64,
+    ) -> (ProximityReport, Vec<Alert>) {
+        let previous = self.previous.get_or_insert_with(|| {
+            load_report()
+                .map(|r| r.pairs)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|p| ((p.rule, p.platform_ids), p.alert))
+                .collect()
+        });
+        let tracks = platforms
+            .iter()
+            .filter_map(|(p, fixes)| Some((p._id.clone(), latest_and_velocity(fixes)?)))
+            .collect::<BTreeMap<_, _>>();
+        let with_fixes = platforms
+            .iter()
+            .map(|(p, _)| p.clone())
+            .filter(|p| tracks.contains_key(&p._id))
+            .collect::<Vec<_>>();
+
+        let mut pairs = Vec::new();
+        let mut alerts = Vec::new();
+        for rule in rules {
+            for (a, b) in rule.pairs(&with_fixes) {
+                let mut pair = pair_proximity(rule, a, &tracks[&a._id], b, &tracks[&b._id], now_ms);
+                let key = (pair.rule.clone(), pair.platform_ids.clone());
+                let was_alert = previous.get(&key).copied().unwrap_or(false);
+                if pair.stale {
+                    pair.alert = was_alert;
+                } else if pair.alert != was_alert {
+                    alerts.push(proximity_alert(&pair, a, b, now_ms));
+                }
+                pairs.push(pair);
+            }
+        }
+        *previous = pairs
+            .iter()
+            .map(|p| ((p.rule.clone(), p.platform_ids.clone()), p.alert))
+            .collect();
+        let report = ProximityReport {
+            updated_ms: now_ms,
+            pairs,
+        };
+        (report, alerts)
+    }
+}
+
+fn pair_proximity(
+    rule: &ProximityRule,
+    a: &PlatformRes,
+    (a_fix, a_velocity): &(Position, (f64, f64)),
+    b: &PlatformRes,
+    (b_fix, b_velocity): &(Position, (f64, f64)),
+    now_ms: u64,
+) -> PairProximity {
+    let time_ms = a_fix.time_ms.max(b_fix.time_ms);
+    let oldest_ms = a_fix.time_ms.min(b_fix.time_ms);
+    let stale = now_ms.saturating_sub(oldest_ms) > rule.max_fix_age.as_millis() as u64;
+    if stale {
+        // not extrapolating old fixes:
+        let distance = geo::distance_m((a_fix.lat, a_fix.lon), (b_fix.lat, b_fix.lon));
+        return PairProximity {
+            rule: rule.name.clone(),
+            platform_ids: vec![a._id.clone(), b._id.clone()],
+            platform_names: vec![a.name.clone(), b.name.clone()],
+            time_ms,
+            distance,
+            cpa_time_ms: time_ms,
+            cpa_distance: distance,
+            alert: false,
+            stale,
+        };
+    }
+    let project = |fix: &Position, (speed, course): (f64, f64)| {
+        let elapsed_s = (time_ms - fix.time_ms) as f64 / 1000.0;
+        geo::destination((fix.lat, fix.lon), course, speed * elapsed_s)
+    };
+    let a_point = project(a_fix, *a_velocity);
+    let b_point = project(b_fix, *b_velocity);
+    let horizon_s = rule.horizon.as_secs_f64();
+    let (cpa_s, cpa_distance) =
+        geo::closest_approach(a_point, *a_velocity, b_point, *b_velocity, horizon_s);
+    let distance = geo::distance_m(a_point, b_point);
+    PairProximity {
+        rule: rule.name.clone(),
+        platform_ids: vec![a._id.clone(), b._id.clone()],
+        platform_names: vec![a.name.clone(), b.name.clone()],
+        time_ms,
+        distance,
+        cpa_time_ms: time_ms + (cpa_s * 1000.0).round() as u64,
+        cpa_distance,
+        alert: distance < rule.distance || cpa_distance < rule.distance,
+        stale,
+    }
+}
+
+fn proximity_alert(pair: &PairProximity, a: &PlatformRes, b: &PlatformRes, now_ms: u64) -> Alert {
+    let (kind, what) = if pair.alert {
+        let cpa_in = Duration::from_secs(pair.cpa_time_ms.saturating_sub(pair.time_ms) / 1000);
+        let what = format!(
+            "{:.0} m apart, CPA {:.0} m in {}",
+            pair.distance,
+            pair.cpa_distance,
+            humantime::format_duration(cpa_in)
+        );
+        (AlertKind::Proximity, what)
+    } else {
+        let what = format!("{:.0} m apart, no longer close", pair.distance);
+        (AlertKind::ProximityCleared, what)
+    };
+    Alert {
+        time_ms: pair.time_ms,
+        raised_ms: now_ms,
+        kind,
+        platform_id: a._id.clone(),
+        platform_name: a.name.clone(),
+        geofence: None,
+        other_platform_id: Some(b._id.clone()),
+        message: format!(
+            "Proximity '{}': {} ({}) and {} ({}): {what}",
+            pair.rule, a.name, a._id, b.name, b._id
+        ),
+        position: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_proximity() {
+        let platform = |id: &str, type_name: &str| PlatformRes {
+            _id: id.to_string(),
+            name: id.to_string(),
+            abbreviation: id.to_string(),
+            type_name: Some(type_name.to_string()),
+            color: None,
+            icon_url: None,
+        };
+        let fix = |time_s: u64, (lat, lon): geo::LatLon| Position {
+            time_ms: time_s * 1000,
+            lat,
+            lon,
+            kinematics: None,
+        };
+        let rule = ProximityRule {
+            name: "auv-ship".to_string(),
+            platforms: PlatformFilter {
+                type_names: vec!["lrauv".to_string()],
+                ..PlatformFilter::default()
+            },
+            with: Some(PlatformFilter {
+                type_names: vec!["ship".to_string(), "mooring".to_string()],
+                ..PlatformFilter::default()
+            }),
+            distance: 500.0,
+            horizon: default_horizon(),
+            max_fix_age: default_max_fix_age(),
+        };
+        let mooring_at = (36.8, -122.0);
+        // the ship heading east at 5 m/s, 3 km west of the mooring, passing 200 m north;
+        // the AUV drifting 5 km north of the mooring:
+        let ship_at = |time_s: u64| {
+            let start = geo::destination(geo::destination(mooring_at, 270.0, 3000.0), 0.0, 200.0);
+            geo::destination(start, 90.0, 5.0 * time_s as f64)
+        };
+        let auv_at = geo::destination(mooring_at, 0.0, 5000.0);
+        let platforms = vec![
+            (
+                platform("auv", "lrauv"),
+                vec![fix(0, auv_at), fix(60, auv_at)],
+            ),
+            (
+                platform("ship", "ship"),
+                vec![fix(0, ship_at(0)), fix(60, ship_at(60))],
+            ),
+            (platform("mooring", "mooring"), vec![fix(30, mooring_at)]),
+        ];
+        let mut monitor = ProximityMonitor {
+            previous: Some(BTreeMap::new()),
+        };
+        let (report, alerts) = monitor.evaluate(std::slice::from_ref(&rule), &platforms, 100_000);
+        let summary = report
+            .pairs
+            .iter()
+            .map(|p| (p.platform_ids.join("-"), p.alert))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                ("auv-ship".to_string(), false),
+                ("auv-mooring".to_string(), false)
+            ]
+        );
+        assert!(alerts.is_empty());
+
+        // mooring and ship: the ship reaches its CPA 200 m off the mooring in 540 s
+        let rule = ProximityRule {
+            platforms: PlatformFilter {
+                type_names: vec!["ship".to_string(), "mooring".to_string()],
+                ..PlatformFilter::default()
+            },
+            with: None,
+            ..rule
+        };
+        let (report, alerts) = monitor.evaluate(std::slice::from_ref(&rule), &platforms, 100_000);
+        let pair = &report.pairs[0];
+        assert_eq!(pair.platform_ids, vec!["ship", "mooring"]);
+        assert!((pair.distance - 2707.0).abs() < 5.0, "{}", pair.distance);
+        assert!(
+            (pair.cpa_distance - 200.0).abs() < 1.0,
+            "{}",
+            pair.cpa_distance
+        );
+        let cpa_in_s = (pair.cpa_time_ms - pair.time_ms) as f64 / 1000.0;
+        assert!((cpa_in_s - 540.0).abs() < 1.0, "{cpa_in_s}");
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].kind, AlertKind::Proximity);
+
+        // no alert again while in proximity:
+        let (_, alerts) = monitor.evaluate(std::slice::from_ref(&rule), &platforms, 100_000);
+        assert!(alerts.is_empty());
+
+        // no new fixes for longer than maxFixAge: flagged, not projected, nor cleared
+        let later_ms = 100_000 + 2 * 3600 * 1000;
+        let (report, alerts) = monitor.evaluate(std::slice::from_ref(&rule), &platforms, later_ms);
+        let pair = &report.pairs[0];
+        assert!(pair.stale);
+        assert!(pair.alert);
+        assert!((pair.distance - 2707.0).abs() < 5.0, "{}", pair.distance);
+        assert_eq!(pair.cpa_distance, pair.distance);
+        assert!(alerts.is_empty());
+
+        // when fresh again, back to the projection, with no repeated alert:
+        let (report, alerts) = monitor.evaluate(&[rule], &platforms, 100_000);
+        assert!(!report.pairs[0].stale);
+        assert!(alerts.is_empty());
+    }
+}
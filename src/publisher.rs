@@ -1,11 +1,19 @@
 use crate::config::TethysDashConfig;
 use crate::tethysdash_client::XEvent;
 
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
 pub type PostXEventFn = fn(&TethysDashConfig, XEvent) -> Result<(), String>;
 
+/// XEvents per TethysDash instance name.
+pub type XEventsByDestination = BTreeMap<String, Vec<XEvent>>;
+
 pub struct Publisher {
     post_xevent: PostXEventFn,
     tethysdashes: Vec<TethysDashConfig>,
+    /// If given, XEvents are only collected here instead of being posted.
+    dry_run: Option<Mutex<XEventsByDestination>>,
 }
 
 impl Publisher {
@@ -13,14 +21,65 @@ impl Publisher {
         Publisher {
             post_xevent,
             tethysdashes,
+            dry_run: None,
         }
     }
 
-    pub fn publish_xevent(&self, xevent: XEvent) -> Result<(), String> {
+    /// Makes the publisher only collect the XEvents that would be posted.
+    pub fn set_dry_run(&mut self) {
+        self.dry_run = Some(Mutex::new(XEventsByDestination::new()));
+    }
+
+    /// Returns the XEvents collected in dry-run mode since the last call.
+    pub fn take_dry_run_xevents(&self) -> XEventsByDestination {
+        self.dry_run
+            .as_ref()
+            .map(|collected| std::mem::take(&mut *collected.lock().unwrap()))
+            .unwrap_or_default()
+    }
+
+    /// Applies an updated list of TethysDash instances, e.g., upon configuration reload.
+    pub fn set_tethysdashes(&mut self, tethysdashes: Vec<TethysDashConfig>) {
+        self.tethysdashes = tethysdashes;
+    }
+
+    /// Posts the XEvent to the TethysDash instances per their routing rules, returning
+    /// the names of those that successfully received it. `dispatched` indicates whether
+    /// the platform is in the general dispatched list, which is what instances without
+    /// their own dispatch list get. In dry-run mode, nothing is posted, and the names
+    /// are those of the instances that would have received it.
+    pub fn publish_xevent(&self, xevent: XEvent, dispatched: bool) -> Result<Vec<String>, String> {
         let post_xevent = self.post_xevent;
+        let mut sent_to = Vec::new();
         for tethysdash_config in &self.tethysdashes {
-            match post_xevent(tethysdash_config, xevent.clone()) {
-                Ok(_) => (),
+            let accepted = match &tethysdash_config.routing {
+                Some(routing) => routing.accepts(&xevent, dispatched),
+                None => dispatched,
+            };
+            if !accepted {
+                log::debug!(
+                    "Not routing {} to TethysDash instance '{}'",
+                    xevent.asset_id,
+                    tethysdash_config.name
+                );
+                continue;
+            }
+            let mut xevent = xevent.clone();
+            if !tethysdash_config.kinematics {
+                xevent.position.kinematics = None;
+            }
+            if let Some(collected) = &self.dry_run {
+                collected
+                    .lock()
+                    .unwrap()
+                    .entry(tethysdash_config.name.clone())
+                    .or_default()
+                    .push(xevent);
+                sent_to.push(tethysdash_config.name.clone());
+                continue;
+            }
+            match post_xevent(tethysdash_config, xevent) {
+                Ok(_) => sent_to.push(tethysdash_config.name.clone()),
                 Err(e) => {
                     log::error!(
                         "Error posting XEvent to TethysDash instance '{}': {}",
@@ -30,6 +89,6 @@ impl Publisher {
                 }
             }
         }
-        Ok(())
+        Ok(sent_to)
     }
 }
@@ -0,0 +1,162 @@
+use crate::tethysdash_client::XEvent;
+use crate::trackdb_client::PlatformRes;
+
+use serde::{Deserialize, Serialize};
+
+/// Rules to determine which platforms are sent to a TethysDash instance.
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct RoutingRules {
+    /// If given, only platforms matching this filter are sent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub include: Option<PlatformFilter>,
+    /// Platforms matching this filter are not sent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exclude: Option<PlatformFilter>,
+    /// If given, the instance gets the platforms in this list (which are then also
+    /// polled by the dispatcher), instead of the general dispatched platforms.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dispatch_list: Option<Vec<String>>,
+}
+
+/// Matches platforms by any of the given IDs, name patterns, or type names.
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PlatformFilter {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub platform_ids: Vec<String>,
+    /// Case-insensitive patterns for the platform name, with `*` matching any
+    /// sequence of characters and `?` any single character.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub name_patterns: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub type_names: Vec<String>,
+}
+
+impl PlatformFilter {
+    pub fn matches(&self, xevent: &XEvent) -> bool {
+        self.matches_fields(
+            &xevent.asset_id,
+            &xevent.asset_name,
+            xevent.type_name.as_ref(),
+        )
+    }
+
+    pub fn matches_platform(&self, platform: &PlatformRes) -> bool {
+        self.matches_fields(&platform._id, &platform.name, platform.type_name.as_ref())
+    }
+
+    fn matches_fields(&self, id: &String, name: &str, type_name: Option<&String>) -> bool {
+        self.platform_ids.contains(id)
+            || self.name_patterns.iter().any(|p| wildcard_match(p, name))
+            || type_name.is_some_and(|t| self.type_names.contains(t))
+    }
+}
+
+impl RoutingRules {
+    /// Whether the given XEvent is to be sent per these rules.
+    /// `dispatched` indicates whether the platform is in the general dispatched list.
+    pub fn accepts(&self, xevent: &XEvent, dispatched: bool) -> bool {
+        let in_scope = match &self.dispatch_list {
+            Some(list) => list.contains(&xevent.asset_id),
+            None => dispatched,
+        };
+        in_scope
+            && self.include.as_ref().is_none_or(|f| f.matches(xevent))
+            && !self.exclude.as_ref().is_some_and(|f| f.matches(xevent))
+    }
+}
+
+/// Case-insensitive match with `*` and `?` wildcards.
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.to_lowercase().chars().collect::<Vec<_>>();
+    let text = text.to_lowercase().chars().collect::<Vec<_>>();
+    // position in pattern and text after the last `*`, for backtracking:
+    let mut star: Option<(usize, usize)> = None;
+    let (mut p, mut t) = (0, 0);
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p + 1, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trackdb_client::Position;
+
+    fn xevent(asset_id: &str, asset_name: &str, type_name: Option<&str>) -> XEvent {
+        XEvent {
+            asset_id: asset_id.to_string(),
+            asset_name: asset_name.to_string(),
+            position: Position {
+                time_ms: 0,
+                lat: 0.0,
+                lon: 0.0,
+                kinematics: None,
+            },
+            type_name: type_name.map(|t| t.to_string()),
+            color: None,
+            icon_url: None,
+        }
+    }
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("lrauv-*", "LRAUV-Daphne"));
+        assert!(wildcard_match("*ship*", "R/V Western Flyer ship"));
+        assert!(wildcard_match("wg?", "wg1"));
+        assert!(!wildcard_match("wg?", "wg12"));
+        assert!(!wildcard_match("lrauv-*", "tethys"));
+        assert!(wildcard_match("*", ""));
+    }
+
+    #[test]
+    fn test_routing_rules() {
+        let lrauv = xevent("p1", "lrauv-daphne", Some("auv"));
+        let ship = xevent("p2", "Rachel Carson", Some("ship"));
+
+        let no_rules = RoutingRules::default();
+        assert!(no_rules.accepts(&lrauv, true));
+        assert!(!no_rules.accepts(&lrauv, false));
+
+        let no_ships = RoutingRules {
+            exclude: Some(PlatformFilter {
+                type_names: vec!["ship".to_string()],
+                ..PlatformFilter::default()
+            }),
+            ..RoutingRules::default()
+        };
+        assert!(no_ships.accepts(&lrauv, true));
+        assert!(!no_ships.accepts(&ship, true));
+
+        let only_lrauvs = RoutingRules {
+            include: Some(PlatformFilter {
+                name_patterns: vec!["LRAUV-*".to_string()],
+                ..PlatformFilter::default()
+            }),
+            ..RoutingRules::default()
+        };
+        assert!(only_lrauvs.accepts(&lrauv, true));
+        assert!(!only_lrauvs.accepts(&ship, true));
+
+        let own_list = RoutingRules {
+            dispatch_list: Some(vec!["p2".to_string()]),
+            ..RoutingRules::default()
+        };
+        assert!(!own_list.accepts(&lrauv, true));
+        assert!(own_list.accepts(&ship, false));
+    }
+}
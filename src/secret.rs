@@ -0,0 +1,58 @@
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+
+const REDACTED: &str = "REDACTED";
+
+/// A secret value, like an API key, which is redacted in `Debug` and `Serialize` output.
+/// Use [Secret::expose] to get the actual value.
+#[derive(Deserialize, Clone, PartialEq, Eq, Default)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Self {
+        Secret(value.into())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret({REDACTED})")
+    }
+}
+
+impl Serialize for Secret {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.0.is_empty() {
+            serializer.serialize_str("")
+        } else {
+            serializer.serialize_str(REDACTED)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_secret_is_redacted() {
+        let secret = Secret::new("eyFoo");
+        assert_eq!(secret.expose(), "eyFoo");
+        assert_eq!(format!("{secret:?}"), "Secret(REDACTED)");
+        assert_eq!(serde_json::to_string(&secret).unwrap(), r#""REDACTED""#);
+        assert_eq!(
+            serde_json::from_str::<Secret>(r#""eyBaz""#).unwrap(),
+            Secret::new("eyBaz")
+        );
+    }
+}
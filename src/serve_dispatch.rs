@@ -1,9 +1,10 @@
+use crate::alerts;
+use crate::config;
 use crate::dispatched_info::DispatchedInfo;
 use crate::dispatcher::Dispatcher;
 use crate::platform_info::PlatformInfo;
 use crate::server;
 use crate::tethysdash_client::post_xevent;
-use crate::trackdb_client;
 use std::{
     sync::{mpsc, Arc, Mutex},
     thread,
@@ -11,48 +12,79 @@ use std::{
 };
 
 /// Runs a single dispatch if once is true, otherwise runs dispatch in a loop.
-pub fn dispatch(once: bool) {
+/// With dry_run, the XEvents are only printed, and no state is saved.
+pub fn dispatch(once: bool, dry_run: bool) {
     let platform_info = create_platform_info();
     let dispatched_info = create_dispatched_info();
-    let dispatcher = create_dispatcher(platform_info, dispatched_info);
+    let mut dispatcher = create_dispatcher(platform_info, dispatched_info, dry_run);
     if once {
         dispatcher.launch_one_dispatch();
+        alerts::wait_for_posts(Duration::from_secs(10));
     } else {
+        spawn_config_reload_on_sighup();
         dispatcher.launch_dispatch(None);
     }
 }
 
 /// Serves the odss2dash service.
 /// If no_dispatch is true, only the server is launched.
-pub fn serve(no_dispatch: bool) {
+/// With dry_run, the dispatcher only prints the XEvents, and saves no state.
+pub fn serve(no_dispatch: bool, dry_run: bool) {
+    spawn_config_reload_on_sighup();
     if no_dispatch {
         serve_only();
     } else {
-        serve_and_dispatch();
+        serve_and_dispatch(dry_run);
     }
 }
 
-/// Initializes platform info cache via query to TrackingDB/ODSS.
+/// Reloads the configuration upon SIGHUP.
+#[cfg(unix)]
+fn spawn_config_reload_on_sighup() {
+    use tokio::signal::unix::{signal, SignalKind};
+    thread::spawn(|| {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("runtime for signal handling");
+        runtime.block_on(async {
+            let mut hangup = signal(SignalKind::hangup()).expect("SIGHUP handler installed");
+            while hangup.recv().await.is_some() {
+                println!("\nReceived SIGHUP, reloading configuration");
+                match config::reload_config() {
+                    Ok(changes) if changes.is_empty() => {
+                        println!("No changes in configuration");
+                    }
+                    Ok(changes) => {
+                        println!("Configuration reloaded:");
+                        for change in changes {
+                            println!("  {change}");
+                        }
+                    }
+                    Err(e) => eprintln!("error: {e}"),
+                }
+            }
+        });
+    });
+}
+
+#[cfg(not(unix))]
+fn spawn_config_reload_on_sighup() {}
+
+/// Initializes platform info cache via query to the TrackingDB/ODSS sources.
 fn create_platform_info() -> Arc<Mutex<PlatformInfo>> {
-    let platform_info = Arc::new(Mutex::new(PlatformInfo::default()));
-    let platforms_res = trackdb_client::get_platforms();
-    if platforms_res.is_empty() {
+    let mut platform_info = PlatformInfo::default();
+    let refreshed = platform_info.refresh();
+    if refreshed.is_empty() {
         eprintln!("warning: no platforms returned from TrackingDB/ODSS");
     } else {
-        platform_info
-            .lock()
-            .map(|mut platform_info| {
-                println!(
-                    "Initializing platform cache with {} platforms found in TrackingDB/ODSS",
-                    platforms_res.len()
-                );
-                platform_info.set_platforms(platforms_res);
-            })
-            .unwrap_or_else(|e| {
-                eprintln!("unexpected: failed to acquire platform_info lock: {}", e);
-            })
+        println!(
+            "Initializing platform cache with {} platforms found in TrackingDB/ODSS (sources: {})",
+            platform_info.get_platforms().len(),
+            refreshed.join(", ")
+        );
     }
-    platform_info
+    Arc::new(Mutex::new(platform_info))
 }
 
 fn create_dispatched_info() -> Arc<Mutex<DispatchedInfo>> {
@@ -62,8 +94,13 @@ fn create_dispatched_info() -> Arc<Mutex<DispatchedInfo>> {
 fn create_dispatcher(
     platform_info: Arc<Mutex<PlatformInfo>>,
     dispatched_info: Arc<Mutex<DispatchedInfo>>,
+    dry_run: bool,
 ) -> Dispatcher {
-    Dispatcher::new(post_xevent, platform_info, dispatched_info)
+    let mut dispatcher = Dispatcher::new(post_xevent, platform_info, dispatched_info);
+    if dry_run {
+        dispatcher.set_dry_run();
+    }
+    dispatcher
 }
 
 fn serve_only() {
@@ -72,7 +109,7 @@ fn serve_only() {
     server::launch_server(platform_info, dispatched_info, None);
 }
 
-fn serve_and_dispatch() {
+fn serve_and_dispatch(dry_run: bool) {
     let platform_info = create_platform_info();
     let dispatched_info = create_dispatched_info();
 
@@ -89,7 +126,8 @@ fn serve_and_dispatch() {
     let dispatch_handle = {
         thread::spawn(move || {
             thread::sleep(Duration::from_secs(1));
-            create_dispatcher(platform_info, dispatched_info).launch_dispatch(Some(done_receiver));
+            create_dispatcher(platform_info, dispatched_info, dry_run)
+                .launch_dispatch(Some(done_receiver));
         })
     };
 
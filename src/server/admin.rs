@@ -0,0 +1,124 @@
+use crate::config::{self, ReloadError};
+use crate::secret::Secret;
+
+use axum::{
+    extract::ConnectInfo,
+    http::{header, HeaderMap},
+    response::IntoResponse,
+    routing, Extension, Json, Router,
+};
+use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use utoipa::ToSchema;
+
+pub fn create_admin_router() -> Router {
+    Router::new().route("/admin/config/reload", routing::post(reload_config))
+}
+
+/// Result of a configuration reload.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigReloadRes {
+    message: String,
+    /// Applied changes.
+    changes: Vec<String>,
+}
+
+/// Whether an admin request is allowed: with the configured `adminApiKey` as bearer
+/// token, or, if no key is configured, only from localhost.
+fn is_authorized(admin_api_key: &Secret, headers: &HeaderMap, peer: Option<SocketAddr>) -> bool {
+    if admin_api_key.is_empty() {
+        return peer.is_some_and(|peer| peer.ip().is_loopback());
+    }
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .is_some_and(|token| token.trim() == admin_api_key.expose())
+}
+
+/// Reload the configuration file.
+///
+/// Requires the configured `adminApiKey` as bearer token, or, if no key is
+/// configured, a request from localhost.
+///
+/// The new configuration is validated first. Changes to settings that are
+/// only used at startup (`port`, `externalUrl`, `stateDir`, `stateStore`) are
+/// rejected, in which case the current configuration remains in effect.
+#[utoipa::path(
+    post,
+    path = "/admin/config/reload",
+    responses(
+        (status = 200, description = "Configuration reloaded", body = ConfigReloadRes),
+        (status = 401, description = "Missing or invalid admin API key, or not from localhost"),
+        (status = 409, description = "Changes require a restart; configuration not reloaded"),
+        (status = 422, description = "Invalid configuration; configuration not reloaded"),
+    )
+)]
+async fn reload_config(
+    headers: HeaderMap,
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
+) -> impl IntoResponse {
+    let peer = connect_info.map(|ci| ci.0 .0);
+    log::info!("reload_config: from {peer:?}");
+    if !is_authorized(&config::get_config().admin_api_key, &headers, peer) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    match config::reload_config() {
+        Ok(changes) => {
+            let message = if changes.is_empty() {
+                "No changes in configuration".to_string()
+            } else {
+                "Configuration reloaded".to_string()
+            };
+            log::info!("{message}: {changes:?}");
+            Json(ConfigReloadRes { message, changes }).into_response()
+        }
+        Err(e) => {
+            log::warn!("reload_config: {e}");
+            let status = match e {
+                ReloadError::Invalid(_) => StatusCode::UNPROCESSABLE_ENTITY,
+                ReloadError::NotReloadable(_) => StatusCode::CONFLICT,
+            };
+            (status, e.to_string()).into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::init_test_config;
+    use axum::http::HeaderValue;
+    use axum_test::*;
+
+    #[tokio::test]
+    async fn it_should_require_authorization_for_reload() {
+        init_test_config();
+        let server = TestServer::new(create_admin_router().into_make_service());
+        // no configured key, and not known to be from localhost:
+        let response = server.post("/admin/config/reload").await;
+        assert_eq!(response.status_code(), StatusCode::UNAUTHORIZED);
+
+        let local: SocketAddr = "127.0.0.1:5000".parse().unwrap();
+        let remote: SocketAddr = "192.0.2.10:5000".parse().unwrap();
+        let no_key = Secret::default();
+        assert!(is_authorized(&no_key, &HeaderMap::new(), Some(local)));
+        assert!(!is_authorized(&no_key, &HeaderMap::new(), Some(remote)));
+
+        let key = Secret::new("s3cret");
+        let mut headers = HeaderMap::new();
+        assert!(!is_authorized(&key, &headers, Some(local)));
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Bearer nope"),
+        );
+        assert!(!is_authorized(&key, &headers, Some(remote)));
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Bearer s3cret"),
+        );
+        assert!(is_authorized(&key, &headers, Some(remote)));
+    }
+}
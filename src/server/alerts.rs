@@ -0,0 +1,57 @@
+use crate::alerts::{self, Alert};
+use crate::time_util;
+
+use axum::{extract::Query, response::IntoResponse, routing, Json, Router};
+use hyper::StatusCode;
+use serde::Deserialize;
+use utoipa::IntoParams;
+
+pub fn create_alerts_router() -> Router {
+    Router::new().route("/alerts", routing::get(get_alerts))
+}
+
+#[derive(Deserialize, IntoParams, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AlertsQuery {
+    /// Only alerts for this platform
+    platform_id: Option<String>,
+    /// Only alerts raised since this time (ISO 8601 UTC or epoch milliseconds)
+    since: Option<String>,
+    /// Maximum number of last alerts to report
+    limit: Option<usize>,
+}
+
+/// Get the alerts raised by the dispatcher.
+///
+/// The alerts are raised upon a dispatched platform entering or exiting a geofence
+/// (`geofenceEnter`, `geofenceExit`), going stale or recovering (`stale`, `recovered`,
+/// if `staleness.notify`), or getting close to or apart from another platform per
+/// the proximity rules (`proximity`, `proximityCleared`).
+#[utoipa::path(
+    get,
+    path = "/alerts",
+    params(
+        AlertsQuery,
+    ),
+    responses(
+        (status = 200, description = "Alerts, oldest first", body = Vec<Alert>),
+        (status = 400, description = "Invalid query parameter"),
+    )
+)]
+async fn get_alerts(query: Query<AlertsQuery>) -> impl IntoResponse {
+    let query = query.0;
+    log::info!("get_alerts: query: {query:?}");
+
+    let since_ms = match query.since.as_deref().map(time_util::parse_time_ms) {
+        Some(Ok(since_ms)) => Some(since_ms),
+        Some(Err(e)) => return (StatusCode::BAD_REQUEST, e).into_response(),
+        None => None,
+    };
+    match alerts::get_alerts(query.platform_id.as_deref(), since_ms, query.limit) {
+        Ok(alerts) => Json(alerts).into_response(),
+        Err(e) => {
+            log::error!("get_alerts: {e}");
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+        }
+    }
+}
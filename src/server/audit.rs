@@ -0,0 +1,251 @@
+use crate::audit::{self, AuditContext, AuditEntry, AuditSource};
+use crate::config;
+use crate::time_util;
+
+use axum::{
+    extract::{ConnectInfo, FromRequestParts, Query},
+    http::{request::Parts, HeaderMap},
+    response::IntoResponse,
+    routing, Json, Router,
+};
+use hyper::StatusCode;
+use serde::Deserialize;
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use utoipa::IntoParams;
+
+/// Headers with the authenticated user as set by an authenticating reverse proxy,
+/// e.g., oauth2-proxy, in order of preference.
+const IDENTITY_HEADERS: [&str; 2] = ["X-Forwarded-User", "X-Auth-Request-User"];
+
+/// Captures the client address and any authenticated identity of an API request.
+impl<S: Send + Sync> FromRequestParts<S> for AuditContext {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let peer = ConnectInfo::<SocketAddr>::from_request_parts(parts, state)
+            .await
+            .ok()
+            .map(|ci| ci.0.ip());
+        let trusted_proxies = &config::get_config().trusted_proxies;
+        Ok(api_audit_context(&parts.headers, peer, trusted_proxies))
+    }
+}
+
+/// The context of an API request from the given peer, with the forwarded client
+/// address and identity headers only honored if the peer is a trusted proxy.
+fn api_audit_context(
+    headers: &HeaderMap,
+    peer: Option<IpAddr>,
+    trusted_proxies: &[IpAddr],
+) -> AuditContext {
+    let mut context = AuditContext {
+        source: AuditSource::Api,
+        client: peer.map(|ip| ip.to_string()),
+        identity: None,
+    };
+    if !peer.is_some_and(|ip| trusted_proxies.contains(&ip)) {
+        return context;
+    }
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+    };
+    // original client behind the proxies, as the entries added by untrusted hops,
+    // including the client itself, may be anything:
+    if let Some(forwarded_for) = header("X-Forwarded-For") {
+        let hops = forwarded_for.split(',').map(str::trim).collect::<Vec<_>>();
+        let is_trusted = |hop: &str| {
+            hop.parse::<IpAddr>()
+                .is_ok_and(|ip| trusted_proxies.contains(&ip))
+        };
+        let client = hops
+            .iter()
+            .rev()
+            .find(|hop| !is_trusted(hop))
+            .or(hops.first())
+            .filter(|hop| !hop.is_empty());
+        if let Some(client) = client {
+            context.client = Some(client.to_string());
+        }
+    }
+    context.identity = IDENTITY_HEADERS.iter().find_map(|name| header(name));
+    context
+}
+
+pub fn create_audit_router() -> Router {
+    Router::new().route("/runtime/audit", routing::get(get_audit))
+}
+
+#[derive(Deserialize, IntoParams, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditQuery {
+    /// Only entries for this platform
+    platform_id: Option<String>,
+    /// Only entries since this time (ISO 8601 UTC or epoch milliseconds)
+    since: Option<String>,
+    /// Maximum number of last entries to report
+    limit: Option<usize>,
+}
+
+/// Get the audit log of changes to the dispatched platforms.
+///
+/// Each entry indicates the added or removed platform, when, the source of the
+/// change (`api`, `cli`, `autoPrune`, or `expired`), and, if known, the client address and
+/// the authenticated identity.
+#[utoipa::path(
+    get,
+    path = "/runtime/audit",
+    params(
+        AuditQuery,
+    ),
+    responses(
+        (status = 200, description = "Audit log entries, oldest first", body = Vec<AuditEntry>),
+        (status = 400, description = "Invalid query parameter"),
+    )
+)]
+async fn get_audit(query: Query<AuditQuery>) -> impl IntoResponse {
+    let query = query.0;
+    log::info!("get_audit: query: {query:?}");
+
+    let since_ms = match query.since.as_deref().map(time_util::parse_time_ms) {
+        Some(Ok(since_ms)) => Some(since_ms),
+        Some(Err(e)) => return (StatusCode::BAD_REQUEST, e).into_response(),
+        None => None,
+    };
+    match audit::get_entries(query.platform_id.as_deref(), since_ms, query.limit) {
+        Ok(entries) => Json(entries).into_response(),
+        Err(e) => {
+            log::error!("get_audit: {e}");
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::AuditAction;
+    use crate::config::init_test_config;
+    use crate::dispatched_info::{DispatchDetails, DispatchedInfo};
+    use crate::platform_info::PlatformInfo;
+    use crate::server::dispatched::create_dispatched_router;
+    use axum::extract::connect_info::MockConnectInfo;
+    use axum_test::*;
+    use pretty_assertions::assert_eq;
+    use std::sync::{Arc, Mutex};
+
+    fn create_test_server(peer: IpAddr) -> TestServer {
+        init_test_config();
+
+        let platform_info = Arc::new(Mutex::new(PlatformInfo::default()));
+        let dispatched_info = Arc::new(Mutex::new(DispatchedInfo::new()));
+        let app = create_dispatched_router(platform_info, dispatched_info)
+            .merge(create_audit_router())
+            .layer(MockConnectInfo(SocketAddr::new(peer, 5000)))
+            .into_make_service();
+
+        TestServer::new(app)
+    }
+
+    #[test]
+    fn test_api_audit_context() {
+        let proxy: IpAddr = "10.0.0.1".parse().unwrap();
+        let other: IpAddr = "192.0.2.10".parse().unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("X-Forwarded-For", "198.51.100.7, 10.0.0.2".parse().unwrap());
+        headers.insert("X-Auth-Request-User", "jdoe".parse().unwrap());
+
+        let context = api_audit_context(&headers, Some(proxy), &[proxy]);
+        assert_eq!(context.client.as_deref(), Some("10.0.0.2"));
+        assert_eq!(context.identity.as_deref(), Some("jdoe"));
+
+        let inner_proxy: IpAddr = "10.0.0.2".parse().unwrap();
+        let context = api_audit_context(&headers, Some(proxy), &[proxy, inner_proxy]);
+        assert_eq!(context.client.as_deref(), Some("198.51.100.7"));
+
+        // a spoofed leftmost entry, as sent by the client itself:
+        let mut spoofed = headers.clone();
+        spoofed.insert(
+            "X-Forwarded-For",
+            "203.0.113.66, 198.51.100.7, 10.0.0.2".parse().unwrap(),
+        );
+        let context = api_audit_context(&spoofed, Some(proxy), &[proxy, inner_proxy]);
+        assert_eq!(context.client.as_deref(), Some("198.51.100.7"));
+
+        let context = api_audit_context(&headers, Some(other), &[proxy]);
+        assert_eq!(context.client.as_deref(), Some("192.0.2.10"));
+        assert_eq!(context.identity, None);
+
+        let context = api_audit_context(&headers, None, &[proxy]);
+        assert_eq!(context.client, None);
+        assert_eq!(context.identity, None);
+    }
+
+    #[tokio::test]
+    async fn it_should_record_dispatch_changes() {
+        init_test_config();
+        let trusted_proxy = config::get_config().trusted_proxies[0];
+        let untrusted = "192.0.2.10".parse().unwrap();
+        let platform_ids = ["audit-test-1".to_string(), "audit-test-2".to_string()];
+        DispatchedInfo::new().add_platform_ids(
+            platform_ids.to_vec(),
+            DispatchDetails::default(),
+            &AuditContext::cli(),
+        );
+
+        // spoofed headers from an untrusted peer are ignored:
+        let server = create_test_server(untrusted);
+        let response = server
+            .delete("/runtime/platforms/audit-test-1")
+            .add_header("X-Forwarded-For", "198.51.100.7")
+            .add_header("X-Forwarded-User", "admin")
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+
+        let server = create_test_server(trusted_proxy);
+        let response = server
+            .delete("/runtime/platforms/audit-test-2")
+            .add_header("X-Forwarded-For", "198.51.100.7")
+            .add_header("X-Forwarded-User", "jdoe")
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+
+        let response = server.get("/runtime/audit?platformId=audit-test-1").await;
+        let actions = response
+            .json::<Vec<AuditEntry>>()
+            .iter()
+            .map(|e| (e.action, e.source))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            actions,
+            vec![
+                (AuditAction::Add, AuditSource::Cli),
+                (AuditAction::Remove, AuditSource::Api)
+            ]
+        );
+
+        let response = server
+            .get("/runtime/audit?platformId=audit-test-1&limit=1")
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let removal = &response.json::<Vec<AuditEntry>>()[0];
+        assert_eq!(removal.action, AuditAction::Remove);
+        assert_eq!(removal.source, AuditSource::Api);
+        assert_eq!(removal.client.as_deref(), Some("192.0.2.10"));
+        assert_eq!(removal.identity, None);
+
+        let response = server
+            .get("/runtime/audit?platformId=audit-test-2&limit=1")
+            .await;
+        let removal = &response.json::<Vec<AuditEntry>>()[0];
+        assert_eq!(removal.client.as_deref(), Some("198.51.100.7"));
+        assert_eq!(removal.identity.as_deref(), Some("jdoe"));
+
+        let response = server.get("/runtime/audit?since=bad").await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -1,5 +1,8 @@
-use crate::dispatched_info::DispatchedInfo;
+use crate::audit::AuditContext;
+use crate::config;
+use crate::dispatched_info::{DispatchDetails, DispatchedInfo};
 use crate::platform_info::PlatformInfo;
+use crate::time_util;
 use crate::trackdb_client::PlatformRes;
 
 use axum::{
@@ -42,6 +45,8 @@ pub fn create_dispatched_router(
             "/runtime/platforms/{platform_id}",
             routing::delete(delete_dispatched_platform),
         )
+        .route("/runtime/details", routing::get(get_dispatched_details))
+        .route("/runtime/orphans", routing::get(get_orphans))
         .with_state(info)
 }
 
@@ -56,17 +61,13 @@ pub fn create_dispatched_router(
 async fn get_dispatched_platforms(State(info): State<Arc<Mutex<Info>>>) -> Json<Vec<PlatformRes>> {
     log::info!("get_dispatched_platforms");
     let info = info.lock().unwrap();
-    let mut dispatched_info = info.dispatched_info.lock().unwrap();
-    let platform_ids = dispatched_info.get_platform_ids();
+    let platform_ids = info.dispatched_info.lock().unwrap().get_platform_ids();
     let platform_info = info.platform_info.lock().unwrap();
     let mut platforms_res: Vec<PlatformRes> = Vec::new();
     for platform_id in platform_ids {
         match platform_info.get_platform(&platform_id) {
             Some(platform_res) => platforms_res.push(platform_res),
-            None => {
-                log::debug!("Platform not found, so no longer dispatched: {platform_id}");
-                dispatched_info.delete_platform_id(&platform_id);
-            }
+            None => log::debug!("Dispatched platform not in platform cache: {platform_id}"),
         }
     }
     Json(platforms_res)
@@ -89,17 +90,16 @@ async fn get_dispatched_platform(
 ) -> impl IntoResponse {
     log::info!("get_dispatched_platform: platform_id={}", platform_id);
     let info = info.lock().unwrap();
-    let mut dispatched_info = info.dispatched_info.lock().unwrap();
+    let dispatched_info = info.dispatched_info.lock().unwrap();
     if dispatched_info.is_dispatched_platform(&platform_id) {
         let platform_info = info.platform_info.lock().unwrap();
         match platform_info.get_platform(&platform_id) {
             Some(platform_res) => Json(platform_res).into_response(),
             None => {
-                log::debug!("Platform not found, so no longer dispatched: {platform_id}");
-                dispatched_info.delete_platform_id(&platform_id);
+                log::debug!("Dispatched platform not in platform cache: {platform_id}");
                 (
                     StatusCode::NOT_FOUND,
-                    "Platform not found, so no longer dispatched",
+                    "Dispatched platform, but not found in Tracking DB",
                 )
                     .into_response()
             }
@@ -109,27 +109,57 @@ async fn get_dispatched_platform(
     }
 }
 
-/// Platform IDs to add for dispatching.
+/// Platform IDs to add for dispatching, with optional details applying to all of them.
+/// Times are given in ISO 8601 UTC or as epoch milliseconds.
 #[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct PlatformAdd {
     platform_ids: Vec<String>,
+    /// Dispatch only from this time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    start_time: Option<String>,
+    /// Undispatch at this time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    note: Option<String>,
+    /// Who adds the platforms. By default, the authenticated identity, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    added_by: Option<String>,
 }
 
 /// Add platforms to be dispatched.
+///
+/// Besides the platform IDs, a dispatch time window (`startTime`, `expiresAt`),
+/// a note, and who adds the platforms can optionally be given.
+/// Upon expiration, the platforms are automatically undispatched.
+/// For already dispatched platforms, any given time window or note replaces the
+/// existing details.
 #[utoipa::path(
     post,
     path = "/runtime/platforms",
     request_body = PlatformAdd,
     responses(
         (status = 201, description = "Platforms added successfully", body = Vec<String>),
+        (status = 400, description = "Invalid time window"),
     )
 )]
 async fn add_dispatched_platforms(
     State(info): State<Arc<Mutex<Info>>>,
+    audit_context: AuditContext,
     Json(platform_add): Json<PlatformAdd>,
-) -> Json<Vec<String>> {
+) -> impl IntoResponse {
     log::info!("add_dispatched_platforms: platform_add={:?}", platform_add);
+    let details = match DispatchDetails::new(
+        platform_add.start_time.as_deref(),
+        platform_add.expires_at.as_deref(),
+        platform_add.note,
+        platform_add.added_by,
+    ) {
+        Ok(details) => details,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+
     let info = info.lock().unwrap();
     let platform_info = get_platform_info(&info);
     let mut dispatched_info = info.dispatched_info.lock().unwrap();
@@ -137,13 +167,60 @@ async fn add_dispatched_platforms(
     let mut added: Vec<String> = Vec::new();
     for platform_id in &platform_add.platform_ids {
         if platform_info.get_platform(platform_id).is_some() {
-            dispatched_info.add_platform_id(platform_id);
             added.push(platform_id.clone());
         } else {
             log::debug!("Platform not found, so not dispatched: {platform_id}");
         }
     }
-    Json(added)
+    dispatched_info.add_platform_ids(added.clone(), details, &audit_context);
+    Json(added).into_response()
+}
+
+/// A dispatched platform with its details.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DispatchedDetailsRes {
+    platform_id: String,
+    /// Whether the platform is currently dispatched per its time window, if any.
+    active: bool,
+    /// Names of the groups the platform belongs to.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    groups: Vec<String>,
+    #[serde(flatten)]
+    details: DispatchDetails,
+}
+
+/// Get the details of all dispatched platforms, including the groups they belong to.
+#[utoipa::path(
+    get,
+    path = "/runtime/details",
+    responses(
+       (status = 200, description = "Dispatched platforms with details", body = Vec<DispatchedDetailsRes>)
+    )
+)]
+async fn get_dispatched_details(
+    State(info): State<Arc<Mutex<Info>>>,
+) -> Json<Vec<DispatchedDetailsRes>> {
+    log::info!("get_dispatched_details");
+    let now_ms = time_util::now_ms();
+    let info = info.lock().unwrap();
+    let dispatched_info = info.dispatched_info.lock().unwrap();
+    let mut details = dispatched_info.get_details();
+    let mut memberships = dispatched_info.get_memberships();
+    let details_res = dispatched_info
+        .get_platform_ids()
+        .into_iter()
+        .map(|platform_id| {
+            let details = details.remove(&platform_id).unwrap_or_default();
+            DispatchedDetailsRes {
+                active: details.is_active(now_ms),
+                groups: memberships.remove(&platform_id).unwrap_or_default(),
+                platform_id,
+                details,
+            }
+        })
+        .collect();
+    Json(details_res)
 }
 
 fn get_platform_info(info: &Info) -> PlatformInfo {
@@ -171,13 +248,99 @@ pub struct PlatformDeleteRes {
 async fn delete_dispatched_platform(
     State(info): State<Arc<Mutex<Info>>>,
     Path(platform_id): Path<String>,
+    audit_context: AuditContext,
 ) -> impl IntoResponse {
     log::info!("delete_dispatched_platform: platform_id={}", platform_id);
     let info = info.lock().unwrap();
     let mut dispatched_info = info.dispatched_info.lock().unwrap();
 
-    match dispatched_info.delete_platform_id(&platform_id) {
+    match dispatched_info.delete_platform_id(&platform_id, &audit_context) {
         Some(_) => Json(PlatformDeleteRes { platform_id }).into_response(),
         None => (StatusCode::NOT_FOUND, "Platform not found").into_response(),
     }
 }
+
+/// A dispatched platform missing from the Tracking DB.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct OrphanRes {
+    platform_id: String,
+    /// Since when (epoch ms) the platform has been missing.
+    orphaned_since_ms: u64,
+    /// When (epoch ms) the platform is to be undispatched, unless it reappears
+    /// in the meantime. Absent if pruning is disabled or in dry-run mode.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    prune_at_ms: Option<u64>,
+}
+
+/// Get the orphaned dispatched platforms.
+///
+/// These are dispatched platforms that were missing from the Tracking DB as of the
+/// latest full platform refresh by the dispatcher. Per the pruning configuration,
+/// they get undispatched after a grace period, unless they reappear.
+#[utoipa::path(
+    get,
+    path = "/runtime/orphans",
+    responses(
+       (status = 200, description = "List of orphaned dispatched platforms", body = Vec<OrphanRes>)
+    )
+)]
+async fn get_orphans(State(info): State<Arc<Mutex<Info>>>) -> Json<Vec<OrphanRes>> {
+    log::info!("get_orphans");
+    let pruning = config::get_config().pruning.clone();
+    let info = info.lock().unwrap();
+    let orphans = info.dispatched_info.lock().unwrap().get_orphans();
+    let orphans_res = orphans
+        .into_iter()
+        .map(|(platform_id, orphaned_since_ms)| OrphanRes {
+            platform_id,
+            orphaned_since_ms,
+            prune_at_ms: pruning
+                .is_active()
+                .then(|| orphaned_since_ms + pruning.grace_period.as_millis() as u64),
+        })
+        .collect();
+    Json(orphans_res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::init_test_config;
+    use crate::state_store::JsonStore;
+    use axum_test::*;
+    use pretty_assertions::assert_eq;
+    use std::collections::BTreeSet;
+
+    #[tokio::test]
+    async fn it_should_get_orphans() {
+        init_test_config();
+        let store_dir =
+            std::env::temp_dir().join(format!("odss2dash-orphans-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&store_dir);
+        std::fs::create_dir_all(&store_dir).unwrap();
+        let mut dispatched_info = DispatchedInfo::with_store(Arc::new(JsonStore::new(&store_dir)));
+        dispatched_info.add_platform_ids(
+            vec!["orphans-test-1".to_string(), "orphans-test-2".to_string()],
+            DispatchDetails::default(),
+            &AuditContext::cli(),
+        );
+        let known_ids = BTreeSet::from(["orphans-test-2".to_string()]);
+        dispatched_info.update_orphans(&known_ids, &|_| true, 1_700_000_000_000);
+
+        let platform_info = Arc::new(Mutex::new(PlatformInfo::default()));
+        let app = create_dispatched_router(platform_info, Arc::new(Mutex::new(dispatched_info)));
+        let server = TestServer::new(app.into_make_service());
+
+        let response = server.get("/runtime/orphans").await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let orphans = response.json::<Vec<OrphanRes>>();
+        assert_eq!(orphans.len(), 1);
+        assert_eq!(orphans[0].platform_id, "orphans-test-1");
+        assert_eq!(orphans[0].orphaned_since_ms, 1_700_000_000_000);
+        let grace_ms = config::get_config().pruning.grace_period.as_millis() as u64;
+        assert_eq!(orphans[0].prune_at_ms, Some(1_700_000_000_000 + grace_ms));
+
+        std::fs::remove_dir_all(&store_dir).unwrap();
+    }
+}
@@ -0,0 +1,29 @@
+use crate::staleness::{self, DispatcherStatus};
+
+use axum::{response::IntoResponse, routing, Json, Router};
+use hyper::StatusCode;
+
+pub fn create_dispatcher_router() -> Router {
+    Router::new().route("/dispatcher/status", routing::get(get_dispatcher_status))
+}
+
+/// Get the status of the dispatched platforms, as last determined by the dispatcher.
+///
+/// For each dispatched platform, the time of its latest fix, and whether it is stale,
+/// that is, with no new fix within the staleness threshold that applies to it, if any
+/// (see the `staleness` settings).
+#[utoipa::path(
+    get,
+    path = "/dispatcher/status",
+    responses(
+        (status = 200, description = "Dispatcher status", body = DispatcherStatus),
+        (status = 404, description = "No status saved by the dispatcher yet"),
+    )
+)]
+async fn get_dispatcher_status() -> impl IntoResponse {
+    log::info!("get_dispatcher_status");
+    match staleness::load_status() {
+        Some(status) => Json(status).into_response(),
+        None => (StatusCode::NOT_FOUND, "No dispatcher status yet").into_response(),
+    }
+}
@@ -0,0 +1,138 @@
+use crate::geofence;
+
+use axum::{extract::Path, response::IntoResponse, routing, Json, Router};
+use hyper::StatusCode;
+use serde_json::{json, Value};
+
+pub fn create_geofences_router() -> Router {
+    Router::new()
+        .route(
+            "/geofences",
+            routing::get(get_geofences).post(add_geofences),
+        )
+        .route("/geofences/{name}", routing::delete(delete_geofence))
+}
+
+fn feature_collection(features: Vec<Value>) -> Value {
+    json!({"type": "FeatureCollection", "features": features})
+}
+
+/// Get the geofences.
+///
+/// The geofences are reported as a GeoJSON FeatureCollection, with each Feature having
+/// a `name` property, and a `source` property indicating whether the geofence is from
+/// the configuration (`config`) or was added via the API (`api`).
+/// A circle is reported as a Point with a `radius` property in meters.
+#[utoipa::path(
+    get,
+    path = "/geofences",
+    responses(
+        (status = 200, description = "GeoJSON FeatureCollection", body = Object),
+    )
+)]
+async fn get_geofences() -> impl IntoResponse {
+    let config_features = geofence::get_config_geofences()
+        .into_iter()
+        .map(|g| g.to_geojson("config"));
+    let api_features = geofence::get_api_geofences()
+        .into_iter()
+        .map(|g| g.to_geojson("api"));
+    Json(feature_collection(
+        config_features.chain(api_features).collect(),
+    ))
+}
+
+/// Add geofences.
+///
+/// The geofences are given as a GeoJSON Feature or FeatureCollection, each Feature
+/// with a `name` property, and a Polygon geometry, or a Point geometry with a `radius`
+/// property in meters for a circle. Optional `platformIds`, `namePatterns`, and
+/// `typeNames` properties restrict the platforms the geofence applies to.
+/// Any geofence previously added via the API by the same name is replaced.
+#[utoipa::path(
+    post,
+    path = "/geofences",
+    request_body(content = Object, description = "GeoJSON Feature or FeatureCollection"),
+    responses(
+        (status = 201, description = "Added geofences, as a GeoJSON FeatureCollection", body = Object),
+        (status = 400, description = "Invalid geofences, or name of a configured geofence"),
+    )
+)]
+async fn add_geofences(Json(geojson): Json<Value>) -> impl IntoResponse {
+    log::info!("add_geofences");
+    let geofences = match geofence::from_geojson(&geojson) {
+        Ok(geofences) => geofences,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+    let features = geofences.iter().map(|g| g.to_geojson("api")).collect();
+    match geofence::put_api_geofences(geofences) {
+        Ok(()) => (StatusCode::CREATED, Json(feature_collection(features))).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+/// Delete a geofence added via the API.
+#[utoipa::path(
+    delete,
+    path = "/geofences/{name}",
+    params(
+        ("name" = String, Path, description = "Geofence name"),
+    ),
+    responses(
+        (status = 200, description = "Geofence deleted"),
+        (status = 404, description = "No geofence by that name added via the API"),
+    )
+)]
+async fn delete_geofence(Path(name): Path<String>) -> impl IntoResponse {
+    log::info!("delete_geofence: {name}");
+    match geofence::delete_api_geofence(&name) {
+        Ok(true) => StatusCode::OK.into_response(),
+        Ok(false) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            log::error!("delete_geofence: {e}");
+            (StatusCode::INTERNAL_SERVER_ERROR, e).into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::init_test_config;
+    use axum_test::*;
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn it_should_add_and_delete_geofences() {
+        init_test_config();
+        let server = TestServer::new(create_geofences_router().into_make_service());
+
+        let feature = json!({
+            "type": "Feature",
+            "properties": {"name": "test-harbor", "radius": 300.0, "platformIds": ["none"]},
+            "geometry": {"type": "Point", "coordinates": [-121.79, 36.8]}
+        });
+        let response = server.post("/geofences").json(&feature).await;
+        assert_eq!(response.status_code(), StatusCode::CREATED);
+
+        let response = server.get("/geofences").await;
+        let names = response.json::<Value>()["features"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|f| f["properties"]["name"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert!(names.contains(&"test-harbor".to_string()));
+
+        let response = server
+            .post("/geofences")
+            .json(&json!({"type": "Feature", "properties": {"name": "bad"}}))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+
+        let response = server.delete("/geofences/test-harbor").await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let response = server.delete("/geofences/test-harbor").await;
+        assert_eq!(response.status_code(), StatusCode::NOT_FOUND);
+    }
+}
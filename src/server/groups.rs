@@ -0,0 +1,341 @@
+use crate::audit::AuditContext;
+use crate::dispatched_info::{check_group_name, DispatchDetails, DispatchedInfo};
+use crate::platform_info::PlatformInfo;
+
+use axum::{
+    extract::{Path, State},
+    response::IntoResponse,
+    routing, Json, Router,
+};
+use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use utoipa::ToSchema;
+
+#[derive(Clone)]
+pub struct Info {
+    platform_info: Arc<Mutex<PlatformInfo>>,
+    dispatched_info: Arc<Mutex<DispatchedInfo>>,
+}
+
+pub fn create_groups_router(
+    platform_info: Arc<Mutex<PlatformInfo>>,
+    dispatched_info: Arc<Mutex<DispatchedInfo>>,
+) -> Router {
+    let info = Arc::new(Info {
+        platform_info,
+        dispatched_info,
+    });
+
+    Router::new()
+        .route("/runtime/groups", routing::get(get_groups))
+        .route(
+            "/runtime/groups/{name}",
+            routing::get(get_group).put(put_group).delete(delete_group),
+        )
+        .route(
+            "/runtime/groups/{name}/dispatch",
+            routing::post(dispatch_group),
+        )
+        .route(
+            "/runtime/groups/{name}/undispatch",
+            routing::post(undispatch_group),
+        )
+        .with_state(info)
+}
+
+/// A named group of platforms.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupRes {
+    name: String,
+    platform_ids: Vec<String>,
+}
+
+/// Get all platform groups.
+#[utoipa::path(
+    get,
+    path = "/runtime/groups",
+    responses(
+       (status = 200, description = "List of groups", body = Vec<GroupRes>)
+    )
+)]
+async fn get_groups(State(info): State<Arc<Info>>) -> Json<Vec<GroupRes>> {
+    log::info!("get_groups");
+    let groups = info.dispatched_info.lock().unwrap().get_groups();
+    let groups_res = groups
+        .into_iter()
+        .map(|(name, platform_ids)| GroupRes { name, platform_ids })
+        .collect();
+    Json(groups_res)
+}
+
+/// Get a platform group.
+#[utoipa::path(
+    get,
+    path = "/runtime/groups/{name}",
+    params(
+        ("name" = String, Path, description = "Group name"),
+    ),
+    responses(
+       (status = 200, description = "The group", body = GroupRes),
+       (status = 404, description = "Group not found"),
+    )
+)]
+async fn get_group(State(info): State<Arc<Info>>, Path(name): Path<String>) -> impl IntoResponse {
+    log::info!("get_group: name={name}");
+    match info.dispatched_info.lock().unwrap().get_group(&name) {
+        Some(platform_ids) => Json(GroupRes { name, platform_ids }).into_response(),
+        None => (StatusCode::NOT_FOUND, "Group not found").into_response(),
+    }
+}
+
+/// Platforms of a group.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupPut {
+    platform_ids: Vec<String>,
+}
+
+/// Create or replace a platform group.
+///
+/// This does not affect the dispatch of any platforms.
+#[utoipa::path(
+    put,
+    path = "/runtime/groups/{name}",
+    params(
+        ("name" = String, Path, description = "Group name"),
+    ),
+    request_body = GroupPut,
+    responses(
+       (status = 201, description = "Group created", body = GroupRes),
+       (status = 200, description = "Group replaced", body = GroupRes),
+       (status = 400, description = "Invalid group name"),
+    )
+)]
+async fn put_group(
+    State(info): State<Arc<Info>>,
+    Path(name): Path<String>,
+    Json(group_put): Json<GroupPut>,
+) -> impl IntoResponse {
+    log::info!("put_group: name={name} group_put={group_put:?}");
+    if let Err(e) = check_group_name(&name) {
+        return (StatusCode::BAD_REQUEST, e).into_response();
+    }
+    let mut dispatched_info = info.dispatched_info.lock().unwrap();
+    let created = dispatched_info.set_group(&name, group_put.platform_ids);
+    let platform_ids = dispatched_info.get_group(&name).unwrap_or_default();
+    let status = if created {
+        StatusCode::CREATED
+    } else {
+        StatusCode::OK
+    };
+    (status, Json(GroupRes { name, platform_ids })).into_response()
+}
+
+/// Delete a platform group.
+///
+/// This does not affect the dispatch of its platforms.
+#[utoipa::path(
+    delete,
+    path = "/runtime/groups/{name}",
+    params(
+        ("name" = String, Path, description = "Group name"),
+    ),
+    responses(
+       (status = 200, description = "Group deleted", body = GroupRes),
+       (status = 404, description = "Group not found"),
+    )
+)]
+async fn delete_group(
+    State(info): State<Arc<Info>>,
+    Path(name): Path<String>,
+) -> impl IntoResponse {
+    log::info!("delete_group: name={name}");
+    match info.dispatched_info.lock().unwrap().delete_group(&name) {
+        Some(platform_ids) => Json(GroupRes { name, platform_ids }).into_response(),
+        None => (StatusCode::NOT_FOUND, "Group not found").into_response(),
+    }
+}
+
+/// Optional details for the dispatch of a group, as for adding platforms.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupDispatch {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    start_time: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    note: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    added_by: Option<String>,
+}
+
+/// Dispatch all platforms of a group.
+///
+/// Only platforms known to the Tracking DB are dispatched.
+/// The request body is optional, with the same details as for adding platforms.
+#[utoipa::path(
+    post,
+    path = "/runtime/groups/{name}/dispatch",
+    params(
+        ("name" = String, Path, description = "Group name"),
+    ),
+    request_body = Option<GroupDispatch>,
+    responses(
+       (status = 200, description = "Dispatched platforms", body = Vec<String>),
+       (status = 400, description = "Invalid time window"),
+       (status = 404, description = "Group not found"),
+    )
+)]
+async fn dispatch_group(
+    State(info): State<Arc<Info>>,
+    Path(name): Path<String>,
+    audit_context: AuditContext,
+    group_dispatch: Option<Json<GroupDispatch>>,
+) -> impl IntoResponse {
+    let group_dispatch = group_dispatch.map(|j| j.0).unwrap_or_default();
+    log::info!("dispatch_group: name={name} group_dispatch={group_dispatch:?}");
+    let details = match DispatchDetails::new(
+        group_dispatch.start_time.as_deref(),
+        group_dispatch.expires_at.as_deref(),
+        group_dispatch.note,
+        group_dispatch.added_by,
+    ) {
+        Ok(details) => details,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+
+    let mut dispatched_info = info.dispatched_info.lock().unwrap();
+    let Some(platform_ids) = dispatched_info.get_group(&name) else {
+        return (StatusCode::NOT_FOUND, "Group not found").into_response();
+    };
+    let platform_ids = {
+        let platform_info = info.platform_info.lock().unwrap();
+        platform_ids
+            .into_iter()
+            .filter(|id| {
+                let known = platform_info.get_platform(id).is_some();
+                if !known {
+                    log::debug!("Platform not found, so not dispatched: {id}");
+                }
+                known
+            })
+            .collect::<Vec<_>>()
+    };
+    dispatched_info.add_platform_ids(platform_ids.clone(), details, &audit_context);
+    Json(platform_ids).into_response()
+}
+
+/// Undispatch all platforms of a group.
+#[utoipa::path(
+    post,
+    path = "/runtime/groups/{name}/undispatch",
+    params(
+        ("name" = String, Path, description = "Group name"),
+    ),
+    responses(
+       (status = 200, description = "Undispatched platforms", body = Vec<String>),
+       (status = 404, description = "Group not found"),
+    )
+)]
+async fn undispatch_group(
+    State(info): State<Arc<Info>>,
+    Path(name): Path<String>,
+    audit_context: AuditContext,
+) -> impl IntoResponse {
+    log::info!("undispatch_group: name={name}");
+    let mut dispatched_info = info.dispatched_info.lock().unwrap();
+    let Some(platform_ids) = dispatched_info.get_group(&name) else {
+        return (StatusCode::NOT_FOUND, "Group not found").into_response();
+    };
+    let removed = platform_ids
+        .iter()
+        .filter_map(|id| dispatched_info.delete_platform_id(id, &audit_context))
+        .collect::<Vec<_>>();
+    Json(removed).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::init_test_config;
+    use crate::state_store::JsonStore;
+    use axum_test::*;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn it_should_manage_and_dispatch_groups() {
+        init_test_config();
+        let store_dir =
+            std::env::temp_dir().join(format!("odss2dash-groups-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&store_dir);
+        std::fs::create_dir_all(&store_dir).unwrap();
+        let store = Arc::new(JsonStore::new(&store_dir));
+        let dispatched_info = Arc::new(Mutex::new(DispatchedInfo::with_store(store)));
+        let platform_info = Arc::new(Mutex::new(PlatformInfo::default()));
+        platform_info.lock().unwrap().refresh();
+        let app = create_groups_router(platform_info, dispatched_info.clone());
+        let server = TestServer::new(app.into_make_service());
+
+        let response = server.get("/runtime/groups/canon").await;
+        assert_eq!(response.status_code(), StatusCode::NOT_FOUND);
+
+        // one platform known to the Tracking DB, one not:
+        let known_id = "54065b5560d0e168c88d4043";
+        let group_put = json!({"platformIds": [known_id, "unknown-platform"]});
+        let response = server.put("/runtime/groups/canon").json(&group_put).await;
+        assert_eq!(response.status_code(), StatusCode::CREATED);
+        let response = server.put("/runtime/groups/canon").json(&group_put).await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let response = server.put("/runtime/groups/%20").json(&group_put).await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+
+        let response = server.get("/runtime/groups/canon").await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let group = response.json::<GroupRes>();
+        assert_eq!(group.platform_ids, vec![known_id, "unknown-platform"]);
+        let response = server.get("/runtime/groups").await;
+        assert_eq!(response.json::<Vec<GroupRes>>().len(), 1);
+
+        // only the known platform is dispatched, with the given details:
+        let response = server
+            .post("/runtime/groups/canon/dispatch")
+            .json(&json!({"expiresAt": "2100-01-01T00:00:00Z", "note": "cruise"}))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.json::<Vec<String>>(), vec![known_id]);
+        {
+            let dispatched_info = dispatched_info.lock().unwrap();
+            assert_eq!(dispatched_info.get_platform_ids(), vec![known_id]);
+            let details = &dispatched_info.get_details()[known_id];
+            assert_eq!(details.note.as_deref(), Some("cruise"));
+            assert_eq!(details.expires_at_ms, Some(4_102_444_800_000));
+        }
+        let response = server
+            .post("/runtime/groups/canon/dispatch")
+            .json(&json!({"expiresAt": "not a time"}))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        let response = server.post("/runtime/groups/other/dispatch").await;
+        assert_eq!(response.status_code(), StatusCode::NOT_FOUND);
+
+        let response = server.post("/runtime/groups/canon/undispatch").await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.json::<Vec<String>>(), vec![known_id]);
+        assert!(dispatched_info
+            .lock()
+            .unwrap()
+            .get_platform_ids()
+            .is_empty());
+
+        let response = server.delete("/runtime/groups/canon").await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let response = server.delete("/runtime/groups/canon").await;
+        assert_eq!(response.status_code(), StatusCode::NOT_FOUND);
+
+        std::fs::remove_dir_all(&store_dir).unwrap();
+    }
+}
@@ -0,0 +1,163 @@
+use crate::history::{History, HistoryEntry};
+use crate::platform_info::PlatformInfo;
+use crate::time_util;
+
+use axum::{
+    extract::{Path, Query, State},
+    response::IntoResponse,
+    routing, Json, Router,
+};
+use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use utoipa::{IntoParams, ToSchema};
+
+pub fn create_history_router(platform_info: Arc<Mutex<PlatformInfo>>) -> Router {
+    Router::new()
+        .route(
+            "/history/platforms/{platform_id}/positions",
+            routing::get(get_history_positions),
+        )
+        .with_state(platform_info)
+}
+
+#[derive(Deserialize, IntoParams, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryQuery {
+    /// Maximum number of last positions to report
+    last_number_of_fixes: Option<usize>,
+    /// Lower limit for time range restriction (ISO 8601 UTC or epoch milliseconds)
+    start_date: Option<String>,
+    /// Upper limit for time range restriction (ISO 8601 UTC or epoch milliseconds)
+    end_date: Option<String>,
+}
+
+/// Positions of a platform from the local history.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryResponse {
+    pub platform_id: String,
+    pub platform_name: Option<String>,
+    pub positions: Vec<HistoryEntry>,
+}
+
+/// Get platform positions from the local history.
+///
+/// These are the positions that have been seen by the dispatcher, along with
+/// the TethysDash instances each was successfully sent to, or, for those rejected
+/// by the fix filter or skipped per decimation, the reason (`notDispatched`).
+/// Unlike for the ODSS request, all given parameters are applied.
+#[utoipa::path(
+    get,
+    path = "/history/platforms/{platform_id}/positions",
+    params(
+        ("platform_id" = String, Path, description = "Platform ID"),
+        HistoryQuery,
+    ),
+    responses(
+        (status = 200, description = "List of positions", body = HistoryResponse),
+        (status = 400, description = "Invalid query parameter"),
+        (status = 404, description = "No history for the platform"),
+    )
+)]
+async fn get_history_positions(
+    State(platform_info): State<Arc<Mutex<PlatformInfo>>>,
+    Path(platform_id): Path<String>,
+    query: Query<HistoryQuery>,
+) -> impl IntoResponse {
+    let query = query.0;
+    log::info!("get_history_positions: platform_id={platform_id} query: {query:?}");
+
+    let parse = |date: &Option<String>| date.as_deref().map(time_util::parse_time_ms).transpose();
+    let (start_ms, end_ms) = match (parse(&query.start_date), parse(&query.end_date)) {
+        (Ok(start_ms), Ok(end_ms)) => (start_ms, end_ms),
+        (Err(e), _) | (_, Err(e)) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+
+    let Some(history) = History::from_config() else {
+        return (StatusCode::NOT_FOUND, "History is not enabled").into_response();
+    };
+    match history.get_entries(&platform_id, start_ms, end_ms, query.last_number_of_fixes) {
+        Ok(Some(positions)) => {
+            let platform_name = platform_info
+                .lock()
+                .unwrap()
+                .get_platform(&platform_id)
+                .map(|p| p.name);
+            Json(HistoryResponse {
+                platform_id,
+                platform_name,
+                positions,
+            })
+            .into_response()
+        }
+        Ok(None) => (StatusCode::NOT_FOUND, "No history for the platform").into_response(),
+        Err(e) => {
+            log::error!("get_history_positions: {e}");
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::init_test_config;
+    use axum_test::*;
+    use pretty_assertions::assert_eq;
+
+    fn create_test_server() -> TestServer {
+        init_test_config();
+
+        let platform_info = Arc::new(Mutex::new(PlatformInfo::default()));
+        let app = create_history_router(platform_info).into_make_service();
+
+        TestServer::new(app)
+    }
+
+    #[tokio::test]
+    async fn it_should_get_history_positions() {
+        let server = create_test_server();
+
+        let platform_id = "history-test:1";
+        let entries = [1000, 2000, 3000].map(|time_ms| HistoryEntry {
+            time_ms,
+            lat: 36.8,
+            lon: -121.9,
+            seen_ms: time_ms + 500,
+            sent_to: vec!["mock".to_string()],
+            not_dispatched: None,
+        });
+        History::from_config()
+            .unwrap()
+            .append(platform_id, &entries)
+            .unwrap();
+
+        let path = format!("/history/platforms/{platform_id}/positions");
+        let response = server.get(&path).await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let history_res = response.json::<HistoryResponse>();
+        assert_eq!(history_res.platform_id, platform_id);
+        assert_eq!(history_res.positions, entries.to_vec());
+
+        let response = server
+            .get(&format!("{path}?startDate=1500&lastNumberOfFixes=1"))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let times = response
+            .json::<HistoryResponse>()
+            .positions
+            .iter()
+            .map(|e| e.time_ms)
+            .collect::<Vec<_>>();
+        assert_eq!(times, vec![3000]);
+
+        let response = server.get(&format!("{path}?endDate=bad")).await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+
+        let response = server
+            .get("/history/platforms/history-test:unknown/positions")
+            .await;
+        assert_eq!(response.status_code(), StatusCode::NOT_FOUND);
+    }
+}
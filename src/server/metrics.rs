@@ -1,6 +1,6 @@
 use axum::{routing, Router};
 use axum_prometheus::PrometheusMetricLayerBuilder;
-use metrics::{describe_gauge, gauge, Unit};
+use metrics::{describe_counter, describe_gauge, gauge, Unit};
 use metrics_process::Collector;
 use sysinfo::{CpuRefreshKind, MemoryRefreshKind, RefreshKind, System};
 
@@ -9,6 +9,9 @@ const FREE_MEMORY: &str = "odss2dash_free_memory_bytes";
 const TOTAL_MEMORY: &str = "odss2dash_total_memory_bytes";
 const CPUS: &str = "odss2dash_cpus";
 const BUILD_INFO: &str = "odss2dash_build_info";
+pub const REJECTED_FIXES: &str = "odss2dash_rejected_fixes_total";
+pub const ALERTS: &str = "odss2dash_alerts_total";
+pub const STALE_PLATFORMS: &str = "odss2dash_stale_platforms";
 
 pub fn create_metrics_router(path: &str) -> Router {
     let (prometheus_layer, metric_handle) = PrometheusMetricLayerBuilder::new()
@@ -26,6 +29,21 @@ pub fn create_metrics_router(path: &str) -> Router {
         BUILD_INFO,
         "Build information about the running binary; value is always 1."
     );
+    describe_counter!(
+        REJECTED_FIXES,
+        Unit::Count,
+        "Fixes rejected by the dispatcher, by reason."
+    );
+    describe_counter!(
+        ALERTS,
+        Unit::Count,
+        "Alerts raised by the dispatcher, by kind."
+    );
+    describe_gauge!(
+        STALE_PLATFORMS,
+        Unit::Count,
+        "Dispatched platforms with no new fix within their staleness threshold."
+    );
 
     Router::new()
         .route(
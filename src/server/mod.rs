@@ -1,6 +1,14 @@
+mod admin;
+mod alerts;
+mod audit;
 mod dispatched;
+mod dispatcher;
+mod geofences;
+mod groups;
 pub mod health;
-mod metrics;
+mod history;
+pub mod metrics;
+mod proximity;
 mod rapidoc;
 mod swagger;
 mod trackdb;
@@ -40,24 +48,67 @@ pub fn launch_server(
         dispatched::get_dispatched_platform,
         dispatched::add_dispatched_platforms,
         dispatched::delete_dispatched_platform,
+        dispatched::get_dispatched_details,
+        dispatched::get_orphans,
+        groups::get_groups,
+        groups::get_group,
+        groups::put_group,
+        groups::delete_group,
+        groups::dispatch_group,
+        groups::undispatch_group,
+        audit::get_audit,
+        geofences::get_geofences,
+        geofences::add_geofences,
+        geofences::delete_geofence,
+        alerts::get_alerts,
+        dispatcher::get_dispatcher_status,
+        proximity::get_proximity,
         trackdb::get_platforms,
         trackdb::get_platform,
         trackdb::get_platform_positions,
+        history::get_history_positions,
+        admin::reload_config,
     ),
     components(
         schemas(
             health::HealthStatus,
             dispatched::PlatformAdd,
             dispatched::PlatformDeleteRes,
+            dispatched::OrphanRes,
+            dispatched::DispatchedDetailsRes,
+            crate::dispatched_info::DispatchDetails,
+            groups::GroupRes,
+            groups::GroupPut,
+            groups::GroupDispatch,
+            crate::audit::AuditEntry,
+            crate::audit::AuditAction,
+            crate::audit::AuditSource,
+            crate::alerts::Alert,
+            crate::alerts::AlertKind,
+            crate::staleness::DispatcherStatus,
+            crate::staleness::PlatformStatus,
+            crate::proximity::ProximityReport,
+            crate::proximity::PairProximity,
             trackdb_client::PlatformRes,
             trackdb_client::PositionsResponse,
             trackdb_client::Position,
+            history::HistoryResponse,
+            crate::history::HistoryEntry,
+            admin::ConfigReloadRes,
         ),
     ),
     tags(
         (name = "health", description = "Basic service status"),
         (name = "dispatched", description = "Dispatched platforms for position notifications"),
+        (name = "groups", description = "Named platform groups for bulk dispatch"),
+        (name = "audit", description = "Audit log of changes to the dispatched platforms"),
+        (name = "geofences", description = "Geofences evaluated by the dispatcher"),
+        (name = "alerts", description = "Alerts raised by the dispatcher"),
+        (name = "dispatcher", description = "Status of the dispatched platforms"),
+        (name = "proximity", description = "Proximity between dispatched platforms"),
         (name = "trackdb", description = "Tracking DB platform information"),
+        (name = "history", description = "Local history of dispatched positions"),
+        (name = "admin", description = "Service administration"),
     )
 )]
 struct ApiDoc;
@@ -73,9 +124,18 @@ async fn launch(
 
     let api_router = {
         let health_router = health::create_health_router();
+        let groups_router =
+            groups::create_groups_router(Arc::clone(&platform_info), Arc::clone(&dispatched_info));
         let dispatched_router =
             dispatched::create_dispatched_router(Arc::clone(&platform_info), dispatched_info);
         let trackdb_router = trackdb::create_trackdb_router(Arc::clone(&platform_info));
+        let history_router = history::create_history_router(Arc::clone(&platform_info));
+        let admin_router = admin::create_admin_router();
+        let audit_router = audit::create_audit_router();
+        let geofences_router = geofences::create_geofences_router();
+        let alerts_router = alerts::create_alerts_router();
+        let dispatcher_router = dispatcher::create_dispatcher_router();
+        let proximity_router = proximity::create_proximity_router();
         let api_path = "/api";
         paths.push(("API", api_path));
         let cors = CorsLayer::permissive(); // TODO not so permissive
@@ -85,7 +145,15 @@ async fn launch(
                 Router::new()
                     .merge(health_router)
                     .merge(dispatched_router)
-                    .merge(trackdb_router),
+                    .merge(groups_router)
+                    .merge(audit_router)
+                    .merge(geofences_router)
+                    .merge(alerts_router)
+                    .merge(dispatcher_router)
+                    .merge(proximity_router)
+                    .merge(trackdb_router)
+                    .merge(history_router)
+                    .merge(admin_router),
             )
             .merge(get_openapi_router(&mut paths))
             .layer(cors)
@@ -109,9 +177,13 @@ async fn launch(
         println!("  {name:8} : {}{path}", &config.external_url);
     }
 
-    axum::serve(listener, app.into_make_service())
-        .with_graceful_shutdown(shutdown_signal())
-        .await?;
+    // with connect info for the client address in the audit log:
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown_signal())
+    .await?;
 
     if let Some(done_sender) = done_sender {
         done_sender.send(()).expect("error sending done message")
@@ -0,0 +1,48 @@
+use crate::proximity;
+
+use axum::{extract::Query, response::IntoResponse, routing, Json, Router};
+use hyper::StatusCode;
+use serde::Deserialize;
+use utoipa::IntoParams;
+
+pub fn create_proximity_router() -> Router {
+    Router::new().route("/proximity", routing::get(get_proximity))
+}
+
+#[derive(Deserialize, IntoParams, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ProximityQuery {
+    /// Only pairs including this platform
+    platform_id: Option<String>,
+}
+
+/// Get the current pairwise distances between dispatched platforms.
+///
+/// For each pair of platforms selected by the configured `proximity` rules, as last
+/// determined by the dispatcher, the separation and the projected closest point of
+/// approach (CPA), per the velocities from their recent fixes, and whether either
+/// is below the distance threshold of the rule.
+#[utoipa::path(
+    get,
+    path = "/proximity",
+    params(
+        ProximityQuery,
+    ),
+    responses(
+        (status = 200, description = "Proximity report", body = proximity::ProximityReport),
+        (status = 404, description = "No proximity report saved by the dispatcher yet"),
+    )
+)]
+async fn get_proximity(query: Query<ProximityQuery>) -> impl IntoResponse {
+    let query = query.0;
+    log::info!("get_proximity: query: {query:?}");
+    let Some(mut report) = proximity::load_report() else {
+        return (StatusCode::NOT_FOUND, "No proximity report yet").into_response();
+    };
+    if let Some(platform_id) = &query.platform_id {
+        report
+            .pairs
+            .retain(|p| p.platform_ids.contains(platform_id));
+    }
+    Json(report).into_response()
+}
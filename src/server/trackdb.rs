@@ -1,3 +1,4 @@
+use crate::decimation;
 use crate::platform_info::PlatformInfo;
 use crate::trackdb_client::{self, PlatformRes, PositionsResponse};
 
@@ -51,13 +52,13 @@ async fn get_platforms(
     log::info!("get_platforms query: {:?}", query);
 
     if query.refresh == Some(true) {
+        let platforms_by_source = trackdb_client::get_platforms_by_source();
         let mut platform_info = platform_info.lock().unwrap();
-        let platforms_res = trackdb_client::get_platforms();
-        if platforms_res.is_empty() {
+        let refreshed = platform_info.apply_refresh(platforms_by_source);
+        if refreshed.is_empty() {
             log::warn!("get_platforms: no platforms found; returning cached platforms");
         } else {
-            log::info!("get_platforms: {} platforms found", platforms_res.len());
-            platform_info.set_platforms(platforms_res);
+            log::info!("get_platforms: refreshed sources: {}", refreshed.join(", "));
         }
         Json(platform_info.get_platforms())
     } else {
@@ -112,6 +113,11 @@ pub struct PositionsQuery {
     start_date: Option<String>,
     /// Upper limit for time range restriction
     end_date: Option<String>,
+    /// Tolerance in meters to simplify the track (Douglas–Peucker)
+    simplify: Option<f64>,
+    /// Whether to include the speed and course over ground, and the distance from the
+    /// previous fix and along the track
+    kinematics: Option<bool>,
 }
 
 /// Get latest platform positions.
@@ -123,6 +129,13 @@ pub struct PositionsQuery {
 /// to the request to ODSS if any of `startDate` or `endDate` is given,
 /// in which case, odss2dash will apply the `lastNumberOfFixes` limit on the response
 /// from ODSS prior to responding to this request.
+///
+/// With `simplify`, only the positions needed to keep the track within the given
+/// tolerance are returned, so long playback tracks stay light.
+///
+/// With `kinematics=true`, each position includes `distance` (meters from the previous
+/// fix), `cumulativeDistance` (meters along the track), `sog` (speed over ground in m/s),
+/// and `cog` (course over ground in degrees), computed before any simplification.
 #[utoipa::path(
     get,
     path = "/trackdb/platforms/{platform_id}/positions",
@@ -131,7 +144,9 @@ pub struct PositionsQuery {
         PositionsQuery,
     ),
     responses(
-       (status = 200, description = "List of positions", body = PositionsResponse)
+       (status = 200, description = "List of positions", body = PositionsResponse),
+       (status = 400, description = "Invalid simplify tolerance"),
+       (status = 404, description = "Platform not found")
     )
 )]
 async fn get_platform_positions(
@@ -145,6 +160,14 @@ async fn get_platform_positions(
         query
     );
 
+    if query.simplify.is_some_and(|t| t.is_nan() || t <= 0.0) {
+        return (
+            StatusCode::BAD_REQUEST,
+            "simplify must be greater than zero",
+        )
+            .into_response();
+    }
+
     let platform_info = get_platform_info(platform_info);
 
     let positions = trackdb_client::get_positions(
@@ -156,8 +179,16 @@ async fn get_platform_positions(
     match positions {
         Some(pos_res) => {
             let platform_name = platform_info.get_platform(&platform_id).map(|p| p.name);
+            let mut positions = pos_res.positions;
+            if query.kinematics == Some(true) {
+                trackdb_client::add_kinematics(None, &mut positions);
+            }
+            if let Some(tolerance_m) = query.simplify {
+                positions = decimation::simplify(positions, tolerance_m);
+            }
             Json(PositionsResponse {
                 platform_name,
+                positions,
                 ..pos_res
             })
             .into_response()
@@ -173,24 +204,13 @@ fn get_platform_info(platform_info: Arc<Mutex<PlatformInfo>>) -> PlatformInfo {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::{set_config, Config};
+    use crate::config::init_test_config;
     use axum_test::*;
     use http::status::StatusCode;
     use pretty_assertions::assert_eq;
 
-    fn odss_api() -> String {
-        // TODO launch OdssApi mock server
-        // ...
-        // return mock server address:
-        "https://odss.mbari.org/odss".to_string()
-    }
-
     fn create_test_server() -> TestServer {
-        set_config(Config {
-            odss_api: odss_api(),
-            default_last_number_of_fixes: 2,
-            ..Config::default()
-        });
+        init_test_config();
 
         let platform_info = Arc::new(Mutex::new(PlatformInfo::default()));
         let app = create_trackdb_router(platform_info).into_make_service();
@@ -203,26 +223,92 @@ mod tests {
         let server = create_test_server();
 
         let response = server.get("/trackdb/platforms").await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+
+        let response = server.get("/trackdb/platforms?refresh=true").await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.json::<Vec<PlatformRes>>().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn it_should_get_platform_positions() {
+        let server = create_test_server();
+
+        let response = server
+            .get("/trackdb/platforms/54065b5560d0e168c88d4043/positions")
+            .await;
 
         assert_eq!(response.status_code(), StatusCode::OK);
+        let pos_res = response.json::<PositionsResponse>();
+        let times = pos_res
+            .positions
+            .iter()
+            .map(|p| p.time_ms)
+            .collect::<Vec<_>>();
+        assert_eq!(times, vec![1700000300000, 1700000200000]);
     }
 
-    // #[tokio::test]
-    // async fn it_should_return_404_for_bad_platform() {
-    //     let server = create_test_server();
-    //
-    //     let response = server.get("/trackdb/platforms/BAD").await;
-    //
-    //     assert_eq!(response.status_code(), StatusCode::NOT_FOUND);
-    // }
-
-    // #[tokio::test]
-    // async fn it_should_return_404_for_bad_platform_positions() {
-    //     let server = create_test_server();
-    //
-    //     let response = server.get("/trackdb/platforms/BAD/positions").await;
-    //
-    //     println!("TEXT = {:?}", response.text());
-    //     assert_eq!(response.status_code(), StatusCode::NOT_FOUND);
-    // }
+    #[tokio::test]
+    async fn it_should_simplify_platform_positions() {
+        let server = create_test_server();
+        let path = "/trackdb/platforms/5d5b2ea653a65f9ec656d872/positions";
+
+        let response = server
+            .get(&format!("{path}?lastNumberOfFixes=3&simplify=5000"))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let pos_res = response.json::<PositionsResponse>();
+        let times = pos_res
+            .positions
+            .iter()
+            .map(|p| p.time_ms)
+            .collect::<Vec<_>>();
+        assert_eq!(times, vec![1700000240000, 1700000000000]);
+
+        let response = server.get(&format!("{path}?simplify=0")).await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn it_should_include_kinematics() {
+        let server = create_test_server();
+
+        let response = server
+            .get("/trackdb/platforms/5d5b2ea653a65f9ec656d872/positions?lastNumberOfFixes=3&kinematics=true")
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let pos_res = response.json::<PositionsResponse>();
+        let kinematics = pos_res
+            .positions
+            .iter()
+            .map(|p| p.kinematics.clone().unwrap())
+            .collect::<Vec<_>>();
+        // most recent first, with the first fix having no speed or course:
+        assert_eq!(kinematics[2].distance, 0.0);
+        assert_eq!(kinematics[2].sog, None);
+        let sog = kinematics[0].sog.unwrap();
+        assert!((sog - kinematics[0].distance / 120.0).abs() < 1e-9, "{sog}");
+        let cog = kinematics[0].cog.unwrap();
+        assert!(cog > 90.0 && cog < 180.0, "{cog}");
+        let cumulative = kinematics[0].cumulative_distance.unwrap();
+        assert!((cumulative - kinematics[0].distance - kinematics[1].distance).abs() < 1e-6);
+    }
+
+    #[tokio::test]
+    async fn it_should_return_404_for_bad_platform() {
+        let server = create_test_server();
+
+        let response = server.get("/trackdb/platforms/BAD").await;
+
+        assert_eq!(response.status_code(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn it_should_return_404_for_bad_platform_positions() {
+        let server = create_test_server();
+
+        let response = server.get("/trackdb/platforms/BAD/positions").await;
+
+        assert_eq!(response.status_code(), StatusCode::NOT_FOUND);
+    }
 }
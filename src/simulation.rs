@@ -0,0 +1,412 @@
+//! Tracking DB source backed by simulated platforms, for training and UI development.
+//! Fixes are a deterministic function of the fix index since the simulation start
+//! (when the program started), so repeated requests see consistent tracks.
+//! Only the fixes in the requested window are computed, with the random walks
+//! resumed from periodic checkpoints of their state.
+
+use crate::geo::{self, LatLon};
+use crate::time_util;
+use crate::trackdb_client::{PlatformRes, Position};
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+/// Simulated platforms.
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulationConfig {
+    /// Seed for the pseudo-random dropouts, jitter, and random walks.
+    #[serde(default)]
+    pub seed: u64,
+    pub platforms: Vec<SimulatedPlatform>,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulatedPlatform {
+    pub id: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub type_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon_url: Option<String>,
+    /// Speed over ground in meters per second.
+    pub speed: f64,
+    #[serde(with = "humantime_serde")]
+    pub fix_period: Duration,
+    /// Probability of a fix being missing.
+    #[serde(default)]
+    pub dropout: f64,
+    /// Maximum position error of a fix, in meters.
+    #[serde(default)]
+    pub jitter: f64,
+    pub motion: Motion,
+}
+
+/// How a simulated platform moves, with points given as `[lat, lon]`.
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub enum Motion {
+    /// Along the waypoints, looping back to the first one.
+    /// A single waypoint gives a stationary platform, e.g., a mooring.
+    Waypoints(Vec<LatLon>),
+    /// Back and forth along the great circle between the two points.
+    #[serde(rename_all = "camelCase")]
+    GreatCircle { from: LatLon, to: LatLon },
+    /// Random walk from the start point, turning up to `maxTurn` degrees at each fix.
+    #[serde(rename_all = "camelCase")]
+    RandomWalk {
+        start: LatLon,
+        #[serde(default = "default_max_turn")]
+        max_turn: f64,
+    },
+}
+
+fn default_max_turn() -> f64 {
+    30.0
+}
+
+impl SimulationConfig {
+    /// Checks the simulation settings, returning any problems found.
+    pub fn validate(&self, setting: &str) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut ids = Vec::new();
+        for p in &self.platforms {
+            let mut problem = |msg: &str| {
+                problems.push(format!("{setting}.platforms['{}']: {msg}", p.id));
+            };
+            if ids.contains(&&p.id) {
+                problem("duplicate id");
+            }
+            ids.push(&p.id);
+            if p.speed < 0.0 {
+                problem("speed must be non-negative");
+            }
+            if p.fix_period.is_zero() {
+                problem("fixPeriod must be greater than zero");
+            }
+            if !(0.0..1.0).contains(&p.dropout) {
+                problem("dropout must be in [0, 1)");
+            }
+            if p.jitter < 0.0 {
+                problem("jitter must be non-negative");
+            }
+            let points = match &p.motion {
+                Motion::Waypoints(points) => points.clone(),
+                Motion::GreatCircle { from, to } => vec![*from, *to],
+                Motion::RandomWalk { start, .. } => vec![*start],
+            };
+            if points.is_empty() {
+                problem("no waypoints");
+            }
+            if points
+                .iter()
+                .any(|(lat, lon)| !(-90.0..=90.0).contains(lat) || !(-180.0..=180.0).contains(lon))
+            {
+                problem("invalid point");
+            }
+        }
+        problems
+    }
+}
+
+/// Time of the simulation start, that is, of the first use of the simulation.
+fn simulation_start_ms() -> u64 {
+    static START_MS: OnceLock<u64> = OnceLock::new();
+    *START_MS.get_or_init(time_util::now_ms)
+}
+
+pub fn get_platforms(config: &SimulationConfig) -> Vec<PlatformRes> {
+    config
+        .platforms
+        .iter()
+        .map(|p| PlatformRes {
+            _id: p.id.clone(),
+            name: p.name.clone(),
+            abbreviation: p.id.clone(),
+            type_name: p.type_name.clone(),
+            color: p.color.clone(),
+            icon_url: p.icon_url.clone(),
+        })
+        .collect()
+}
+
+pub fn get_platform(config: &SimulationConfig, platform_id: &str) -> Option<PlatformRes> {
+    get_platforms(config)
+        .into_iter()
+        .find(|p| p._id == platform_id)
+}
+
+/// Gets the fixes of the platform so far, most recent first, in the given time range,
+/// or else the last number of fixes, as the ODSS would.
+pub fn get_positions(
+    config: &SimulationConfig,
+    platform_id: &str,
+    last_number_of_fixes: u32,
+    start_date: Option<&str>,
+    end_date: Option<&str>,
+) -> Result<Vec<Position>, String> {
+    let Some(platform) = config.platforms.iter().find(|p| p.id == platform_id) else {
+        return Ok(Vec::new());
+    };
+    let start_ms = start_date.map(time_util::parse_time_ms).transpose()?;
+    let end_ms = end_date.map(time_util::parse_time_ms).transpose()?;
+    let sim_start_ms = simulation_start_ms();
+    let period_ms = platform.fix_period.as_millis().max(1) as u64;
+    let last_k = time_util::now_ms().saturating_sub(sim_start_ms) / period_ms;
+
+    if start_ms.is_some() || end_ms.is_some() {
+        let first_k = start_ms.map_or(0, |s| s.saturating_sub(sim_start_ms).div_ceil(period_ms));
+        let last_k = match end_ms {
+            Some(e) if e < sim_start_ms => return Ok(Vec::new()),
+            Some(e) => last_k.min((e - sim_start_ms) / period_ms),
+            None => last_k,
+        };
+        if first_k > last_k {
+            return Ok(Vec::new());
+        }
+        let fixes = simulate(config.seed, platform, sim_start_ms, first_k..=last_k);
+        return Ok(fixes.into_iter().rev().collect());
+    }
+
+    // the window with the last number of fixes, widened as needed per the dropouts:
+    let limit = last_number_of_fixes as usize;
+    let mut span = (limit as u64).max(1) * 2;
+    loop {
+        let first_k = last_k.saturating_sub(span - 1);
+        let fixes = simulate(config.seed, platform, sim_start_ms, first_k..=last_k);
+        if fixes.len() >= limit || first_k == 0 {
+            return Ok(fixes.into_iter().rev().take(limit).collect());
+        }
+        span *= 2;
+    }
+}
+
+/// The fixes of the platform with the given indices since the simulation start,
+/// in chronological order.
+fn simulate(
+    seed: u64,
+    platform: &SimulatedPlatform,
+    start_ms: u64,
+    indices: RangeInclusive<u64>,
+) -> Vec<Position> {
+    let period_ms = platform.fix_period.as_millis().max(1) as u64;
+    let random = Random::new(seed, &platform.id);
+    let step = platform.speed * period_ms as f64 / 1000.0;
+
+    let mut walk = match platform.motion {
+        Motion::RandomWalk { .. } => Some(walk_state(seed, platform, &random, *indices.start())),
+        _ => None,
+    };
+    let mut fixes = Vec::new();
+    for k in indices.clone() {
+        let position = match (&platform.motion, &mut walk) {
+            (Motion::RandomWalk { max_turn, .. }, Some(state)) => {
+                if k > *indices.start() {
+                    *state = walk_step(*state, k, &random, *max_turn, step);
+                }
+                state.0
+            }
+            (motion, _) => position_along(motion, step * k as f64),
+        };
+        if random.get(k, 1) < platform.dropout {
+            continue;
+        }
+        let position = if platform.jitter > 0.0 {
+            let error = random.get(k, 2) * platform.jitter;
+            geo::destination(position, random.get(k, 3) * 360.0, error)
+        } else {
+            position
+        };
+        fixes.push(Position {
+            time_ms: start_ms + k * period_ms,
+            lat: position.0,
+            lon: position.1,
+            kinematics: None,
+        });
+    }
+    fixes
+}
+
+/// Position and heading of a random walk.
+type WalkState = (LatLon, f64);
+
+/// Number of steps between the checkpoints of a random walk.
+const WALK_CHECKPOINT_INTERVAL: u64 = 1000;
+
+/// The state of the random walk of the platform at the given fix index, resumed from
+/// the latest checkpoint at or before it, with any missing checkpoints added.
+fn walk_state(seed: u64, platform: &SimulatedPlatform, random: &Random, k: u64) -> WalkState {
+    static CHECKPOINTS: OnceLock<Mutex<HashMap<String, Vec<WalkState>>>> = OnceLock::new();
+    let Motion::RandomWalk { start, max_turn } = platform.motion else {
+        panic!("not a random walk");
+    };
+    let step = platform.speed * platform.fix_period.as_millis().max(1) as f64 / 1000.0;
+
+    // keyed by all that determines the walk, in case of a configuration reload:
+    let key = format!("{seed}:{}", serde_json::to_string(platform).unwrap());
+    let mut checkpoints = CHECKPOINTS.get_or_init(Default::default).lock().unwrap();
+    let checkpoints = checkpoints
+        .entry(key)
+        .or_insert_with(|| vec![(start, random.get(0, 0) * 360.0)]);
+    let index = (k / WALK_CHECKPOINT_INTERVAL) as usize;
+    while checkpoints.len() <= index {
+        let from_k = (checkpoints.len() as u64 - 1) * WALK_CHECKPOINT_INTERVAL;
+        let mut state = *checkpoints.last().unwrap();
+        for k in from_k + 1..=from_k + WALK_CHECKPOINT_INTERVAL {
+            state = walk_step(state, k, random, max_turn, step);
+        }
+        checkpoints.push(state);
+    }
+    let mut state = checkpoints[index];
+    for k in index as u64 * WALK_CHECKPOINT_INTERVAL + 1..=k {
+        state = walk_step(state, k, random, max_turn, step);
+    }
+    state
+}
+
+/// The state of a random walk at fix index `k` from that at `k - 1`.
+fn walk_step(state: WalkState, k: u64, random: &Random, max_turn: f64, step: f64) -> WalkState {
+    let heading = state.1 + (random.get(k, 0) * 2.0 - 1.0) * max_turn;
+    (geo::destination(state.0, heading, step), heading)
+}
+
+/// Position after traveling the given distance along a path motion.
+fn position_along(motion: &Motion, distance: f64) -> LatLon {
+    let points = match motion {
+        Motion::Waypoints(points) => points.clone(),
+        Motion::GreatCircle { from, to } => vec![*from, *to],
+        Motion::RandomWalk { start, .. } => return *start,
+    };
+    // including the leg back to the first point:
+    let legs = points
+        .iter()
+        .zip(points.iter().cycle().skip(1))
+        .map(|(from, to)| (*from, *to, geo::distance_m(*from, *to)))
+        .collect::<Vec<_>>();
+    let total = legs.iter().map(|(_, _, length)| length).sum::<f64>();
+    let mut remaining = if total > 0.0 {
+        distance.rem_euclid(total)
+    } else {
+        0.0
+    };
+    for (from, to, length) in legs {
+        if remaining < length {
+            return geo::interpolate(from, to, remaining / length);
+        }
+        remaining -= length;
+    }
+    points[0]
+}
+
+/// Deterministic pseudo-random numbers for a platform.
+struct Random {
+    base: u64,
+}
+
+impl Random {
+    fn new(seed: u64, platform_id: &str) -> Self {
+        let base = platform_id
+            .bytes()
+            .fold(splitmix64(seed), |h, b| splitmix64(h ^ b as u64));
+        Random { base }
+    }
+
+    /// Number in `[0, 1)` for the given fix index and purpose.
+    fn get(&self, k: u64, purpose: u64) -> f64 {
+        let value = splitmix64(self.base ^ splitmix64(k.wrapping_mul(4).wrapping_add(purpose)));
+        (value >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn platform(motion: Motion) -> SimulatedPlatform {
+        SimulatedPlatform {
+            id: "sim1".to_string(),
+            name: "Sim 1".to_string(),
+            type_name: None,
+            color: None,
+            icon_url: None,
+            speed: 10.0,
+            fix_period: Duration::from_secs(60),
+            dropout: 0.0,
+            jitter: 0.0,
+            motion,
+        }
+    }
+
+    #[test]
+    fn test_simulate() {
+        let (from, to) = ((36.8, -122.0), (36.8, -121.9));
+        let length = geo::distance_m(from, to);
+        let round_trip_ms = (2.0 * length / 10.0 * 1000.0) as u64;
+        let fixes = simulate(
+            0,
+            &platform(Motion::GreatCircle { from, to }),
+            0,
+            0..=round_trip_ms / 60_000,
+        );
+        assert_eq!(fixes.len() as u64, round_trip_ms / 60_000 + 1);
+        assert!(geo::distance_m(from, (fixes[0].lat, fixes[0].lon)) < 1e-6);
+        // the farthest fix is near the turning point:
+        let max_distance = fixes
+            .iter()
+            .map(|p| geo::distance_m(from, (p.lat, p.lon)))
+            .fold(0.0, f64::max);
+        assert!(length - max_distance < 600.0 + 1e-6, "{max_distance}");
+
+        // random walk is deterministic per seed, with exact step lengths:
+        let walk = platform(Motion::RandomWalk {
+            start: from,
+            max_turn: 45.0,
+        });
+        let fixes = simulate(7, &walk, 0, 0..=60);
+        assert_eq!(fixes.len(), 61);
+        for pair in fixes.windows(2) {
+            let step = geo::distance_m((pair[0].lat, pair[0].lon), (pair[1].lat, pair[1].lon));
+            assert!((step - 600.0).abs() < 1e-6, "{step}");
+        }
+        let again = simulate(7, &walk, 0, 0..=60);
+        assert_eq!(fixes.last().unwrap().lat, again.last().unwrap().lat);
+
+        // a window is as in the full simulation, including beyond walk checkpoints:
+        let points = |fixes: &[Position]| {
+            fixes
+                .iter()
+                .map(|p| (p.time_ms, p.lat, p.lon))
+                .collect::<Vec<_>>()
+        };
+        let all = simulate(7, &walk, 0, 0..=2500);
+        let window = simulate(7, &walk, 0, 2400..=2500);
+        assert_eq!(points(&window), points(&all[2400..]));
+        let window = simulate(7, &walk, 0, 999..=1001);
+        assert_eq!(points(&window), points(&all[999..=1001]));
+
+        let mooring = SimulatedPlatform {
+            dropout: 0.5,
+            jitter: 20.0,
+            ..platform(Motion::Waypoints(vec![from]))
+        };
+        let fixes = simulate(0, &mooring, 0, 0..=100);
+        assert!(fixes.len() > 20 && fixes.len() < 80, "{}", fixes.len());
+        assert!(fixes
+            .iter()
+            .all(|p| geo::distance_m(from, (p.lat, p.lon)) <= 20.0 + 1e-6));
+    }
+}
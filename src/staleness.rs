@@ -0,0 +1,265 @@
+//! Detection of dispatched platforms that stopped reporting, per the time since their
+//! latest fix, with thresholds in general, by platform type, or by platform ID.
+//! The resulting status is kept under the state directory (available via
+//! `/api/dispatcher/status`), so restarts do not re-fire the stale/recovered alerts.
+
+use crate::alerts::{Alert, AlertKind};
+use crate::config;
+use crate::state_file;
+use crate::trackdb_client::PlatformRes;
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use utoipa::ToSchema;
+
+/// File under the state directory with the latest dispatcher status.
+const STATUS_FILENAME: &str = "dispatcher_status.json";
+
+/// Thresholds for a platform to be considered stale, with that for a platform given
+/// by its ID, or else by its type name, or else the general one.
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct StalenessConfig {
+    /// Default: no staleness check, except per `by_type` or `by_platform`.
+    #[serde(
+        default,
+        with = "humantime_serde",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub threshold: Option<Duration>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub by_type: BTreeMap<String, humantime_serde::Serde<Duration>>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub by_platform: BTreeMap<String, humantime_serde::Serde<Duration>>,
+    /// Whether to raise alerts when a platform goes stale or recovers. These go to the
+    /// alert sinks (the alerts file and any webhooks), not to the TethysDash instances,
+    /// whose XEvents API only takes positions.
+    #[serde(default)]
+    pub notify: bool,
+}
+
+impl StalenessConfig {
+    pub fn threshold_for(&self, platform_id: &str, type_name: Option<&str>) -> Option<Duration> {
+        self.by_platform
+            .get(platform_id)
+            .or_else(|| type_name.and_then(|t| self.by_type.get(t)))
+            .map(|d| **d)
+            .or(self.threshold)
+    }
+
+    /// Checks the settings, returning any problems found.
+    pub fn validate(&self) -> Vec<String> {
+        let thresholds = self
+            .threshold
+            .iter()
+            .map(|t| ("staleness.threshold".to_string(), *t))
+            .chain(
+                self.by_type
+                    .iter()
+                    .map(|(t, d)| (format!("staleness.byType['{t}']"), **d)),
+            )
+            .chain(
+                self.by_platform
+                    .iter()
+                    .map(|(id, d)| (format!("staleness.byPlatform['{id}']"), **d)),
+            );
+        thresholds
+            .filter(|(_, threshold)| threshold.is_zero())
+            .map(|(setting, _)| format!("{setting} must be greater than zero"))
+            .collect()
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PlatformStatus {
+    pub platform_id: String,
+    pub platform_name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub type_name: Option<String>,
+    /// Time of the latest fix seen by the dispatcher, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_fix_ms: Option<u64>,
+    /// Staleness threshold in seconds, if any applies to the platform.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stale_after_secs: Option<u64>,
+    pub stale: bool,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DispatcherStatus {
+    /// When the status was determined.
+    pub updated_ms: u64,
+    /// The dispatched platforms.
+    pub platforms: Vec<PlatformStatus>,
+}
+
+impl DispatcherStatus {
+    pub fn num_stale(&self) -> usize {
+        self.platforms.iter().filter(|p| p.stale).count()
+    }
+}
+
+/// The status last saved by the dispatcher, if any.
+pub fn load_status() -> Option<DispatcherStatus> {
+    let path = config::get_config().state_path(STATUS_FILENAME);
+    state_file::load_json(&path)
+}
+
+pub fn save_status(status: &DispatcherStatus) {
+    let path = config::get_config().state_path(STATUS_FILENAME);
+    if let Err(e) = state_file::save_json(&path, status) {
+        log::error!("Failed to save dispatcher status: {e}");
+    }
+}
+
+/// Keeps track of the platforms going stale or recovering.
+#[derive(Default)]
+pub struct StalenessMonitor {
+    /// The previous status by platform ID, loaded upon first use.
+    previous: Option<BTreeMap<String, PlatformStatus>>,
+}
+
+impl StalenessMonitor {
+    /// Determines the status of the given platforms, each with the time of its latest
+    /// fix, if known (otherwise, that in the previous status is used), also returning
+    /// the alerts for any platform going stale or recovering.
+    pub fn evaluate(
+        &mut self,
+        config: &StalenessConfig,
+        platforms: &[(PlatformRes, Option<u64>)],
+        now_ms: u64,
+    ) -> (DispatcherStatus, Vec<Alert>) {
+        let previous = self.previous.get_or_insert_with(|| {
+            load_status()
+                .map(|s| s.platforms)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|p| (p.platform_id.clone(), p))
+                .collect()
+        });
+        let mut alerts = Vec::new();
+        let mut statuses = Vec::new();
+        for (platform, last_fix_ms) in platforms {
+            let before = previous.get(&platform._id);
+            let last_fix_ms = last_fix_ms.or(before.and_then(|b| b.last_fix_ms));
+            let threshold = config.threshold_for(&platform._id, platform.type_name.as_deref());
+            let stale = match (last_fix_ms, threshold) {
+                (Some(last_fix_ms), Some(threshold)) => {
+                    now_ms.saturating_sub(last_fix_ms) > threshold.as_millis() as u64
+                }
+                _ => false,
+            };
+            let was_stale = before.is_some_and(|b| b.stale);
+            if stale != was_stale {
+                let (kind, what) = if stale {
+                    let silent_for = Duration::from_secs(
+                        now_ms.saturating_sub(last_fix_ms.unwrap_or(now_ms)) / 1000,
+                    );
+                    let silent_for = humantime::format_duration(silent_for);
+                    (AlertKind::Stale, format!("no new fix for {silent_for}"))
+                } else {
+                    (AlertKind::Recovered, "reporting again".to_string())
+                };
+                alerts.push(Alert {
+                    time_ms: last_fix_ms.unwrap_or(now_ms),
+                    raised_ms: now_ms,
+                    kind,
+                    platform_id: platform._id.clone(),
+                    platform_name: platform.name.clone(),
+                    geofence: None,
+                    other_platform_id: None,
+                    message: format!("{} ({}): {what}", platform.name, platform._id),
+                    position: None,
+                });
+            }
+            statuses.push(PlatformStatus {
+                platform_id: platform._id.clone(),
+                platform_name: platform.name.clone(),
+                type_name: platform.type_name.clone(),
+                last_fix_ms,
+                stale_after_secs: threshold.map(|t| t.as_secs()),
+                stale,
+            });
+        }
+        *previous = statuses
+            .iter()
+            .map(|s| (s.platform_id.clone(), s.clone()))
+            .collect();
+        let status = DispatcherStatus {
+            updated_ms: now_ms,
+            platforms: statuses,
+        };
+        (status, alerts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_staleness() {
+        let config = StalenessConfig {
+            threshold: Some(Duration::from_secs(600)),
+            by_type: BTreeMap::from([("glider".to_string(), Duration::from_secs(3600).into())]),
+            ..StalenessConfig::default()
+        };
+        let platform = |id: &str, type_name: Option<&str>| PlatformRes {
+            _id: id.to_string(),
+            name: id.to_string(),
+            abbreviation: id.to_string(),
+            type_name: type_name.map(|t| t.to_string()),
+            color: None,
+            icon_url: None,
+        };
+        let lrauv = platform("lrauv", Some("lrauv"));
+        let glider = platform("glider", Some("glider"));
+        let mut monitor = StalenessMonitor {
+            previous: Some(BTreeMap::new()),
+        };
+        let minute_ms = 60_000;
+        let now_ms = 1_000 * minute_ms;
+
+        // lrauv: 20 min silent; glider: 20 min silent, but within its threshold
+        let platforms = vec![
+            (lrauv.clone(), Some(now_ms - 20 * minute_ms)),
+            (glider.clone(), Some(now_ms - 20 * minute_ms)),
+        ];
+        let (status, alerts) = monitor.evaluate(&config, &platforms, now_ms);
+        assert_eq!(status.num_stale(), 1);
+        assert_eq!(status.platforms[1].stale_after_secs, Some(3600));
+        let kinds = |alerts: &[Alert]| {
+            alerts
+                .iter()
+                .map(|a| (a.platform_id.clone(), a.kind))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            kinds(&alerts),
+            vec![("lrauv".to_string(), AlertKind::Stale)]
+        );
+        assert_eq!(alerts[0].message, "lrauv (lrauv): no new fix for 20m");
+
+        // no fix known this time: still stale, but no alert again
+        let platforms = vec![(lrauv.clone(), None), (glider.clone(), None)];
+        let (status, alerts) = monitor.evaluate(&config, &platforms, now_ms + minute_ms);
+        assert_eq!(status.num_stale(), 1);
+        assert!(alerts.is_empty());
+
+        // lrauv reporting again, glider now beyond its threshold
+        let platforms = vec![(lrauv, Some(now_ms + 100 * minute_ms)), (glider, None)];
+        let (status, alerts) = monitor.evaluate(&config, &platforms, now_ms + 101 * minute_ms);
+        assert_eq!(status.num_stale(), 1);
+        assert_eq!(
+            kinds(&alerts),
+            vec![
+                ("lrauv".to_string(), AlertKind::Recovered),
+                ("glider".to_string(), AlertKind::Stale),
+            ]
+        );
+    }
+}
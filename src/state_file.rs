@@ -0,0 +1,193 @@
+use serde::{de::DeserializeOwned, Serialize};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Saves the value as JSON in the given file in a crash-safe manner:
+/// the contents are written to a temporary file, which is synced and then renamed
+/// to the target file. Before that, the current file, if valid, is copied to a
+/// backup file (`<file>.bak`) to allow recovery via [load_json].
+pub fn save_json<T: Serialize>(path: &Path, value: &T) -> io::Result<()> {
+    if let Some(dir) = parent_dir(path) {
+        fs::create_dir_all(dir)?;
+    }
+
+    let tmp_path = sibling_path(path, "tmp");
+    {
+        let mut f = File::create(&tmp_path)?;
+        serde_json::to_writer_pretty(&mut f, value)?;
+        f.write_all(b"\n")?;
+        f.sync_all()?;
+    }
+
+    if is_valid_json(path) {
+        fs::copy(path, backup_path(path))?;
+    }
+    fs::rename(&tmp_path, path)?;
+    sync_dir(path);
+    Ok(())
+}
+
+/// Loads the value from the given JSON file, returning `None` if the file does not exist.
+/// If the file cannot be read or parsed, it is moved aside (`<file>.corrupt`) and the
+/// backup file is used instead, if valid, being also restored as the file; otherwise `None`
+/// is also returned, so the caller can start afresh. These situations are reported loudly
+/// in any case.
+pub fn load_json<T: DeserializeOwned>(path: &Path) -> Option<T> {
+    if !path.exists() {
+        return None;
+    }
+    let error = match read_json(path) {
+        Ok(value) => return Some(value),
+        Err(e) => e,
+    };
+
+    let corrupt_path = sibling_path(path, "corrupt");
+    log::error!(
+        "Failed to load '{}': {error}. Moving it to '{}'",
+        path.display(),
+        corrupt_path.display()
+    );
+    eprintln!(
+        "error: failed to load '{}': {error}. Moving it to '{}'",
+        path.display(),
+        corrupt_path.display()
+    );
+    if let Err(e) = fs::rename(path, &corrupt_path) {
+        log::error!("Failed to move '{}': {e}", path.display());
+    }
+
+    let backup_path = backup_path(path);
+    match read_json(&backup_path) {
+        Ok(value) => {
+            log::warn!("Recovered '{}' from backup", path.display());
+            eprintln!("warning: recovered '{}' from backup", path.display());
+            // so subsequent loads also get it:
+            if let Err(e) = restore_backup(&backup_path, path) {
+                log::error!("Failed to restore '{}' from backup: {e}", path.display());
+                eprintln!(
+                    "error: failed to restore '{}' from backup: {e}",
+                    path.display()
+                );
+            }
+            Some(value)
+        }
+        Err(e) => {
+            log::error!(
+                "No usable backup '{}': {e}. Starting afresh",
+                backup_path.display()
+            );
+            eprintln!(
+                "error: no usable backup '{}': {e}. Starting afresh",
+                backup_path.display()
+            );
+            None
+        }
+    }
+}
+
+/// Copies the backup to the file in the same crash-safe manner as [save_json].
+fn restore_backup(backup_path: &Path, path: &Path) -> io::Result<()> {
+    let tmp_path = sibling_path(path, "tmp");
+    fs::copy(backup_path, &tmp_path)?;
+    File::open(&tmp_path)?.sync_all()?;
+    fs::rename(&tmp_path, path)?;
+    sync_dir(path);
+    Ok(())
+}
+
+fn read_json<T: DeserializeOwned>(path: &Path) -> Result<T, String> {
+    let s = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    serde_json::from_str(&s).map_err(|e| e.to_string())
+}
+
+fn is_valid_json(path: &Path) -> bool {
+    read_json::<serde_json::Value>(path).is_ok()
+}
+
+pub fn backup_path(path: &Path) -> PathBuf {
+    sibling_path(path, "bak")
+}
+
+/// `dir/name.json` -> `dir/name.json.<extension>`
+fn sibling_path(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(extension);
+    path.with_file_name(name)
+}
+
+fn parent_dir(path: &Path) -> Option<&Path> {
+    path.parent().filter(|dir| !dir.as_os_str().is_empty())
+}
+
+/// So the rename itself is persisted.
+fn sync_dir(path: &Path) {
+    #[cfg(unix)]
+    {
+        let dir = parent_dir(path).unwrap_or(Path::new("."));
+        if let Err(e) = File::open(dir).and_then(|d| d.sync_all()) {
+            log::warn!("Failed to sync directory '{}': {e}", dir.display());
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::collections::BTreeMap;
+
+    type Map = BTreeMap<String, u64>;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("odss2dash-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = test_dir("state-save");
+        let path = dir.join("reported.json");
+        assert_eq!(load_json::<Map>(&path), None);
+
+        let map1 = Map::from([("a".to_string(), 1)]);
+        save_json(&path, &map1).unwrap();
+        assert_eq!(load_json::<Map>(&path), Some(map1.clone()));
+        assert!(!backup_path(&path).exists());
+
+        let map2 = Map::from([("a".to_string(), 2)]);
+        save_json(&path, &map2).unwrap();
+        assert_eq!(load_json::<Map>(&path), Some(map2));
+        assert_eq!(read_json::<Map>(&backup_path(&path)), Ok(map1));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_recovery_from_truncated_file() {
+        let dir = test_dir("state-recovery");
+        let path = dir.join("dispatched.json");
+
+        let map1 = Map::from([("a".to_string(), 1)]);
+        let map2 = Map::from([("a".to_string(), 2)]);
+        save_json(&path, &map1).unwrap();
+        save_json(&path, &map2).unwrap();
+        fs::write(&path, "{\n  \"a\": ").unwrap();
+
+        // backup has the previous good version, also restored for subsequent loads:
+        assert_eq!(load_json::<Map>(&path), Some(map1.clone()));
+        assert!(sibling_path(&path, "corrupt").exists());
+        assert_eq!(load_json::<Map>(&path), Some(map1));
+
+        // both corrupted:
+        fs::write(&path, "{").unwrap();
+        fs::write(backup_path(&path), "{").unwrap();
+        assert_eq!(load_json::<Map>(&path), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
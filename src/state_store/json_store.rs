@@ -0,0 +1,74 @@
+use super::{ReportedMap, StateStore};
+use crate::dispatched_info::DispatchedState;
+use crate::state_file;
+
+use std::path::{Path, PathBuf};
+
+/// List of dispatched platforms maintained in this file under the state directory.
+const DISPATCHED_FILENAME: &str = "dispatched.json";
+
+/// File under the state directory that keeps track of last notified position timestamps.
+const REPORTED_FILENAME: &str = "reported.json";
+
+/// State store based on JSON files. This is the default store, but note that
+/// it is not safe for concurrent updates from multiple processes.
+pub struct JsonStore {
+    dispatched_path: PathBuf,
+    reported_path: PathBuf,
+}
+
+impl JsonStore {
+    pub fn new(dir: &Path) -> Self {
+        JsonStore {
+            dispatched_path: dir.join(DISPATCHED_FILENAME),
+            reported_path: dir.join(REPORTED_FILENAME),
+        }
+    }
+
+    /// Creates any missing files.
+    pub fn init(&self) -> Result<(), String> {
+        if !self.dispatched_path.exists() {
+            println!("Creating new '{}' file", self.dispatched_path.display());
+            self.save_dispatched(&DispatchedState::default())?;
+        }
+        if !self.reported_path.exists() {
+            self.save_reported(&ReportedMap::new())?;
+        }
+        Ok(())
+    }
+
+    fn save_dispatched(&self, state: &DispatchedState) -> Result<(), String> {
+        println!("Saving '{}'", self.dispatched_path.display());
+        state_file::save_json(&self.dispatched_path, state)
+            .map_err(|e| format!("failed to save '{}': {e}", self.dispatched_path.display()))
+    }
+}
+
+impl StateStore for JsonStore {
+    fn load_dispatched(&self) -> Result<DispatchedState, String> {
+        log::debug!("Loading '{}'", self.dispatched_path.display());
+        Ok(state_file::load_json(&self.dispatched_path).unwrap_or_default())
+    }
+
+    fn update_dispatched(
+        &self,
+        update: &mut dyn FnMut(&mut DispatchedState),
+    ) -> Result<(), String> {
+        let current = self.load_dispatched()?;
+        let mut state = current.clone();
+        update(&mut state);
+        if state != current {
+            self.save_dispatched(&state)?;
+        }
+        Ok(())
+    }
+
+    fn load_reported(&self) -> Result<ReportedMap, String> {
+        Ok(state_file::load_json(&self.reported_path).unwrap_or_default())
+    }
+
+    fn save_reported(&self, reported_map: &ReportedMap) -> Result<(), String> {
+        state_file::save_json(&self.reported_path, reported_map)
+            .map_err(|e| format!("failed to save '{}': {e}", self.reported_path.display()))
+    }
+}
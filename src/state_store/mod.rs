@@ -0,0 +1,76 @@
+mod json_store;
+mod sqlite_store;
+
+use crate::config::{self, Config, StateStoreKind};
+use crate::dispatched_info::DispatchedState;
+
+pub use json_store::JsonStore;
+pub use sqlite_store::SqliteStore;
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+
+type PlatformId = String;
+type LastTsReported = u64;
+
+/// Timestamp of the latest reported position for each platform.
+pub type ReportedMap = BTreeMap<PlatformId, LastTsReported>;
+
+/// Storage of the odss2dash state, that is, the dispatched platforms and the
+/// timestamps of the latest reported positions.
+pub trait StateStore: Send + Sync {
+    fn load_dispatched(&self) -> Result<DispatchedState, String>;
+
+    /// Applies the given update to the dispatched state as a single atomic operation.
+    fn update_dispatched(&self, update: &mut dyn FnMut(&mut DispatchedState))
+        -> Result<(), String>;
+
+    fn load_reported(&self) -> Result<ReportedMap, String>;
+
+    fn save_reported(&self, reported_map: &ReportedMap) -> Result<(), String>;
+}
+
+static STORE: OnceLock<Arc<dyn StateStore>> = OnceLock::new();
+
+/// Gets the state store per the configuration.
+pub fn get_store() -> Arc<dyn StateStore> {
+    STORE
+        .get_or_init(|| {
+            open_store(&config::get_config()).unwrap_or_else(|e| {
+                eprintln!("error: cannot open state store: {e}");
+                std::process::exit(1);
+            })
+        })
+        .clone()
+}
+
+fn open_store(config: &Config) -> Result<Arc<dyn StateStore>, String> {
+    Ok(match config.state_store {
+        StateStoreKind::Json => {
+            let store = JsonStore::new(&config.state_dir);
+            store.init()?;
+            Arc::new(store)
+        }
+        StateStoreKind::Sqlite => Arc::new(SqliteStore::open(
+            &config.state_path(SqliteStore::FILENAME),
+        )?),
+    })
+}
+
+/// Imports the state from the JSON files in the given directory into the configured store,
+/// returning the number of dispatched platforms and of reported entries.
+pub fn import_json_state(from_dir: &Path) -> Result<(usize, usize), String> {
+    let config = config::get_config();
+    if config.state_store != StateStoreKind::Sqlite {
+        return Err("stateStore is not configured as 'sqlite'".to_string());
+    }
+    let json_store = JsonStore::new(from_dir);
+    let dispatched = json_store.load_dispatched()?;
+    let reported_map = json_store.load_reported()?;
+
+    let store = get_store();
+    store.update_dispatched(&mut |state| *state = dispatched.clone())?;
+    store.save_reported(&reported_map)?;
+    Ok((dispatched.platform_ids.len(), reported_map.len()))
+}
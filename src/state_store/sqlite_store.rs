@@ -0,0 +1,336 @@
+use super::{ReportedMap, StateStore};
+use crate::dispatched_info::{DispatchDetails, DispatchedState};
+
+use rusqlite::{params, Connection, TransactionBehavior};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Schema migrations, applied in order, with `PRAGMA user_version` keeping
+/// track of the number of migrations already applied to the database.
+/// Only append to this list.
+const MIGRATIONS: &[&str] = &[
+    // 1: initial schema
+    "CREATE TABLE dispatched (
+        platform_id TEXT PRIMARY KEY
+    );
+    CREATE TABLE poll_periods (
+        platform_id TEXT PRIMARY KEY,
+        poll_period_ms INTEGER NOT NULL
+    );
+    CREATE TABLE reported (
+        platform_id TEXT PRIMARY KEY,
+        last_ts_reported INTEGER NOT NULL
+    );",
+    // 2: orphaned platforms
+    "CREATE TABLE orphaned (
+        platform_id TEXT PRIMARY KEY,
+        since_ms INTEGER NOT NULL
+    );",
+    // 3: dispatch details
+    "CREATE TABLE details (
+        platform_id TEXT PRIMARY KEY,
+        start_time_ms INTEGER,
+        expires_at_ms INTEGER,
+        note TEXT,
+        added_by TEXT
+    );",
+    // 4: platform groups
+    "CREATE TABLE groups (
+        name TEXT PRIMARY KEY
+    );
+    CREATE TABLE group_members (
+        name TEXT NOT NULL REFERENCES groups(name),
+        platform_id TEXT NOT NULL,
+        PRIMARY KEY (name, platform_id)
+    );",
+];
+
+/// State store based on an embedded SQLite database, which allows multiple
+/// processes (e.g., a running `serve` and the `add-dispatched` command)
+/// to safely share the state.
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    /// Database file under the state directory.
+    pub const FILENAME: &'static str = "odss2dash.db";
+
+    pub fn open(path: &Path) -> Result<Self, String> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let open = || -> rusqlite::Result<Connection> {
+            let mut conn = Connection::open(path)?;
+            conn.busy_timeout(Duration::from_secs(10))?;
+            conn.pragma_update(None, "journal_mode", "WAL")?;
+            migrate(&mut conn)?;
+            Ok(conn)
+        };
+        let conn = open().map_err(|e| format!("'{}': {e}", path.display()))?;
+        println!("Using state store '{}'", path.display());
+        Ok(SqliteStore {
+            conn: Mutex::new(conn),
+        })
+    }
+}
+
+fn migrate(conn: &mut Connection) -> rusqlite::Result<()> {
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let version: usize = tx.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        log::info!("Applying state store migration {}", index + 1);
+        tx.execute_batch(migration)?;
+    }
+    if version < MIGRATIONS.len() {
+        tx.pragma_update(None, "user_version", MIGRATIONS.len())?;
+    }
+    tx.commit()
+}
+
+fn read_dispatched(conn: &Connection) -> rusqlite::Result<DispatchedState> {
+    let mut state = DispatchedState::default();
+
+    let mut stmt = conn.prepare("SELECT platform_id FROM dispatched ORDER BY platform_id")?;
+    for platform_id in stmt.query_map([], |row| row.get::<_, String>(0))? {
+        state.platform_ids.push(platform_id?);
+    }
+
+    let mut stmt = conn.prepare("SELECT platform_id, poll_period_ms FROM poll_periods")?;
+    let rows = stmt.query_map([], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, u64>(1)?))
+    })?;
+    for row in rows {
+        let (platform_id, poll_period_ms) = row?;
+        let poll_period = Duration::from_millis(poll_period_ms);
+        state.poll_periods.insert(platform_id, poll_period.into());
+    }
+
+    let mut stmt = conn.prepare("SELECT platform_id, since_ms FROM orphaned")?;
+    for row in stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))? {
+        let (platform_id, since_ms) = row?;
+        state.orphaned_since.insert(platform_id, since_ms);
+    }
+
+    let mut stmt = conn
+        .prepare("SELECT platform_id, start_time_ms, expires_at_ms, note, added_by FROM details")?;
+    let rows = stmt.query_map([], |row| {
+        let details = DispatchDetails {
+            start_time_ms: row.get(1)?,
+            expires_at_ms: row.get(2)?,
+            note: row.get(3)?,
+            added_by: row.get(4)?,
+        };
+        Ok((row.get(0)?, details))
+    })?;
+    for row in rows {
+        let (platform_id, details) = row?;
+        state.details.insert(platform_id, details);
+    }
+
+    let mut stmt = conn.prepare("SELECT name FROM groups")?;
+    for name in stmt.query_map([], |row| row.get::<_, String>(0))? {
+        state.groups.insert(name?, Vec::new());
+    }
+    let mut stmt =
+        conn.prepare("SELECT name, platform_id FROM group_members ORDER BY name, platform_id")?;
+    for row in stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get(1)?)))? {
+        let (name, platform_id) = row?;
+        state.groups.entry(name).or_default().push(platform_id);
+    }
+    Ok(state)
+}
+
+fn write_dispatched(conn: &Connection, state: &DispatchedState) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM dispatched", [])?;
+    let mut stmt = conn.prepare("INSERT INTO dispatched (platform_id) VALUES (?1)")?;
+    for platform_id in &state.platform_ids {
+        stmt.execute(params![platform_id])?;
+    }
+
+    conn.execute("DELETE FROM poll_periods", [])?;
+    let mut stmt =
+        conn.prepare("INSERT INTO poll_periods (platform_id, poll_period_ms) VALUES (?1, ?2)")?;
+    for (platform_id, poll_period) in &state.poll_periods {
+        stmt.execute(params![platform_id, poll_period.as_millis() as u64])?;
+    }
+
+    conn.execute("DELETE FROM orphaned", [])?;
+    let mut stmt = conn.prepare("INSERT INTO orphaned (platform_id, since_ms) VALUES (?1, ?2)")?;
+    for (platform_id, since_ms) in &state.orphaned_since {
+        stmt.execute(params![platform_id, since_ms])?;
+    }
+
+    conn.execute("DELETE FROM details", [])?;
+    let mut stmt = conn.prepare(
+        "INSERT INTO details (platform_id, start_time_ms, expires_at_ms, note, added_by)
+        VALUES (?1, ?2, ?3, ?4, ?5)",
+    )?;
+    for (platform_id, d) in &state.details {
+        stmt.execute(params![
+            platform_id,
+            d.start_time_ms,
+            d.expires_at_ms,
+            d.note,
+            d.added_by
+        ])?;
+    }
+
+    conn.execute("DELETE FROM group_members", [])?;
+    conn.execute("DELETE FROM groups", [])?;
+    let mut group_stmt = conn.prepare("INSERT INTO groups (name) VALUES (?1)")?;
+    let mut member_stmt =
+        conn.prepare("INSERT INTO group_members (name, platform_id) VALUES (?1, ?2)")?;
+    for (name, platform_ids) in &state.groups {
+        group_stmt.execute(params![name])?;
+        for platform_id in platform_ids {
+            member_stmt.execute(params![name, platform_id])?;
+        }
+    }
+    Ok(())
+}
+
+impl StateStore for SqliteStore {
+    fn load_dispatched(&self) -> Result<DispatchedState, String> {
+        let conn = self.conn.lock().unwrap();
+        read_dispatched(&conn).map_err(|e| e.to_string())
+    }
+
+    fn update_dispatched(
+        &self,
+        update: &mut dyn FnMut(&mut DispatchedState),
+    ) -> Result<(), String> {
+        let mut conn = self.conn.lock().unwrap();
+        let mut run = || -> rusqlite::Result<()> {
+            // immediate, to lock out other writers during the read-modify-write:
+            let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            let current = read_dispatched(&tx)?;
+            let mut state = current.clone();
+            update(&mut state);
+            if state != current {
+                write_dispatched(&tx, &state)?;
+            }
+            tx.commit()
+        };
+        run().map_err(|e| e.to_string())
+    }
+
+    fn load_reported(&self) -> Result<ReportedMap, String> {
+        let conn = self.conn.lock().unwrap();
+        let run = || -> rusqlite::Result<ReportedMap> {
+            let mut stmt = conn.prepare("SELECT platform_id, last_ts_reported FROM reported")?;
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect()
+        };
+        run().map_err(|e| e.to_string())
+    }
+
+    /// Upserts each entry, never moving a timestamp back, so that concurrent
+    /// processes (each with a possibly outdated map) do not lose each other's updates.
+    fn save_reported(&self, reported_map: &ReportedMap) -> Result<(), String> {
+        let mut conn = self.conn.lock().unwrap();
+        let mut run = || -> rusqlite::Result<()> {
+            let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            {
+                let mut stmt = tx.prepare(
+                    "INSERT INTO reported (platform_id, last_ts_reported) VALUES (?1, ?2)
+                    ON CONFLICT(platform_id) DO UPDATE
+                    SET last_ts_reported = MAX(last_ts_reported, excluded.last_ts_reported)",
+                )?;
+                for (platform_id, last_ts_reported) in reported_map {
+                    stmt.execute(params![platform_id, last_ts_reported])?;
+                }
+            }
+            tx.commit()
+        };
+        run().map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_sqlite_store() {
+        let path = std::env::temp_dir().join(format!("odss2dash-test-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let store = SqliteStore::open(&path).unwrap();
+        assert_eq!(store.load_dispatched().unwrap(), DispatchedState::default());
+
+        store
+            .update_dispatched(&mut |state| {
+                state.platform_ids = vec!["p2".to_string(), "p1".to_string()];
+                state
+                    .poll_periods
+                    .insert("p1".to_string(), Duration::from_secs(10).into());
+                state
+                    .orphaned_since
+                    .insert("p2".to_string(), 1_700_000_000_000);
+                let details = DispatchDetails {
+                    expires_at_ms: Some(1_800_000_000_000),
+                    note: Some("cruise".to_string()),
+                    ..DispatchDetails::default()
+                };
+                state.details.insert("p1".to_string(), details);
+                state.groups.insert("empty".to_string(), vec![]);
+                state.groups.insert(
+                    "canon".to_string(),
+                    vec!["p1".to_string(), "p3".to_string()],
+                );
+            })
+            .unwrap();
+        let reported_map = ReportedMap::from([("p1".to_string(), 1_700_000_000_000)]);
+        store.save_reported(&reported_map).unwrap();
+
+        // reopening applies no migrations again and sees the same state:
+        drop(store);
+        let store = SqliteStore::open(&path).unwrap();
+        let state = store.load_dispatched().unwrap();
+        assert_eq!(state.platform_ids, vec!["p1", "p2"]);
+        assert_eq!(*state.poll_periods["p1"], Duration::from_secs(10));
+        assert_eq!(state.orphaned_since["p2"], 1_700_000_000_000);
+        assert_eq!(state.details["p1"].expires_at_ms, Some(1_800_000_000_000));
+        assert_eq!(state.details["p1"].start_time_ms, None);
+        assert_eq!(state.groups["empty"], Vec::<String>::new());
+        assert_eq!(state.groups["canon"], vec!["p1", "p3"]);
+        assert_eq!(store.load_reported().unwrap(), reported_map);
+
+        drop(store);
+        std::fs::remove_file(&path).unwrap();
+        let _ = std::fs::remove_file(path.with_extension("db-wal"));
+        let _ = std::fs::remove_file(path.with_extension("db-shm"));
+    }
+
+    #[test]
+    fn test_sqlite_store_shared_reported() {
+        let path =
+            std::env::temp_dir().join(format!("odss2dash-test-shared-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        // two processes on the same database, each with its own view:
+        let store1 = SqliteStore::open(&path).unwrap();
+        let store2 = SqliteStore::open(&path).unwrap();
+        let reported1 = ReportedMap::from([("p1".to_string(), 2_000), ("p2".to_string(), 1_000)]);
+        store1.save_reported(&reported1).unwrap();
+        let reported2 = ReportedMap::from([("p2".to_string(), 500), ("p3".to_string(), 3_000)]);
+        store2.save_reported(&reported2).unwrap();
+
+        let expected = ReportedMap::from([
+            ("p1".to_string(), 2_000),
+            ("p2".to_string(), 1_000),
+            ("p3".to_string(), 3_000),
+        ]);
+        assert_eq!(store1.load_reported().unwrap(), expected);
+        assert_eq!(store2.load_reported().unwrap(), expected);
+
+        drop(store1);
+        drop(store2);
+        std::fs::remove_file(&path).unwrap();
+        let _ = std::fs::remove_file(path.with_extension("db-wal"));
+        let _ = std::fs::remove_file(path.with_extension("db-shm"));
+    }
+}
@@ -1,10 +1,10 @@
 use crate::config::TethysDashConfig;
 use crate::trackdb_client::Position;
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
-#[derive(Serialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct XEvent {
     pub asset_id: String,
@@ -35,7 +35,7 @@ pub fn post_xevent(tethysdash_config: &TethysDashConfig, xevent: XEvent) -> Resu
         .post(&endpoint)
         .header(
             "Authorization",
-            &format!("Bearer {}", tethysdash_config.api_key),
+            &format!("Bearer {}", tethysdash_config.api_key.expose()),
         )
         .send_json(json);
 
@@ -44,3 +44,25 @@ pub fn post_xevent(tethysdash_config: &TethysDashConfig, xevent: XEvent) -> Resu
         Err(e) => Err(format!("POST {endpoint}: error: {}", e)),
     }
 }
+
+/// Checks that a TethysDash instance is reachable and does not reject the API key.
+/// This is a GET request against the API endpoint, with any response other than
+/// 401 or 403 taken as a successful check.
+pub fn probe(tethysdash_config: &TethysDashConfig) -> Result<(), String> {
+    let request = create_agent()
+        .get(&tethysdash_config.api)
+        .header(
+            "Authorization",
+            &format!("Bearer {}", tethysdash_config.api_key.expose()),
+        )
+        .call();
+
+    match request {
+        Ok(_) => Ok(()),
+        Err(ureq::Error::StatusCode(code @ (401 | 403))) => {
+            Err(format!("API key rejected (status {code})"))
+        }
+        Err(ureq::Error::StatusCode(_)) => Ok(()),
+        Err(e) => Err(format!("GET {}: error: {}", tethysdash_config.api, e)),
+    }
+}
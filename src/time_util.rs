@@ -0,0 +1,42 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Current time in milliseconds since the epoch.
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// Parses a time given either as milliseconds since the epoch, or as an ISO 8601
+/// UTC date-time, e.g., `2026-10-01T12:00:00Z` or `2026-10-01 12:00:00`.
+/// A date alone, e.g., `2026-10-01`, is taken as the start of that day.
+pub fn parse_time_ms(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    if let Ok(ms) = value.parse::<u64>() {
+        return Ok(ms);
+    }
+    let date_time = if value.len() == 10 {
+        format!("{value}T00:00:00Z")
+    } else {
+        value.to_string()
+    };
+    humantime::parse_rfc3339_weak(&date_time)
+        .map(|t| t.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64)
+        .map_err(|e| format!("invalid time '{value}': {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_parse_time_ms() {
+        assert_eq!(parse_time_ms("1700000000000"), Ok(1_700_000_000_000));
+        assert_eq!(parse_time_ms("2023-11-14T22:13:20Z"), Ok(1_700_000_000_000));
+        assert_eq!(parse_time_ms("2023-11-14 22:13:20"), Ok(1_700_000_000_000));
+        assert_eq!(parse_time_ms("2023-11-14"), Ok(1_699_920_000_000));
+        assert!(parse_time_ms("yesterday").is_err());
+    }
+}
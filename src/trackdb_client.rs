@@ -1,6 +1,10 @@
-use crate::config;
+use crate::config::{self, DEFAULT_SOURCE};
+use crate::file_source;
+use crate::geo;
+use crate::simulation::{self, SimulationConfig};
 
 use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 use std::time::Duration;
 use utoipa::ToSchema;
 
@@ -17,14 +21,43 @@ pub struct PlatformRes {
 }
 
 #[derive(Serialize, Deserialize, Debug)]
-struct TrackRes {
+pub struct TrackRes {
     status: String,
     data: TrackDataRes,
 }
 
+impl TrackRes {
+    /// A successful track with the given positions, as from the ODSS.
+    pub fn from_positions(positions: &[Position]) -> Self {
+        TrackRes {
+            status: "success".to_string(),
+            data: TrackDataRes {
+                _type: "LineString".to_string(),
+                timestamps: positions.iter().map(|p| p.time_ms).collect(),
+                coordinates: positions.iter().map(|p| vec![p.lon, p.lat]).collect(),
+            },
+        }
+    }
+
+    /// The positions in the track, in the given order.
+    pub fn positions(self) -> Vec<Position> {
+        let timestamps = self.data.timestamps;
+        let coordinates = self.data.coordinates;
+        let pairs = timestamps.iter().zip(coordinates.iter());
+        pairs
+            .map(|(time_ms, coords)| Position {
+                time_ms: *time_ms,
+                lat: coords[1],
+                lon: coords[0],
+                kinematics: None,
+            })
+            .collect()
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 struct TrackDataRes {
-    #[serde(rename(deserialize = "type"))]
+    #[serde(rename = "type")]
     _type: String, // normally "LineString", which we simply assume.
     pub timestamps: Vec<u64>,
     pub coordinates: Vec<Vec<f64>>,
@@ -37,19 +70,101 @@ fn create_agent() -> ureq::Agent {
         .into()
 }
 
-fn get_url(endpoint: &str) -> String {
+/// Separates the source name from the source's own ID in a namespaced platform ID.
+const SOURCE_SEPARATOR: char = ':';
+
+/// A Tracking DB/ODSS source.
+#[derive(Clone, Debug)]
+struct Source {
+    name: String,
+    backend: Backend,
+}
+
+#[derive(Clone, Debug)]
+enum Backend {
+    /// ODSS API base URL.
+    Odss(String),
+    /// Directory with recorded data, see [file_source].
+    Files(PathBuf),
+    /// Simulated platforms, see [simulation].
+    Simulation(SimulationConfig),
+}
+
+impl Backend {
+    fn new(
+        odss_api: &str,
+        data_dir: &Option<PathBuf>,
+        simulation: &Option<SimulationConfig>,
+    ) -> Self {
+        match (simulation, data_dir) {
+            (Some(simulation), _) => Backend::Simulation(simulation.clone()),
+            (None, Some(dir)) => Backend::Files(dir.clone()),
+            (None, None) => Backend::Odss(odss_api.to_string()),
+        }
+    }
+}
+
+impl Source {
+    /// The platform ID as exposed by odss2dash for the given ID within this source.
+    fn namespaced_id(&self, id: &str) -> String {
+        if self.name == DEFAULT_SOURCE {
+            id.to_string()
+        } else {
+            format!("{}{SOURCE_SEPARATOR}{id}", self.name)
+        }
+    }
+}
+
+/// The default source followed by any additional named sources.
+fn get_sources() -> Vec<Source> {
     let config = config::get_config();
-    format!("{}{endpoint}", config.odss_api)
+    let default = Source {
+        name: DEFAULT_SOURCE.to_string(),
+        backend: Backend::new(&config.odss_api, &config.data_dir, &config.simulation),
+    };
+    let named = config.sources.iter().map(|s| Source {
+        name: s.name.clone(),
+        backend: Backend::new(&s.odss_api, &s.data_dir, &s.simulation),
+    });
+    std::iter::once(default).chain(named).collect()
 }
 
-fn make_get_request<T>(endpoint: &str) -> Option<T>
-where
-    T: std::fmt::Debug + for<'de> serde::Deserialize<'de>,
-{
-    make_get_request_with_params(endpoint, &Vec::new())
+/// Resolves a possibly namespaced platform ID into its source and the ID within it.
+fn resolve(platform_id: &str) -> (Source, String) {
+    let mut sources = get_sources();
+    if let Some((name, id)) = platform_id.split_once(SOURCE_SEPARATOR) {
+        if let Some(index) = sources.iter().position(|s| s.name == name) {
+            return (sources.swap_remove(index), id.to_string());
+        }
+    }
+    (sources.swap_remove(0), platform_id.to_string())
+}
+
+/// Name of the source of the given platform.
+pub fn source_of(platform_id: &str) -> String {
+    resolve(platform_id).0.name
+}
+
+fn get_url(odss_api: &str, endpoint: &str) -> String {
+    format!("{odss_api}{endpoint}")
 }
 
-fn make_get_request_with_params<'a, T>(endpoint: &str, params: &Vec<(&'a str, String)>) -> Option<T>
+/// Logs the error of a request, if any.
+fn log_error<T>(result: Result<T, String>) -> Option<T> {
+    match result {
+        Ok(res) => Some(res),
+        Err(e) => {
+            log::error!("{e}");
+            None
+        }
+    }
+}
+
+fn try_get_request_with_params<'a, T>(
+    source: &Source,
+    endpoint: &str,
+    params: &Vec<(&'a str, String)>,
+) -> Result<T, String>
 where
     T: std::fmt::Debug + for<'de> serde::Deserialize<'de>,
 {
@@ -66,45 +181,95 @@ where
                     .join(", "),
             )
         };
-        format!("GET {endpoint}{params}")
+        if source.name == DEFAULT_SOURCE {
+            format!("GET {endpoint}{params}")
+        } else {
+            format!("GET [{}] {endpoint}{params}", source.name)
+        }
+    };
+    let Backend::Odss(odss_api) = &source.backend else {
+        return Err(format!("{}: not an ODSS source", log_prefix()));
     };
 
     log::debug!("{}", log_prefix());
-    let url = get_url(endpoint);
+    let url = get_url(odss_api, endpoint);
     let req = create_agent().get(&url).query_pairs(params.clone());
 
-    let mut response = match req.call() {
-        Ok(res) => res,
-        Err(e) => {
-            log::error!("{}: request failed: {}", log_prefix(), e);
-            return None;
-        }
-    };
+    let mut response = req
+        .call()
+        .map_err(|e| format!("{}: request failed: {}", log_prefix(), e))?;
 
-    let res = match response
+    let res = response
         .body_mut()
         .with_config()
         .limit(30 * 1024 * 1024)
         .read_json::<T>()
-    {
-        Ok(parsed) => parsed,
-        Err(e) => {
-            log::error!("{}: failed to parse response JSON: {}", log_prefix(), e);
-            return None;
-        }
-    };
+        .map_err(|e| format!("{}: failed to parse response JSON: {}", log_prefix(), e))?;
     log::debug!("GET {endpoint} => {:?}", res);
-    Some(res)
+    Ok(res)
+}
+
+/// Checks that each source is reachable, returning the number of platforms
+/// by source name.
+pub fn probe() -> Vec<(String, Result<usize, String>)> {
+    get_platforms_by_source()
+        .into_iter()
+        .map(|(name, platforms)| (name, platforms.map(|p| p.len())))
+        .collect()
+}
+
+/// Gets the platforms from each source, with namespaced IDs, distinguishing
+/// a failed request from an empty response.
+pub fn get_platforms_by_source() -> Vec<(String, Result<Vec<PlatformRes>, String>)> {
+    get_sources()
+        .into_iter()
+        .map(|source| {
+            let platforms = match &source.backend {
+                Backend::Odss(_) => try_get_request_with_params(&source, "/platforms", &Vec::new()),
+                Backend::Files(dir) => file_source::get_platforms(dir),
+                Backend::Simulation(simulation) => Ok(simulation::get_platforms(simulation)),
+            };
+            let platforms = platforms.map(|platforms: Vec<PlatformRes>| {
+                platforms
+                    .into_iter()
+                    .map(|p| namespaced_platform(&source, p))
+                    .collect()
+            });
+            (source.name, platforms)
+        })
+        .collect()
 }
 
+/// Gets the platforms from all sources, skipping any failing source.
 pub fn get_platforms() -> Vec<PlatformRes> {
-    let endpoint = "/platforms";
-    make_get_request(endpoint).unwrap_or_else(Vec::new)
+    let mut all_platforms = Vec::new();
+    for (name, platforms) in get_platforms_by_source() {
+        match platforms {
+            Ok(platforms) => all_platforms.extend(platforms),
+            Err(e) => log::error!("source '{name}': {e}"),
+        }
+    }
+    all_platforms
 }
 
 pub fn get_platform(platform_id: &str) -> Option<PlatformRes> {
-    let endpoint = format!("/platforms/{platform_id}");
-    make_get_request(&endpoint)
+    let (source, id) = resolve(platform_id);
+    let platform_res = match &source.backend {
+        Backend::Odss(_) => {
+            let endpoint = format!("/platforms/{id}");
+            log_error(try_get_request_with_params(&source, &endpoint, &Vec::new()))
+        }
+        Backend::Files(dir) => log_error(file_source::get_platform(dir, &id)).flatten(),
+        Backend::Simulation(simulation) => simulation::get_platform(simulation, &id),
+    };
+    platform_res.map(|p| namespaced_platform(&source, p))
+}
+
+fn namespaced_platform(source: &Source, platform_res: PlatformRes) -> PlatformRes {
+    PlatformRes {
+        _id: source.namespaced_id(&platform_res._id),
+        ..platform_res
+    }
 }
 
 #[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
@@ -121,6 +286,61 @@ pub struct Position {
     pub time_ms: u64,
     pub lat: f64,
     pub lon: f64,
+    /// Only included if requested.
+    #[serde(flatten, default, skip_serializing_if = "Option::is_none")]
+    pub kinematics: Option<Kinematics>,
+}
+
+/// Kinematics derived from consecutive fixes.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Kinematics {
+    /// Distance in meters from the previous fix (zero for the first fix).
+    pub distance: f64,
+    /// Distance in meters along the track up to this fix.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cumulative_distance: Option<f64>,
+    /// Speed over ground in meters per second.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sog: Option<f64>,
+    /// Course over ground in degrees from true north.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cog: Option<f64>,
+}
+
+/// Sets the kinematics of the positions, given in any order, from consecutive fixes
+/// in chronological order, starting with the `previous` fix, if given, and with the
+/// cumulative distance from there.
+pub fn add_kinematics(previous: Option<&Position>, positions: &mut [Position]) {
+    let mut indices = (0..positions.len()).collect::<Vec<_>>();
+    indices.sort_by_key(|&i| positions[i].time_ms);
+    let mut previous = previous.map(|p| (p.time_ms, (p.lat, p.lon)));
+    let mut cumulative_distance = 0.0;
+    for i in indices {
+        let position = &mut positions[i];
+        let point = (position.lat, position.lon);
+        let kinematics = match previous {
+            Some((time_ms, previous_point)) => {
+                let distance = geo::distance_m(previous_point, point);
+                cumulative_distance += distance;
+                let elapsed_s = position.time_ms.saturating_sub(time_ms) as f64 / 1000.0;
+                Kinematics {
+                    distance,
+                    cumulative_distance: Some(cumulative_distance),
+                    sog: (elapsed_s > 0.0).then(|| distance / elapsed_s),
+                    cog: (distance > 0.0).then(|| geo::bearing_deg(previous_point, point)),
+                }
+            }
+            None => Kinematics {
+                distance: 0.0,
+                cumulative_distance: Some(0.0),
+                sog: None,
+                cog: None,
+            },
+        };
+        position.kinematics = Some(kinematics);
+        previous = Some((position.time_ms, point));
+    }
 }
 
 /// Get the latest positions for the given platform according to the default number of fixes.
@@ -141,13 +361,45 @@ pub fn get_positions(
     end_date: Option<String>,
 ) -> Option<PositionsResponse> {
     log::debug!("get_positions: platform_id='{}'", platform_id);
-    let params =
-        create_params_for_positions(platform_id, last_number_of_fixes, start_date, end_date);
-    let endpoint = "/tracks";
-    if let Some(track_res) = make_get_request_with_params::<TrackRes>(endpoint, &params) {
-        track_res_to_positions_response(platform_id, last_number_of_fixes, track_res)
-    } else {
-        None
+    let (source, id) = resolve(platform_id);
+    let track_res = match &source.backend {
+        Backend::Odss(_) => {
+            let params =
+                create_params_for_positions(&id, last_number_of_fixes, start_date, end_date);
+            log_error(try_get_request_with_params::<TrackRes>(
+                &source, "/tracks", &params,
+            ))?
+        }
+        Backend::Files(dir) => {
+            let positions = log_error(file_source::get_positions(
+                dir,
+                &id,
+                resolve_last_number_of_fixes(last_number_of_fixes),
+                start_date.as_deref(),
+                end_date.as_deref(),
+            ))?;
+            TrackRes::from_positions(&positions)
+        }
+        Backend::Simulation(simulation) => {
+            simulation::get_platform(simulation, &id)?;
+            let positions = log_error(simulation::get_positions(
+                simulation,
+                &id,
+                resolve_last_number_of_fixes(last_number_of_fixes),
+                start_date.as_deref(),
+                end_date.as_deref(),
+            ))?;
+            TrackRes::from_positions(&positions)
+        }
+    };
+    track_res_to_positions_response(platform_id, last_number_of_fixes, track_res)
+}
+
+/// The given number of fixes if positive, otherwise the configured default.
+fn resolve_last_number_of_fixes(last_number_of_fixes: Option<u32>) -> u32 {
+    match last_number_of_fixes {
+        Some(number) if number > 0 => number,
+        _ => config::get_config().default_last_number_of_fixes,
     }
 }
 
@@ -157,8 +409,6 @@ fn create_params_for_positions<'a>(
     start_date: Option<String>,
     end_date: Option<String>,
 ) -> Vec<(&'a str, String)> {
-    let config = config::get_config();
-
     let mut params = Vec::new();
     params.push(("platformID", platform_id.to_string()));
     params.push(("returnFormat", "json".to_string()));
@@ -172,17 +422,7 @@ fn create_params_for_positions<'a>(
             params.push(("endDate", end_date));
         }
     } else {
-        let default = config.default_last_number_of_fixes;
-        let last_number_of_fixes = match last_number_of_fixes {
-            Some(number) => {
-                if number > 0 {
-                    number
-                } else {
-                    default
-                }
-            }
-            None => default,
-        };
+        let last_number_of_fixes = resolve_last_number_of_fixes(last_number_of_fixes);
         params.push(("lastNumberOfFixes", last_number_of_fixes.to_string()));
     }
     params
@@ -201,16 +441,7 @@ fn track_res_to_positions_response(
         );
         return None;
     };
-    let timestamps = track_res.data.timestamps;
-    let coordinates = track_res.data.coordinates;
-    let pairs = timestamps.iter().zip(coordinates.iter());
-    let mut positions = pairs
-        .map(|(time_ms, coords)| Position {
-            time_ms: *time_ms,
-            lat: coords[1],
-            lon: coords[0],
-        })
-        .collect::<Vec<Position>>();
+    let mut positions = track_res.positions();
 
     // If given, apply lastNumberOfFixes restriction:
     if let Some(number) = last_number_of_fixes {